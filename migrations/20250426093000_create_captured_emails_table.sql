@@ -0,0 +1,11 @@
+CREATE TABLE captured_emails (
+    captured_email_id uuid NOT NULL,
+    sender TEXT NOT NULL,
+    recipient TEXT NOT NULL,
+    subject TEXT NOT NULL,
+    html_body TEXT NOT NULL,
+    text_body TEXT NOT NULL,
+    headers jsonb NOT NULL,
+    captured_at timestamptz NOT NULL,
+    PRIMARY KEY(captured_email_id)
+);
\ No newline at end of file
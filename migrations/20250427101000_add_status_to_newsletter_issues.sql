@@ -0,0 +1,13 @@
+BEGIN;
+    ALTER TABLE newsletter_issues ADD COLUMN status TEXT NULL;
+    UPDATE newsletter_issues i SET status = CASE
+        WHEN EXISTS (
+            SELECT 1 FROM issue_delivery_queue q
+            WHERE q.newsletter_issue_id = i.newsletter_issue_id
+        ) THEN 'sending'
+        ELSE 'sent'
+    END;
+    ALTER TABLE newsletter_issues ALTER COLUMN status SET NOT NULL;
+    -- Drafts are not published yet
+    ALTER TABLE newsletter_issues ALTER COLUMN published_at DROP NOT NULL;
+COMMIT;
\ No newline at end of file
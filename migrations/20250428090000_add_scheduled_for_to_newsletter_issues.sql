@@ -0,0 +1 @@
+ALTER TABLE newsletter_issues ADD COLUMN scheduled_for timestamptz NULL;
\ No newline at end of file
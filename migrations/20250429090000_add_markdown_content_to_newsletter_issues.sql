@@ -0,0 +1 @@
+ALTER TABLE newsletter_issues ADD COLUMN markdown_content TEXT NULL;
\ No newline at end of file
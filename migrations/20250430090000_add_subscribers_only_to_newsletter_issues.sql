@@ -0,0 +1 @@
+ALTER TABLE newsletter_issues ADD COLUMN subscribers_only BOOLEAN NOT NULL DEFAULT false;
\ No newline at end of file
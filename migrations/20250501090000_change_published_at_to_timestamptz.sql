@@ -0,0 +1,3 @@
+-- The column was created as TEXT, but it has always been filled with now()
+ALTER TABLE newsletter_issues
+    ALTER COLUMN published_at TYPE timestamptz USING published_at::timestamptz;
\ No newline at end of file
@@ -0,0 +1,14 @@
+CREATE TABLE issue_deliveries (
+    issue_delivery_id uuid NOT NULL,
+    newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues (newsletter_issue_id),
+    subscriber_email TEXT NOT NULL,
+    subscriber_id uuid NULL,
+    attempted_at timestamptz NOT NULL,
+    -- 'sent', 'retrying', 'failed' or 'skipped'
+    outcome TEXT NOT NULL,
+    provider_message_id TEXT NULL,
+    error TEXT NULL,
+    PRIMARY KEY(issue_delivery_id)
+);
+CREATE INDEX issue_deliveries_by_issue
+    ON issue_deliveries (newsletter_issue_id, subscriber_email, attempted_at);
\ No newline at end of file
@@ -0,0 +1,11 @@
+ALTER TABLE newsletter_issues ADD COLUMN track_opens BOOLEAN NOT NULL DEFAULT false;
+
+CREATE TABLE issue_opens (
+    issue_open_id uuid NOT NULL,
+    issue_delivery_id uuid NOT NULL REFERENCES issue_deliveries (issue_delivery_id),
+    newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues (newsletter_issue_id),
+    subscriber_id uuid NULL,
+    opened_at timestamptz NOT NULL,
+    PRIMARY KEY(issue_open_id)
+);
+CREATE INDEX issue_opens_by_issue ON issue_opens (newsletter_issue_id);
\ No newline at end of file
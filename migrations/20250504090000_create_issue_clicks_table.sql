@@ -0,0 +1,10 @@
+CREATE TABLE issue_clicks (
+    issue_click_id uuid NOT NULL,
+    issue_delivery_id uuid NOT NULL REFERENCES issue_deliveries (issue_delivery_id),
+    newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues (newsletter_issue_id),
+    subscriber_id uuid NULL,
+    url TEXT NOT NULL,
+    clicked_at timestamptz NOT NULL,
+    PRIMARY KEY(issue_click_id)
+);
+CREATE INDEX issue_clicks_by_issue ON issue_clicks (newsletter_issue_id);
\ No newline at end of file
@@ -0,0 +1,14 @@
+CREATE TABLE email_events (
+    email_event_id uuid NOT NULL,
+    -- As reported by the provider, e.g. 'Bounce' and 'HardBounce'
+    record_type TEXT NOT NULL,
+    event_type TEXT NOT NULL,
+    email TEXT NOT NULL,
+    provider_message_id TEXT NULL,
+    issue_delivery_id uuid NULL REFERENCES issue_deliveries (issue_delivery_id),
+    details TEXT NULL,
+    payload jsonb NOT NULL,
+    received_at timestamptz NOT NULL,
+    PRIMARY KEY(email_event_id)
+);
+CREATE INDEX issue_deliveries_by_provider_message_id ON issue_deliveries (provider_message_id);
\ No newline at end of file
@@ -0,0 +1,18 @@
+CREATE TABLE suppressions (
+    -- Lowercase, so that lookups don't depend on how the address was typed
+    email TEXT NOT NULL,
+    -- 'manual', 'bounce', 'complaint' or 'legal'
+    reason TEXT NOT NULL,
+    note TEXT NULL,
+    created_at timestamptz NOT NULL,
+    PRIMARY KEY(email)
+);
+
+-- Addresses we already know to bounce or complain
+INSERT INTO suppressions (email, reason, created_at)
+SELECT DISTINCT ON (lower(email))
+    lower(email),
+    CASE status WHEN 'bounced' THEN 'bounce' ELSE 'complaint' END,
+    now()
+FROM subscriptions
+WHERE status IN ('bounced', 'complained');
\ No newline at end of file
@@ -0,0 +1,24 @@
+-- Addresses are stored in lowercase from now on, so that signing up again with
+-- different capitals finds the existing subscriber.
+-- Should several subscribers differ only by case, the lowercase or else the oldest
+-- one gets the address; the others are left as they are.
+UPDATE subscriptions s
+SET email = lower(s.email)
+WHERE s.email <> lower(s.email)
+    AND s.id = (
+        SELECT o.id FROM subscriptions o
+        WHERE lower(o.email) = lower(s.email)
+        ORDER BY o.email = lower(o.email) DESC, o.subscribed_at
+        LIMIT 1
+    );
+
+-- Pending deliveries follow the subscribers whose address was changed
+UPDATE issue_delivery_queue q
+SET subscriber_email = lower(q.subscriber_email)
+WHERE q.subscriber_email <> lower(q.subscriber_email)
+    AND NOT EXISTS (SELECT 1 FROM subscriptions s WHERE s.email = q.subscriber_email)
+    AND NOT EXISTS (
+        SELECT 1 FROM issue_delivery_queue o
+        WHERE o.newsletter_issue_id = q.newsletter_issue_id
+            AND o.subscriber_email = lower(q.subscriber_email)
+    );
\ No newline at end of file
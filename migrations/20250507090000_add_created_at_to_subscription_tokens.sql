@@ -0,0 +1,3 @@
+-- Tokens issued before this migration count as issued now
+ALTER TABLE subscription_tokens ADD COLUMN created_at timestamptz NOT NULL DEFAULT now();
+ALTER TABLE subscription_tokens ALTER COLUMN created_at DROP DEFAULT;
\ No newline at end of file
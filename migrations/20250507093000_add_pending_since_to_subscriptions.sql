@@ -0,0 +1,6 @@
+-- When the subscriber last started confirming their subscription, which may be
+-- long after they first subscribed if they left and came back
+ALTER TABLE subscriptions ADD COLUMN pending_since timestamptz NULL;
+UPDATE subscriptions SET pending_since = subscribed_at;
+ALTER TABLE subscriptions ALTER COLUMN pending_since SET NOT NULL;
+ALTER TABLE subscriptions ALTER COLUMN pending_since SET DEFAULT now();
\ No newline at end of file
@@ -0,0 +1,3 @@
+-- Used tokens are kept until they expire, to tell people clicking twice
+-- that they are already subscribed
+ALTER TABLE subscription_tokens ADD COLUMN used_at timestamptz NULL;
\ No newline at end of file
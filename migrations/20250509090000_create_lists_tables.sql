@@ -0,0 +1,34 @@
+-- Each list is a newsletter of its own, with its own subscribers
+CREATE TABLE lists (
+    list_id uuid NOT NULL PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    -- Issues of the list are sent from the configured sender when NULL
+    sender_email TEXT NULL,
+    description TEXT NOT NULL,
+    created_at timestamptz NOT NULL
+);
+
+-- Everything published so far belongs to the default list, the oldest one
+INSERT INTO lists (list_id, name, description, created_at)
+VALUES (gen_random_uuid(), 'Newsletter', '', now());
+
+-- Membership of a subscriber in a list, confirmed separately for each list.
+-- Status is 'pending_confirmation', 'confirmed' or 'unsubscribed'.
+CREATE TABLE list_subscriptions (
+    list_id uuid NOT NULL REFERENCES lists (list_id),
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+    status TEXT NOT NULL,
+    subscribed_at timestamptz NOT NULL,
+    PRIMARY KEY (list_id, subscriber_id)
+);
+
+INSERT INTO list_subscriptions (list_id, subscriber_id, status, subscribed_at)
+SELECT l.list_id, s.id,
+    CASE WHEN s.status IN ('confirmed', 'pending_confirmation') THEN s.status
+        ELSE 'unsubscribed' END,
+    s.subscribed_at
+FROM subscriptions s CROSS JOIN lists l;
+
+ALTER TABLE newsletter_issues ADD COLUMN list_id uuid NULL REFERENCES lists (list_id);
+UPDATE newsletter_issues SET list_id = (SELECT list_id FROM lists);
+ALTER TABLE newsletter_issues ALTER COLUMN list_id SET NOT NULL;
\ No newline at end of file
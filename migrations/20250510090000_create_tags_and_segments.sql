@@ -0,0 +1,24 @@
+-- Tags are lowercase, validated by the application
+CREATE TABLE subscriber_tags (
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+    tag TEXT NOT NULL,
+    tagged_at timestamptz NOT NULL,
+    PRIMARY KEY (subscriber_id, tag)
+);
+
+-- Saved selection of subscribers: every filter that is set must match
+CREATE TABLE segments (
+    segment_id uuid NOT NULL PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    -- e.g. '(rust OR go) AND NOT churned', empty to match any tags
+    tag_expression TEXT NOT NULL,
+    subscribed_from DATE NULL,
+    subscribed_before DATE NULL,
+    status TEXT NULL,
+    created_at timestamptz NOT NULL
+);
+
+-- Issues without a segment go to the whole list. A segment still used by an
+-- unsent issue can't be deleted: the issue would silently go to the whole list instead
+ALTER TABLE newsletter_issues
+    ADD COLUMN segment_id uuid NULL REFERENCES segments (segment_id);
\ No newline at end of file
@@ -0,0 +1,7 @@
+-- Confirmation emails waiting to be sent by the background worker,
+-- e.g. for subscribers imported in bulk
+CREATE TABLE confirmation_email_queue (
+    subscriber_id uuid NOT NULL PRIMARY KEY REFERENCES subscriptions (id),
+    n_retries SMALLINT NOT NULL DEFAULT 0,
+    execute_after timestamptz NOT NULL DEFAULT now()
+);
\ No newline at end of file
@@ -0,0 +1,7 @@
+-- Addresses which were erased on request stay suppressed, but only a hash of
+-- them is kept: the SHA-256 of the lowercase address, hex-encoded
+CREATE TABLE erased_addresses (
+    email_hash TEXT NOT NULL,
+    erased_at timestamptz NOT NULL,
+    PRIMARY KEY(email_hash)
+);
\ No newline at end of file
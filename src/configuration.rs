@@ -1,17 +1,27 @@
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 
 use config::{Config, ConfigError};
 use secrecy::{ExposeSecret, Secret};
 use serde_aux::field_attributes::deserialize_number_from_string;
-use sqlx::postgres::{PgConnectOptions, PgSslMode};
-
-use crate::{domain::SubscriberEmail, email_client::EmailClient};
+use sqlx::{
+    postgres::{PgConnectOptions, PgSslMode},
+    PgPool,
+};
+
+use crate::{
+    domain::SubscriberEmail,
+    email_client::{
+        smtp::{SmtpAuthMechanism, SmtpTls},
+        CaptureEmailClient, EmailSender, PostmarkEmailClient, SmtpEmailClient,
+    },
+};
 
 #[derive(serde::Deserialize, Clone)]
 pub struct Settings {
     pub application: ApplicationSettings,
     pub database: DatabaseSettings,
     pub email_client: EmailClientSettings,
+    pub issue_delivery: IssueDeliverySettings,
     pub redis_uri: Secret<String>,
 }
 
@@ -22,6 +32,22 @@ pub struct ApplicationSettings {
     pub port: u16,
     pub base_url: String,
     pub hmac_secret: Secret<String>,
+    /// How long a confirmation link stays valid
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub confirmation_token_ttl_hours: u64,
+    /// How long subscribers who never confirmed are kept around
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub unconfirmed_subscriber_retention_days: u64,
+}
+
+impl ApplicationSettings {
+    pub fn confirmation_token_ttl(&self) -> Duration {
+        Duration::from_secs(self.confirmation_token_ttl_hours * 60 * 60)
+    }
+
+    pub fn unconfirmed_subscriber_retention(&self) -> Duration {
+        Duration::from_secs(self.unconfirmed_subscriber_retention_days * 24 * 60 * 60)
+    }
 }
 
 #[derive(serde::Deserialize, Clone)]
@@ -54,24 +80,82 @@ impl DatabaseSettings {
 
 #[derive(serde::Deserialize, Clone)]
 pub struct EmailClientSettings {
+    #[serde(default)]
+    pub transport: EmailTransport,
     pub base_url: String,
     pub sender_email: String,
     pub token: Secret<String>,
     pub timeout_ms: u64,
     pub stream: String,
+    pub smtp: Option<SmtpSettings>,
+    pub webhook: WebhookSettings,
+}
+
+/// Credentials the email provider must present (HTTP basic auth)
+/// when it calls our webhooks
+#[derive(serde::Deserialize, Clone)]
+pub struct WebhookSettings {
+    pub username: String,
+    pub password: Secret<String>,
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Default, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum EmailTransport {
+    /// Postmark's HTTP API, configured by `base_url`, `token` and `stream`
+    #[default]
+    Postmark,
+    /// An SMTP relay, configured by the `smtp` section
+    Smtp,
+    /// Nothing is sent: emails are stored in the database and shown in the admin outbox
+    Capture,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct SmtpSettings {
+    pub host: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub port: u16,
+    pub tls: SmtpTls,
+    pub username: String,
+    pub password: Secret<String>,
+    pub auth_mechanism: SmtpAuthMechanism,
 }
 
 impl EmailClientSettings {
-    pub fn client(self) -> EmailClient {
+    pub fn client(self, pool: &PgPool) -> Arc<dyn EmailSender> {
         let sender_email = self.sender().expect("Invalid sender email address.");
         let timeout = self.timeout();
-        EmailClient::new(
-            self.base_url,
-            sender_email,
-            self.token,
-            timeout,
-            self.stream,
-        )
+        match self.transport {
+            EmailTransport::Postmark => Arc::new(PostmarkEmailClient::new(
+                self.base_url,
+                sender_email,
+                self.token,
+                timeout,
+                self.stream,
+            )),
+            EmailTransport::Smtp => {
+                let smtp = self
+                    .smtp
+                    .expect("The smtp settings are required by the smtp transport.");
+                Arc::new(
+                    SmtpEmailClient::new(
+                        &smtp.host,
+                        smtp.port,
+                        smtp.tls,
+                        smtp.username,
+                        smtp.password,
+                        smtp.auth_mechanism,
+                        sender_email,
+                        timeout,
+                    )
+                    .expect("Failed to build the smtp email client."),
+                )
+            }
+            EmailTransport::Capture => {
+                Arc::new(CaptureEmailClient::new(pool.clone(), sender_email))
+            }
+        }
     }
 
     pub fn sender(&self) -> Result<SubscriberEmail, String> {
@@ -83,6 +167,27 @@ impl EmailClientSettings {
     }
 }
 
+#[derive(serde::Deserialize, Clone)]
+pub struct IssueDeliverySettings {
+    /// How many times a failed delivery is retried before being dead-lettered
+    pub max_retries: u16,
+    pub base_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+    /// Address offered to unsubscribe by email, in the `List-Unsubscribe` header.
+    /// The provider forwards what it receives to /webhooks/inbound-email.
+    pub unsubscribe_mailbox: String,
+}
+
+impl IssueDeliverySettings {
+    pub fn base_backoff(&self) -> Duration {
+        Duration::from_millis(self.base_backoff_ms)
+    }
+
+    pub fn max_backoff(&self) -> Duration {
+        Duration::from_millis(self.max_backoff_ms)
+    }
+}
+
 pub enum Environment {
     Local,
     Production,
@@ -0,0 +1,185 @@
+use std::{sync::Arc, time::Duration};
+
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+use crate::{
+    configuration::{IssueDeliverySettings, Settings},
+    domain::SubscriberEmail,
+    email_client::EmailSender,
+    issue_delivery_worker::{backoff_delay, ExecutionOutcome},
+    routes::{generate_subscription_token, send_confirmation_email, store_token},
+    startup::get_connection_pool,
+    suppression_list::is_suppressed,
+};
+
+pub async fn run_confirmation_worker_until_stopped(
+    configuration: Settings,
+) -> Result<(), anyhow::Error> {
+    let connection_pool = get_connection_pool(&configuration.database);
+    let email_client = configuration.email_client.client(&connection_pool);
+    worker_loop(
+        connection_pool,
+        email_client,
+        configuration.issue_delivery,
+        configuration.application.base_url,
+    )
+    .await
+}
+
+async fn worker_loop(
+    pool: PgPool,
+    email_client: Arc<dyn EmailSender>,
+    settings: IssueDeliverySettings,
+    base_url: String,
+) -> Result<(), anyhow::Error> {
+    loop {
+        match try_send_queued_confirmation(&pool, email_client.as_ref(), &settings, &base_url).await
+        {
+            Ok(ExecutionOutcome::EmptyQueue) => {
+                tokio::time::sleep(Duration::from_secs(10)).await;
+            }
+            Err(_) => {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+            Ok(ExecutionOutcome::TaskCompleted) => {}
+        }
+    }
+}
+
+/// Asks a subscriber to confirm their subscription later on, from the background worker
+pub async fn enqueue_confirmation_email(
+    executor: impl PgExecutor<'_>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO confirmation_email_queue (subscriber_id)
+        VALUES ($1)
+        ON CONFLICT (subscriber_id) DO NOTHING
+        "#,
+        subscriber_id
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Sends one of the queued confirmation emails, with a fresh confirmation token
+#[tracing::instrument(
+    name = "Send queued confirmation email",
+    skip_all,
+    fields(subscriber_id=tracing::field::Empty),
+    err
+)]
+pub async fn try_send_queued_confirmation(
+    pool: &PgPool,
+    email_client: &dyn EmailSender,
+    settings: &IssueDeliverySettings,
+    base_url: &str,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let task = sqlx::query!(
+        r#"
+        SELECT q.subscriber_id, q.n_retries, s.email, s.status,
+            EXISTS (
+                SELECT 1 FROM list_subscriptions ls
+                WHERE ls.subscriber_id = s.id AND ls.status = 'pending_confirmation'
+            ) as "has_pending_lists!"
+        FROM confirmation_email_queue q
+        JOIN subscriptions s ON s.id = q.subscriber_id
+        WHERE q.execute_after <= now()
+        FOR UPDATE OF q
+        SKIP LOCKED
+        LIMIT 1
+        "#
+    )
+    .fetch_optional(transaction.as_mut())
+    .await?;
+    let Some(task) = task else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+    tracing::Span::current().record(
+        "subscriber_id",
+        tracing::field::display(&task.subscriber_id),
+    );
+    sqlx::query!(
+        "DELETE FROM confirmation_email_queue WHERE subscriber_id = $1",
+        task.subscriber_id
+    )
+    .execute(transaction.as_mut())
+    .await?;
+
+    // Confirmed subscribers may have been imported into another list
+    let is_pending = task.status == "pending_confirmation"
+        || task.status == "confirmed" && task.has_pending_lists;
+    if !is_pending {
+        tracing::info!("Skipping a subscriber that is no longer waiting for confirmation");
+        transaction.commit().await?;
+        return Ok(ExecutionOutcome::TaskCompleted);
+    }
+    if is_suppressed(&mut *transaction, &task.email).await? {
+        tracing::info!("Skipping an address on the suppression list");
+        transaction.commit().await?;
+        return Ok(ExecutionOutcome::TaskCompleted);
+    }
+    let email = match SubscriberEmail::parse(&task.email) {
+        Ok(email) => email,
+        Err(error) => {
+            tracing::warn!(
+                error.cause_chain= ?error,
+                "Skipping a subscriber. Their stored contact details are invalid",
+            );
+            transaction.commit().await?;
+            return Ok(ExecutionOutcome::TaskCompleted);
+        }
+    };
+
+    let token = generate_subscription_token();
+    store_token(&mut transaction, task.subscriber_id, &token).await?;
+    match send_confirmation_email(email_client, &email, base_url, &token).await {
+        Ok(()) => transaction.commit().await?,
+        Err(e) => {
+            // Forget the token, the next attempt comes with a new one
+            transaction.rollback().await?;
+            if i32::from(task.n_retries) >= i32::from(settings.max_retries) {
+                tracing::error!(
+                error.cause_chain= ?e,
+                error.message= %e,
+                "Failed to send a confirmation email. Retry budget exhausted, giving up.",
+                );
+                sqlx::query!(
+                    "DELETE FROM confirmation_email_queue WHERE subscriber_id = $1",
+                    task.subscriber_id
+                )
+                .execute(pool)
+                .await?;
+            } else {
+                let delay = backoff_delay(
+                    task.n_retries,
+                    settings.base_backoff(),
+                    settings.max_backoff(),
+                );
+                tracing::warn!(
+                error.cause_chain= ?e,
+                error.message= %e,
+                "Failed to send a confirmation email. Retrying in {:?}.",
+                delay
+                );
+                sqlx::query!(
+                    r#"
+                    UPDATE confirmation_email_queue
+                    SET n_retries = n_retries + 1, execute_after = now() + make_interval(secs => $2)
+                    WHERE subscriber_id = $1
+                    "#,
+                    task.subscriber_id,
+                    delay.as_secs_f64()
+                )
+                .execute(pool)
+                .await?;
+            }
+        }
+    }
+
+    Ok(ExecutionOutcome::TaskCompleted)
+}
@@ -1,7 +1,13 @@
 mod new_subscriber;
+mod scheduled_time;
 mod subscriber_email;
 mod subscriber_name;
+mod subscriber_tag;
+mod tag_expression;
 
 pub use new_subscriber::NewSubscriber;
+pub use scheduled_time::ScheduledTime;
 pub use subscriber_email::SubscriberEmail;
 pub use subscriber_name::SubscriberName;
+pub use subscriber_tag::SubscriberTag;
+pub use tag_expression::TagExpression;
@@ -0,0 +1,83 @@
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+
+/// Point in time an issue is scheduled to be published at
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScheduledTime(DateTime<Utc>);
+
+impl ScheduledTime {
+    /// Parses a wall-clock time (as sent by a `datetime-local` input)
+    /// in the given IANA timezone, e.g. `2025-05-05T08:00` in `Europe/Rome`
+    pub fn parse(local_time: &str, timezone: &str) -> Result<ScheduledTime, String> {
+        let tz: Tz = timezone
+            .trim()
+            .parse()
+            .map_err(|_| format!("\"{}\" is not a valid timezone", timezone))?;
+        let naive = ["%Y-%m-%dT%H:%M", "%Y-%m-%dT%H:%M:%S"]
+            .iter()
+            .find_map(|f| NaiveDateTime::parse_from_str(local_time.trim(), f).ok())
+            .ok_or_else(|| format!("\"{}\" is not a valid date and time", local_time))?;
+        // When clocks go back the same wall-clock time happens twice: pick the first one
+        let time = tz.from_local_datetime(&naive).earliest().ok_or_else(|| {
+            format!(
+                "\"{}\" does not exist in {} because of a daylight saving time change",
+                local_time, timezone
+            )
+        })?;
+        Ok(Self(time.with_timezone(&Utc)))
+    }
+}
+
+impl AsRef<DateTime<Utc>> for ScheduledTime {
+    fn as_ref(&self) -> &DateTime<Utc> {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use claims::{assert_err, assert_ok_eq};
+
+    use crate::domain::ScheduledTime;
+
+    #[test]
+    fn local_time_is_converted_to_utc() {
+        let expected = Utc.with_ymd_and_hms(2025, 5, 5, 6, 0, 0).unwrap();
+        let time = ScheduledTime::parse("2025-05-05T08:00", "Europe/Rome").map(|t| *t.as_ref());
+        assert_ok_eq!(time, expected);
+    }
+
+    #[test]
+    fn seconds_are_accepted() {
+        let expected = Utc.with_ymd_and_hms(2025, 5, 5, 8, 0, 30).unwrap();
+        let time = ScheduledTime::parse("2025-05-05T08:00:30", "UTC").map(|t| *t.as_ref());
+        assert_ok_eq!(time, expected);
+    }
+
+    #[test]
+    fn unknown_timezones_are_rejected() {
+        assert_err!(ScheduledTime::parse(
+            "2025-05-05T08:00",
+            "Mars/Olympus_Mons"
+        ));
+    }
+
+    #[test]
+    fn malformed_times_are_rejected() {
+        assert_err!(ScheduledTime::parse("05/05/2025 08:00", "UTC"));
+    }
+
+    #[test]
+    fn times_skipped_by_daylight_saving_are_rejected() {
+        assert_err!(ScheduledTime::parse("2025-03-30T02:30", "Europe/Rome"));
+    }
+
+    #[test]
+    fn ambiguous_times_resolve_to_the_earliest() {
+        // 02:30 happens first in CEST (UTC+2), then again in CET (UTC+1)
+        let expected = Utc.with_ymd_and_hms(2025, 10, 26, 0, 30, 0).unwrap();
+        let time = ScheduledTime::parse("2025-10-26T02:30", "Europe/Rome").map(|t| *t.as_ref());
+        assert_ok_eq!(time, expected);
+    }
+}
@@ -6,11 +6,16 @@ pub struct SubscriberEmail(String);
 impl SubscriberEmail {
     pub fn parse(s: &str) -> Result<SubscriberEmail, String> {
         if s.validate_email() {
-            Ok(Self(s.to_string()))
+            Ok(Self(Self::normalise(s)))
         } else {
             Err(format!("\"{}\" is not a valid email address", s))
         }
     }
+
+    /// Addresses are stored and compared in lowercase, whatever the case they were typed in
+    pub fn normalise(s: &str) -> String {
+        s.trim().to_lowercase()
+    }
 }
 
 impl std::fmt::Display for SubscriberEmail {
@@ -44,6 +49,12 @@ mod tests {
         }
     }
 
+    #[test]
+    fn emails_are_lowercased() {
+        let email = SubscriberEmail::parse("Ursula.Le.Guin@Example.com").unwrap();
+        assert_eq!(email.as_ref(), "ursula.le.guin@example.com");
+    }
+
     #[test]
     fn empty_string_is_rejected() {
         assert_err!(SubscriberEmail::parse(""));
@@ -0,0 +1,81 @@
+/// Label an admin attaches to subscribers, e.g. `webinar-2025`.
+/// Tags are lowercase, made of letters, digits, `-` and `_`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SubscriberTag(String);
+
+/// Words with a meaning of their own in tag expressions
+const RESERVED_WORDS: [&str; 3] = ["and", "or", "not"];
+
+impl SubscriberTag {
+    pub fn parse(s: &str) -> Result<SubscriberTag, String> {
+        let tag = s.trim().to_lowercase();
+        let is_valid = !tag.is_empty()
+            && tag.chars().count() <= 64
+            && tag
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+            && !RESERVED_WORDS.contains(&tag.as_str());
+        if is_valid {
+            Ok(Self(tag))
+        } else {
+            Err(format!("\"{}\" is not a valid tag", s.trim()))
+        }
+    }
+
+    /// Parses tags separated by commas or whitespace, e.g. `webinar, early-adopter`
+    pub fn parse_list(s: &str) -> Result<Vec<SubscriberTag>, String> {
+        let mut tags: Vec<SubscriberTag> = vec![];
+        for tag in s
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|t| !t.is_empty())
+        {
+            let tag = SubscriberTag::parse(tag)?;
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+        Ok(tags)
+    }
+}
+
+impl AsRef<str> for SubscriberTag {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok_eq};
+
+    use crate::domain::SubscriberTag;
+
+    #[test]
+    fn tags_are_lowercased() {
+        let tag = SubscriberTag::parse(" Early-Adopter_2 ").map(|t| t.as_ref().to_owned());
+        assert_ok_eq!(tag, "early-adopter_2".to_owned());
+    }
+
+    #[test]
+    fn tags_with_spaces_or_punctuation_are_rejected() {
+        assert_err!(SubscriberTag::parse("two words"));
+        assert_err!(SubscriberTag::parse("c++"));
+        assert_err!(SubscriberTag::parse("a(b)"));
+    }
+
+    #[test]
+    fn empty_and_reserved_tags_are_rejected() {
+        assert_err!(SubscriberTag::parse("  "));
+        assert_err!(SubscriberTag::parse("NOT"));
+    }
+
+    #[test]
+    fn lists_are_split_on_commas_and_whitespace_without_duplicates() {
+        let tags: Vec<_> = SubscriberTag::parse_list("rust, go  webinar,rust,")
+            .unwrap()
+            .into_iter()
+            .map(|t| t.as_ref().to_owned())
+            .collect();
+        assert_eq!(tags, ["rust", "go", "webinar"]);
+    }
+}
@@ -0,0 +1,210 @@
+use std::collections::HashSet;
+
+use super::SubscriberTag;
+
+/// Boolean combination of tags selecting subscribers,
+/// e.g. `(rust OR go) AND NOT churned`.
+/// `NOT` binds tighter than `AND`, which binds tighter than `OR`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TagExpression {
+    /// Matches every subscriber: the expression was left empty
+    Any,
+    Tag(SubscriberTag),
+    Not(Box<TagExpression>),
+    And(Box<TagExpression>, Box<TagExpression>),
+    Or(Box<TagExpression>, Box<TagExpression>),
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Open,
+    Close,
+}
+
+impl TagExpression {
+    pub fn parse(s: &str) -> Result<TagExpression, String> {
+        let tokens = tokenize(s);
+        if tokens.is_empty() {
+            return Ok(TagExpression::Any);
+        }
+        let mut parser = Parser {
+            tokens,
+            position: 0,
+        };
+        let expression = parser.or()?;
+        match parser.tokens.get(parser.position) {
+            None => Ok(expression),
+            Some(Token::Close) => Err("Unbalanced \")\" in the tag expression.".into()),
+            Some(_) => Err("Tags must be combined with AND or OR in the tag expression.".into()),
+        }
+    }
+
+    /// Whether a subscriber with `tags` is selected
+    pub fn matches(&self, tags: &HashSet<&str>) -> bool {
+        match self {
+            TagExpression::Any => true,
+            TagExpression::Tag(tag) => tags.contains(tag.as_ref()),
+            TagExpression::Not(e) => !e.matches(tags),
+            TagExpression::And(a, b) => a.matches(tags) && b.matches(tags),
+            TagExpression::Or(a, b) => a.matches(tags) || b.matches(tags),
+        }
+    }
+
+    /// The expression as a Postgres `tsquery`, to match the tags of subscribers in SQL
+    /// as `array_to_tsvector(tags) @@ query`. `None` if it matches every subscriber.
+    pub fn to_tsquery(&self) -> Option<String> {
+        match self {
+            TagExpression::Any => None,
+            // Tags are made of letters, digits, '-' and '_': they never need escaping
+            TagExpression::Tag(tag) => Some(format!("'{}'", tag.as_ref())),
+            TagExpression::Not(e) => Some(format!("!{}", e.to_tsquery()?)),
+            TagExpression::And(a, b) => {
+                Some(format!("({} & {})", a.to_tsquery()?, b.to_tsquery()?))
+            }
+            TagExpression::Or(a, b) => Some(format!("({} | {})", a.to_tsquery()?, b.to_tsquery()?)),
+        }
+    }
+}
+
+fn tokenize(s: &str) -> Vec<Token> {
+    let mut tokens = vec![];
+    let mut word = String::new();
+    for c in s.chars() {
+        if c == '(' || c == ')' || c.is_whitespace() {
+            if !word.is_empty() {
+                tokens.push(Token::Word(std::mem::take(&mut word)));
+            }
+            match c {
+                '(' => tokens.push(Token::Open),
+                ')' => tokens.push(Token::Close),
+                _ => {}
+            }
+        } else {
+            word.push(c);
+        }
+    }
+    if !word.is_empty() {
+        tokens.push(Token::Word(word));
+    }
+    tokens
+}
+
+/// Recursive descent over the grammar:
+/// `or := and ("OR" and)*`, `and := not ("AND" not)*`,
+/// `not := "NOT" not | tag | "(" or ")"`
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn next_is_keyword(&self, keyword: &str) -> bool {
+        matches!(
+            self.tokens.get(self.position),
+            Some(Token::Word(w)) if w.eq_ignore_ascii_case(keyword)
+        )
+    }
+
+    fn or(&mut self) -> Result<TagExpression, String> {
+        let mut expression = self.and()?;
+        while self.next_is_keyword("or") {
+            self.position += 1;
+            expression = TagExpression::Or(Box::new(expression), Box::new(self.and()?));
+        }
+        Ok(expression)
+    }
+
+    fn and(&mut self) -> Result<TagExpression, String> {
+        let mut expression = self.not()?;
+        while self.next_is_keyword("and") {
+            self.position += 1;
+            expression = TagExpression::And(Box::new(expression), Box::new(self.not()?));
+        }
+        Ok(expression)
+    }
+
+    fn not(&mut self) -> Result<TagExpression, String> {
+        let token = self.tokens.get(self.position);
+        self.position += 1;
+        match token {
+            Some(Token::Word(w)) if w.eq_ignore_ascii_case("not") => {
+                Ok(TagExpression::Not(Box::new(self.not()?)))
+            }
+            Some(Token::Word(w)) => Ok(TagExpression::Tag(SubscriberTag::parse(w)?)),
+            Some(Token::Open) => {
+                let expression = self.or()?;
+                match self.tokens.get(self.position) {
+                    Some(Token::Close) => {
+                        self.position += 1;
+                        Ok(expression)
+                    }
+                    _ => Err("Missing \")\" in the tag expression.".into()),
+                }
+            }
+            Some(Token::Close) | None => Err("A tag is missing in the tag expression.".into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use claims::{assert_err, assert_ok_eq};
+
+    use crate::domain::TagExpression;
+
+    fn matches(expression: &str, tags: &[&str]) -> bool {
+        let tags: HashSet<&str> = tags.iter().copied().collect();
+        TagExpression::parse(expression).unwrap().matches(&tags)
+    }
+
+    #[test]
+    fn an_empty_expression_matches_everyone() {
+        assert_ok_eq!(TagExpression::parse("  "), TagExpression::Any);
+        assert!(matches("", &[]));
+    }
+
+    #[test]
+    fn a_single_tag_matches_the_subscribers_having_it() {
+        assert!(matches("Rust", &["rust"]));
+        assert!(!matches("rust", &["go"]));
+    }
+
+    #[test]
+    fn not_binds_tighter_than_and_which_binds_tighter_than_or() {
+        // rust OR (go AND (NOT churned))
+        let expression = "rust or go AND NOT churned";
+        assert!(matches(expression, &["rust", "churned"]));
+        assert!(matches(expression, &["go"]));
+        assert!(!matches(expression, &["go", "churned"]));
+    }
+
+    #[test]
+    fn parentheses_group_sub_expressions() {
+        let expression = "(rust OR go) AND NOT churned";
+        assert!(matches(expression, &["go"]));
+        assert!(!matches(expression, &["rust", "churned"]));
+        assert!(matches("NOT (rust OR go)", &["python"]));
+    }
+
+    #[test]
+    fn malformed_expressions_are_rejected() {
+        assert_err!(TagExpression::parse("rust go"));
+        assert_err!(TagExpression::parse("rust AND"));
+        assert_err!(TagExpression::parse("(rust OR go"));
+        assert_err!(TagExpression::parse("rust)"));
+        assert_err!(TagExpression::parse("c++"));
+    }
+
+    #[test]
+    fn expressions_translate_to_tsqueries_with_the_same_precedence() {
+        let tsquery = |e: &str| TagExpression::parse(e).unwrap().to_tsquery();
+        assert_eq!(tsquery(""), None);
+        assert_eq!(
+            tsquery("rust or early-adopter AND NOT churned"),
+            Some("('rust' | ('early-adopter' & !'churned'))".into())
+        );
+    }
+}
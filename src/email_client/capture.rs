@@ -0,0 +1,56 @@
+use sqlx::{types::Json, PgPool};
+use uuid::Uuid;
+
+use crate::domain::SubscriberEmail;
+
+use super::{EmailHeader, EmailSender};
+
+/// Stores outgoing emails in Postgres instead of sending them,
+/// so that they can be browsed from the admin outbox during development.
+pub struct CaptureEmailClient {
+    pool: PgPool,
+    sender: SubscriberEmail,
+}
+
+impl CaptureEmailClient {
+    pub fn new(pool: PgPool, sender: SubscriberEmail) -> Self {
+        Self { pool, sender }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailSender for CaptureEmailClient {
+    fn sender(&self) -> &SubscriberEmail {
+        &self.sender
+    }
+
+    #[tracing::instrument(name = "Capturing an outgoing email", skip_all)]
+    async fn send_email_as(
+        &self,
+        sender: &SubscriberEmail,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[EmailHeader],
+    ) -> Result<Option<String>, anyhow::Error> {
+        let captured_email_id = Uuid::new_v4();
+        sqlx::query!(
+            r#"
+            INSERT INTO captured_emails
+            (captured_email_id, sender, recipient, subject, html_body, text_body, headers, captured_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, now())
+            "#,
+            captured_email_id,
+            sender.as_ref(),
+            recipient.as_ref(),
+            subject,
+            html_content,
+            text_content,
+            Json(headers) as _
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(Some(captured_email_id.to_string()))
+    }
+}
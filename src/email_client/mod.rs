@@ -0,0 +1,64 @@
+mod capture;
+mod postmark;
+pub mod smtp;
+
+pub use capture::CaptureEmailClient;
+pub use postmark::PostmarkEmailClient;
+pub use smtp::SmtpEmailClient;
+
+use crate::domain::SubscriberEmail;
+
+/// A transport able to deliver emails on behalf of the application
+#[async_trait::async_trait]
+pub trait EmailSender: Send + Sync {
+    /// Address the emails are sent from
+    fn sender(&self) -> &SubscriberEmail;
+
+    /// Returns the id the transport assigned to the message, if it exposes one
+    async fn send_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[EmailHeader],
+    ) -> Result<Option<String>, anyhow::Error> {
+        self.send_email_as(
+            self.sender(),
+            recipient,
+            subject,
+            html_content,
+            text_content,
+            headers,
+        )
+        .await
+    }
+
+    /// Same as [`EmailSender::send_email`], from `sender` instead of the default address
+    async fn send_email_as(
+        &self,
+        sender: &SubscriberEmail,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[EmailHeader],
+    ) -> Result<Option<String>, anyhow::Error>;
+}
+
+/// Custom header added to an outgoing email (e.g. `List-Unsubscribe`)
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct EmailHeader {
+    pub name: String,
+    pub value: String,
+}
+
+impl EmailHeader {
+    pub fn new(name: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            value: value.into(),
+        }
+    }
+}
@@ -5,7 +5,10 @@ use secrecy::{ExposeSecret, Secret};
 
 use crate::domain::SubscriberEmail;
 
-pub struct EmailClient {
+use super::{EmailHeader, EmailSender};
+
+/// Sends emails through Postmark's `/email` JSON API
+pub struct PostmarkEmailClient {
     http_client: Client,
     base_url: String,
     sender: SubscriberEmail,
@@ -13,7 +16,7 @@ pub struct EmailClient {
     stream: String,
 }
 
-impl EmailClient {
+impl PostmarkEmailClient {
     pub fn new(
         base_url: String,
         sender: SubscriberEmail,
@@ -30,28 +33,39 @@ impl EmailClient {
             stream,
         }
     }
+}
 
-    pub async fn send_email(
+#[async_trait::async_trait]
+impl EmailSender for PostmarkEmailClient {
+    fn sender(&self) -> &SubscriberEmail {
+        &self.sender
+    }
+
+    async fn send_email_as(
         &self,
+        sender: &SubscriberEmail,
         recipient: &SubscriberEmail,
         subject: &str,
         html_content: &str,
         text_content: &str,
-    ) -> Result<(), reqwest::Error> {
+        headers: &[EmailHeader],
+    ) -> Result<Option<String>, anyhow::Error> {
         // TODO replace base_url with reqwest::Url or a type that can be .into() it.
         // In this way, you can use reqwest::Url::join
         let url = format!("{}/email", self.base_url);
 
         let request = SendEmailRequest {
-            from: self.sender.as_ref(),
+            from: sender.as_ref(),
             to: recipient.as_ref(),
             subject,
             html_body: html_content,
             text_body: text_content,
             message_stream: &self.stream,
+            headers,
         };
 
-        self.http_client
+        let response = self
+            .http_client
             .post(&url)
             .header(
                 "X-Postmark-Server-Token",
@@ -62,7 +76,13 @@ impl EmailClient {
             .await?
             .error_for_status()?;
 
-        Ok(())
+        // The email went out anyway: a body we can't make sense of only costs us its id
+        let message_id = response
+            .json::<SendEmailResponse>()
+            .await
+            .ok()
+            .map(|r| r.message_id);
+        Ok(message_id)
     }
 }
 
@@ -75,6 +95,14 @@ struct SendEmailRequest<'a> {
     html_body: &'a str,
     text_body: &'a str,
     message_stream: &'a str,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    headers: &'a [EmailHeader],
+}
+
+#[derive(serde::Deserialize)]
+struct SendEmailResponse {
+    #[serde(rename = "MessageID")]
+    message_id: String,
 }
 
 #[cfg(test)]
@@ -82,19 +110,20 @@ mod tests {
 
     use std::time::Duration;
 
-    use claims::{assert_err, assert_ok};
+    use claims::{assert_err, assert_ok, assert_ok_eq};
     use secrecy::Secret;
     use wiremock::{
-        matchers::{any, header, header_exists, method, path},
+        matchers::{any, body_partial_json, header, header_exists, method, path},
         Mock, MockServer, ResponseTemplate,
     };
 
     use crate::domain::SubscriberEmail;
+    use crate::email_client::{EmailHeader, EmailSender};
     use fake::faker::internet::en::SafeEmail;
     use fake::faker::lorem::en::{Paragraph, Sentence};
     use fake::{Fake, Faker};
 
-    use super::EmailClient;
+    use super::PostmarkEmailClient;
 
     fn email() -> SubscriberEmail {
         SubscriberEmail::parse(&SafeEmail().fake::<String>()).unwrap()
@@ -108,8 +137,8 @@ mod tests {
         Paragraph(1..10).fake()
     }
 
-    fn email_client(base_url: String) -> EmailClient {
-        EmailClient::new(
+    fn email_client(base_url: String) -> PostmarkEmailClient {
+        PostmarkEmailClient::new(
             base_url,
             email(),
             Secret::new(Faker.fake()),
@@ -134,7 +163,7 @@ mod tests {
             .await;
 
         let _ = email_client
-            .send_email(&email(), &subject(), &content(), &content())
+            .send_email(&email(), &subject(), &content(), &content(), &[])
             .await;
     }
 
@@ -154,12 +183,38 @@ mod tests {
             .await;
 
         let outcome = email_client
-            .send_email(&email(), &subject(), &content(), &content())
+            .send_email(&email(), &subject(), &content(), &content(), &[])
             .await;
 
         assert_ok!(outcome);
     }
 
+    #[tokio::test]
+    async fn send_email_returns_the_postmark_message_id() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "To": "recipient@example.com",
+                "MessageID": "b7bc2f4a-e38e-4336-af7d-e6c392c2f817",
+                "ErrorCode": 0,
+                "Message": "OK"
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content(), &[])
+            .await;
+
+        assert_ok_eq!(
+            outcome,
+            Some("b7bc2f4a-e38e-4336-af7d-e6c392c2f817".to_string())
+        );
+    }
+
     #[tokio::test]
     async fn send_emailfails_if_server_returns_500() {
         let mock_server = MockServer::start().await;
@@ -172,7 +227,7 @@ mod tests {
             .await;
 
         let outcome = email_client
-            .send_email(&email(), &subject(), &content(), &content())
+            .send_email(&email(), &subject(), &content(), &content(), &[])
             .await;
 
         assert_err!(outcome);
@@ -190,12 +245,40 @@ mod tests {
             .await;
 
         let outcome = email_client
-            .send_email(&email(), &subject(), &content(), &content())
+            .send_email(&email(), &subject(), &content(), &content(), &[])
             .await;
 
         assert_err!(outcome);
     }
 
+    #[tokio::test]
+    async fn send_email_includes_custom_headers() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(path("/email"))
+            .and(method("POST"))
+            .and(body_partial_json(serde_json::json!({
+                "Headers": [
+                    { "Name": "List-Unsubscribe-Post", "Value": "List-Unsubscribe=One-Click" }
+                ]
+            })))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let headers = [EmailHeader::new(
+            "List-Unsubscribe-Post",
+            "List-Unsubscribe=One-Click",
+        )];
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content(), &headers)
+            .await;
+
+        assert_ok!(outcome);
+    }
+
     struct EmailBodyMatcher;
 
     fn is_a_valid_email_request() -> EmailBodyMatcher {
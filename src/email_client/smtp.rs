@@ -0,0 +1,300 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use lettre::{
+    message::{
+        header::{HeaderName, HeaderValue},
+        Mailbox, MultiPart,
+    },
+    transport::smtp::authentication::{Credentials, Mechanism},
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use secrecy::{ExposeSecret, Secret};
+use uuid::Uuid;
+
+use crate::domain::SubscriberEmail;
+
+use super::{EmailHeader, EmailSender};
+
+#[derive(serde::Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    /// Upgrade the connection with STARTTLS, failing if the server doesn't support it
+    StartTls,
+    /// Plain-text connection, only meant for local SMTP sinks
+    None,
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpAuthMechanism {
+    Plain,
+    Login,
+}
+
+impl From<SmtpAuthMechanism> for Mechanism {
+    fn from(value: SmtpAuthMechanism) -> Self {
+        match value {
+            SmtpAuthMechanism::Plain => Mechanism::Plain,
+            SmtpAuthMechanism::Login => Mechanism::Login,
+        }
+    }
+}
+
+/// Sends emails to an SMTP relay
+pub struct SmtpEmailClient {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    sender: SubscriberEmail,
+}
+
+impl SmtpEmailClient {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        host: &str,
+        port: u16,
+        tls: SmtpTls,
+        username: String,
+        password: Secret<String>,
+        auth_mechanism: SmtpAuthMechanism,
+        sender: SubscriberEmail,
+        timeout: Duration,
+    ) -> Result<Self, anyhow::Error> {
+        let builder = match tls {
+            SmtpTls::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)
+                .context("Failed to set up the STARTTLS relay")?,
+            SmtpTls::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host),
+        };
+        let transport = builder
+            .port(port)
+            .credentials(Credentials::new(
+                username,
+                password.expose_secret().to_owned(),
+            ))
+            .authentication(vec![auth_mechanism.into()])
+            .timeout(Some(timeout))
+            .build();
+        Ok(Self { transport, sender })
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailSender for SmtpEmailClient {
+    fn sender(&self) -> &SubscriberEmail {
+        &self.sender
+    }
+
+    async fn send_email_as(
+        &self,
+        sender: &SubscriberEmail,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[EmailHeader],
+    ) -> Result<Option<String>, anyhow::Error> {
+        // Our own Message-ID, so that we know it without parsing the server's reply
+        let domain = sender.as_ref().rsplit('@').next().unwrap_or_default();
+        let message_id = format!("<{}@{}>", Uuid::new_v4(), domain);
+        let mut builder = Message::builder()
+            .message_id(Some(message_id.clone()))
+            .from(sender.as_ref().parse::<Mailbox>()?)
+            .to(recipient.as_ref().parse::<Mailbox>()?)
+            .subject(subject);
+        for header in headers {
+            let name = HeaderName::new_from_ascii(header.name.clone())
+                .context("Invalid email header name")?;
+            builder = builder.raw_header(HeaderValue::new(name, header.value.clone()));
+        }
+        let message = builder
+            .multipart(MultiPart::alternative_plain_html(
+                text_content.to_owned(),
+                html_content.to_owned(),
+            ))
+            .context("Failed to build the email message")?;
+
+        self.transport.send(message).await?;
+        Ok(Some(message_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use claims::{assert_err, assert_ok};
+    use secrecy::Secret;
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::TcpListener,
+        task::JoinHandle,
+    };
+
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::{EmailHeader, EmailSender};
+
+    use super::{SmtpAuthMechanism, SmtpEmailClient, SmtpTls};
+
+    /// What a [`smtp_sink`] received during a single SMTP session
+    struct Session {
+        credentials: Vec<String>,
+        recipients: Vec<String>,
+        data: String,
+    }
+
+    /// A bare-bones SMTP server accepting a single message and any credentials
+    async fn smtp_sink() -> (u16, JoinHandle<Session>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            let mut session = Session {
+                credentials: vec![],
+                recipients: vec![],
+                data: String::new(),
+            };
+            writer.write_all(b"220 sink ESMTP\r\n").await.unwrap();
+            while let Some(line) = lines.next_line().await.unwrap() {
+                let command = line.to_uppercase();
+                let reply: &[u8] = if command.starts_with("EHLO") {
+                    b"250-sink\r\n250 AUTH PLAIN LOGIN\r\n"
+                } else if let Some(initial_response) = line.strip_prefix("AUTH PLAIN ") {
+                    session.credentials.push(initial_response.to_owned());
+                    b"235 Authenticated\r\n"
+                } else if command.starts_with("AUTH LOGIN") {
+                    for prompt in ["334 VXNlcm5hbWU6\r\n", "334 UGFzc3dvcmQ6\r\n"] {
+                        writer.write_all(prompt.as_bytes()).await.unwrap();
+                        session
+                            .credentials
+                            .push(lines.next_line().await.unwrap().unwrap());
+                    }
+                    b"235 Authenticated\r\n"
+                } else if command.starts_with("RCPT TO:") {
+                    session.recipients.push(line[8..].to_owned());
+                    b"250 OK\r\n"
+                } else if command.starts_with("DATA") {
+                    writer.write_all(b"354 Go ahead\r\n").await.unwrap();
+                    while let Some(line) = lines.next_line().await.unwrap() {
+                        if line == "." {
+                            break;
+                        }
+                        session.data.push_str(&line);
+                        session.data.push('\n');
+                    }
+                    writer.write_all(b"250 Queued\r\n").await.unwrap();
+                    return session;
+                } else {
+                    b"250 OK\r\n"
+                };
+                writer.write_all(reply).await.unwrap();
+            }
+            session
+        });
+        (port, handle)
+    }
+
+    fn email_client(port: u16, auth_mechanism: SmtpAuthMechanism) -> SmtpEmailClient {
+        SmtpEmailClient::new(
+            "127.0.0.1",
+            port,
+            SmtpTls::None,
+            "user".into(),
+            Secret::new("password".into()),
+            auth_mechanism,
+            SubscriberEmail::parse("sender@example.com").unwrap(),
+            Duration::from_millis(500),
+        )
+        .unwrap()
+    }
+
+    fn recipient() -> SubscriberEmail {
+        SubscriberEmail::parse("recipient@example.com").unwrap()
+    }
+
+    #[tokio::test]
+    async fn send_email_delivers_the_message_to_the_smtp_server() {
+        let (port, sink) = smtp_sink().await;
+        let email_client = email_client(port, SmtpAuthMechanism::Plain);
+        let headers = [EmailHeader::new(
+            "List-Unsubscribe-Post",
+            "List-Unsubscribe=One-Click",
+        )];
+
+        let outcome = email_client
+            .send_email(
+                &recipient(),
+                "Subject line",
+                "<p>html content</p>",
+                "text content",
+                &headers,
+            )
+            .await;
+
+        let message_id = assert_ok!(outcome).unwrap();
+        let session = sink.await.unwrap();
+        assert_eq!(session.recipients, vec!["<recipient@example.com>"]);
+        assert!(session
+            .data
+            .contains(&format!("Message-ID: {}", message_id)));
+        assert!(session.data.contains("Subject: Subject line"));
+        assert!(session
+            .data
+            .contains("List-Unsubscribe-Post: List-Unsubscribe=One-Click"));
+        assert!(session.data.contains("text content"));
+        assert!(session.data.contains("<p>html content</p>"));
+    }
+
+    #[tokio::test]
+    async fn send_email_authenticates_with_auth_plain() {
+        let (port, sink) = smtp_sink().await;
+        let email_client = email_client(port, SmtpAuthMechanism::Plain);
+
+        let outcome = email_client
+            .send_email(&recipient(), "Subject", "html", "text", &[])
+            .await;
+
+        assert_ok!(outcome);
+        let session = sink.await.unwrap();
+        assert_eq!(
+            session.credentials,
+            vec![STANDARD.encode("\0user\0password")]
+        );
+    }
+
+    #[tokio::test]
+    async fn send_email_authenticates_with_auth_login() {
+        let (port, sink) = smtp_sink().await;
+        let email_client = email_client(port, SmtpAuthMechanism::Login);
+
+        let outcome = email_client
+            .send_email(&recipient(), "Subject", "html", "text", &[])
+            .await;
+
+        assert_ok!(outcome);
+        let session = sink.await.unwrap();
+        assert_eq!(
+            session.credentials,
+            vec![STANDARD.encode("user"), STANDARD.encode("password")]
+        );
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_if_the_server_is_unreachable() {
+        // Grab a free port and release it straight away
+        let port = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let email_client = email_client(port, SmtpAuthMechanism::Plain);
+
+        let outcome = email_client
+            .send_email(&recipient(), "Subject", "html", "text", &[])
+            .await;
+
+        assert_err!(outcome);
+    }
+}
@@ -1,22 +1,57 @@
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 
+use anyhow::Context;
+use htmlescape::{decode_html, encode_minimal};
+use rand::Rng;
 use sqlx::{PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
 use crate::{
-    configuration::Settings, domain::SubscriberEmail, email_client::EmailClient,
+    configuration::{IssueDeliverySettings, Settings},
+    domain::SubscriberEmail,
+    email_client::{EmailHeader, EmailSender},
+    issue_template::{render_template, ContentFormat, TemplateVariables},
+    routes::{
+        click_tracking_link, one_click_unsubscribe_link, open_pixel_link, preferences_link,
+        unsubscribe_link,
+    },
+    segments::{get_recipients, get_segment},
+    signed_token::TokenSigner,
     startup::get_connection_pool,
+    suppression_list::is_suppressed,
 };
 
 pub async fn run_worker_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
     let connection_pool = get_connection_pool(&configuration.database);
-    let email_client = configuration.email_client.client();
-    worker_loop(connection_pool, email_client).await
+    let email_client = configuration.email_client.client(&connection_pool);
+    let token_signer = TokenSigner::new(configuration.application.hmac_secret);
+    worker_loop(
+        connection_pool,
+        email_client,
+        configuration.issue_delivery,
+        configuration.application.base_url,
+        token_signer,
+    )
+    .await
 }
 
-async fn worker_loop(pool: PgPool, email_client: EmailClient) -> Result<(), anyhow::Error> {
+async fn worker_loop(
+    pool: PgPool,
+    email_client: Arc<dyn EmailSender>,
+    settings: IssueDeliverySettings,
+    base_url: String,
+    token_signer: TokenSigner,
+) -> Result<(), anyhow::Error> {
     loop {
-        match try_execute_task(&pool, &email_client).await {
+        match try_execute_task(
+            &pool,
+            email_client.as_ref(),
+            &settings,
+            &base_url,
+            &token_signer,
+        )
+        .await
+        {
             Ok(ExecutionOutcome::EmptyQueue) => {
                 tokio::time::sleep(Duration::from_secs(10)).await;
             }
@@ -36,33 +71,173 @@ pub enum ExecutionOutcome {
 #[tracing::instrument(name = "Send mail to subscriber task",skip_all,fields(newsletter_issue_id=tracing::field::Empty,subscriber_email=tracing::field::Empty),err)]
 pub async fn try_execute_task(
     pool: &PgPool,
-    email_client: &EmailClient,
+    email_client: &dyn EmailSender,
+    settings: &IssueDeliverySettings,
+    base_url: &str,
+    token_signer: &TokenSigner,
 ) -> Result<ExecutionOutcome, anyhow::Error> {
     let task = dequeue_task(pool).await?;
     if task.is_none() {
         return Ok(ExecutionOutcome::EmptyQueue);
     }
 
-    let (transaction, issue_id, email) = task.unwrap();
-    match SubscriberEmail::parse(&email) {
+    let (mut transaction, task) = task.unwrap();
+    let issue_delivery_id = Uuid::new_v4();
+    match SubscriberEmail::parse(&task.subscriber_email) {
         Ok(subscriber) => {
-            let issue = get_issue(pool, issue_id).await?;
-
-            if let Err(e) = email_client
-                .send_email(
-                    &subscriber,
-                    &issue.title,
-                    &issue.html_content,
-                    &issue.text_content,
-                )
-                .await
-            {
-                tracing::error!(
-                error.cause_chain= ?e,
-                error.message= %e,
-                "Failed to deliver issue to a confirmed subscriber. \
-                Skipping.",
-                );
+            match get_confirmed_subscriber(pool, &subscriber, task.newsletter_issue_id).await? {
+                Some(ConfirmedSubscriber {
+                    id: subscriber_id,
+                    name,
+                }) => {
+                    let issue = get_issue(pool, task.newsletter_issue_id).await?;
+                    let variables = template_variables(
+                        base_url,
+                        token_signer,
+                        task.newsletter_issue_id,
+                        subscriber_id,
+                        name,
+                    );
+                    let title = render_template(&issue.title, &variables, ContentFormat::Text);
+                    let preferences_url = preferences_link(base_url, token_signer, subscriber_id);
+                    let mut html_footer = format!(
+                        "<p><a href=\"{}\">Unsubscribe</a> from this newsletter \
+                    or <a href=\"{}\">manage your preferences</a>.</p>",
+                        variables.unsubscribe_url, preferences_url
+                    );
+                    if issue.track_opens {
+                        html_footer.push_str(&format!(
+                            "<img src=\"{}\" width=\"1\" height=\"1\" alt=\"\">",
+                            open_pixel_link(base_url, token_signer, issue_delivery_id)
+                        ));
+                    }
+                    let unsubscribe_prefix = format!("{}/subscriptions/unsubscribe", base_url);
+                    let html_content = rewrite_links(
+                        &render_template(&issue.html_content, &variables, ContentFormat::Html),
+                        |url| {
+                            let is_web_link = ["http://", "https://"]
+                                .iter()
+                                .any(|scheme| url.to_ascii_lowercase().starts_with(scheme));
+                            (is_web_link && !url.starts_with(&unsubscribe_prefix)).then(|| {
+                                click_tracking_link(base_url, token_signer, issue_delivery_id, url)
+                            })
+                        },
+                    );
+                    let html_content = with_html_footer(&html_content, &html_footer);
+                    let text_content = format!(
+                        "{}\n\nUnsubscribe from this newsletter: {}\nManage your preferences: {}",
+                        render_template(&issue.text_content, &variables, ContentFormat::Text),
+                        variables.unsubscribe_url,
+                        preferences_url
+                    );
+                    // Addresses of lists are checked when they are set
+                    let list_sender = issue
+                        .sender_email
+                        .as_deref()
+                        .and_then(|sender| SubscriberEmail::parse(sender).ok());
+                    let sender = list_sender.as_ref().unwrap_or(email_client.sender());
+                    let headers = list_unsubscribe_headers(
+                        &settings.unsubscribe_mailbox,
+                        &one_click_unsubscribe_link(base_url, token_signer, subscriber_id),
+                    );
+
+                    // Checked as late as possible: the address may have been suppressed
+                    // long after the issue was queued
+                    if is_suppressed(&mut *transaction, subscriber.as_ref()).await? {
+                        tracing::info!("Skipping an address on the suppression list");
+                        log_delivery(
+                            &mut transaction,
+                            issue_delivery_id,
+                            &task,
+                            Some(subscriber_id),
+                            DeliveryOutcome::Skipped {
+                                reason: "The address is on the suppression list",
+                            },
+                        )
+                        .await?;
+                        delete_task(transaction, &task).await?;
+                        return Ok(ExecutionOutcome::TaskCompleted);
+                    }
+
+                    match email_client
+                        .send_email_as(
+                            sender,
+                            &subscriber,
+                            &title,
+                            &html_content,
+                            &text_content,
+                            &headers,
+                        )
+                        .await
+                    {
+                        Ok(message_id) => {
+                            log_delivery(
+                                &mut transaction,
+                                issue_delivery_id,
+                                &task,
+                                Some(subscriber_id),
+                                DeliveryOutcome::Sent { message_id },
+                            )
+                            .await?;
+                        }
+                        Err(e) => {
+                            let error = e.to_string();
+                            if i32::from(task.n_retries) >= i32::from(settings.max_retries) {
+                                tracing::error!(
+                                error.cause_chain= ?e,
+                                error.message= %e,
+                                "Failed to deliver issue to a confirmed subscriber. \
+                                Retry budget exhausted, moving the task to the dead letters.",
+                                );
+                                log_delivery(
+                                    &mut transaction,
+                                    issue_delivery_id,
+                                    &task,
+                                    Some(subscriber_id),
+                                    DeliveryOutcome::Failed { error: &error },
+                                )
+                                .await?;
+                                dead_letter_task(transaction, &task, &error).await?;
+                            } else {
+                                let delay = backoff_delay(
+                                    task.n_retries,
+                                    settings.base_backoff(),
+                                    settings.max_backoff(),
+                                );
+                                tracing::warn!(
+                                error.cause_chain= ?e,
+                                error.message= %e,
+                                "Failed to deliver issue to a confirmed subscriber. \
+                                Retrying in {:?}.",
+                                delay
+                                );
+                                log_delivery(
+                                    &mut transaction,
+                                    issue_delivery_id,
+                                    &task,
+                                    Some(subscriber_id),
+                                    DeliveryOutcome::Retrying { error: &error },
+                                )
+                                .await?;
+                                reschedule_task(transaction, &task, delay).await?;
+                            }
+                            return Ok(ExecutionOutcome::TaskCompleted);
+                        }
+                    }
+                }
+                None => {
+                    tracing::info!("Skipping a subscriber that is no longer confirmed");
+                    log_delivery(
+                        &mut transaction,
+                        issue_delivery_id,
+                        &task,
+                        None,
+                        DeliveryOutcome::Skipped {
+                            reason: "The subscriber is no longer confirmed",
+                        },
+                    )
+                    .await?;
+                }
             }
         }
         Err(error) => {
@@ -71,18 +246,144 @@ pub async fn try_execute_task(
                 "Skipping a confirmed subscriber. \
                 Their stored contact details are invalid",
             );
+            log_delivery(
+                &mut transaction,
+                issue_delivery_id,
+                &task,
+                None,
+                DeliveryOutcome::Skipped { reason: &error },
+            )
+            .await?;
         }
     }
-    // TODO implment retry and exponential backoff instead of not retrying??
-    delete_task(transaction, &issue_id, &email).await?;
+    delete_task(transaction, &task).await?;
 
     Ok(ExecutionOutcome::TaskCompleted)
 }
 
+/// Values of the issue template variables for a given subscriber
+pub fn template_variables(
+    base_url: &str,
+    token_signer: &TokenSigner,
+    newsletter_issue_id: Uuid,
+    subscriber_id: Uuid,
+    name: String,
+) -> TemplateVariables {
+    TemplateVariables {
+        name,
+        unsubscribe_url: unsubscribe_link(base_url, token_signer, subscriber_id),
+        archive_url: format!("{}/issues/{}", base_url, newsletter_issue_id),
+    }
+}
+
+/// Exponential backoff with "equal jitter": the delay doubles on every retry
+/// (capped at `max`) and a random amount up to half of it is shaved off, so that
+/// tasks failing together don't all come back at the same time.
+pub(crate) fn backoff_delay(n_retries: i16, base: Duration, max: Duration) -> Duration {
+    let exponent = n_retries.clamp(0, 31) as u32;
+    let delay = base.saturating_mul(2u32.saturating_pow(exponent)).min(max);
+    let half = delay / 2;
+    half + half.mul_f64(rand::thread_rng().gen_range(0.0..=1.0))
+}
+
+/// One-click unsubscribe headers (RFC 2369 and RFC 8058), required by the
+/// main mailbox providers for bulk senders.
+/// Emails sent to `mailbox` are handled by the inbound email webhook.
+fn list_unsubscribe_headers(mailbox: &str, one_click_link: &str) -> [EmailHeader; 2] {
+    [
+        EmailHeader::new(
+            "List-Unsubscribe",
+            format!(
+                "<mailto:{}?subject=unsubscribe>, <{}>",
+                mailbox, one_click_link
+            ),
+        ),
+        EmailHeader::new("List-Unsubscribe-Post", "List-Unsubscribe=One-Click"),
+    ]
+}
+
+/// Inserts `footer` at the end of the body of an html document,
+/// or appends it if `html` is just a fragment.
+fn with_html_footer(html: &str, footer: &str) -> String {
+    match html.to_ascii_lowercase().rfind("</body>") {
+        Some(i) => format!("{}{}{}", &html[..i], footer, &html[i..]),
+        None => format!("{}{}", html, footer),
+    }
+}
+
+/// Replaces the target of every `<a href>` in `html` with what `rewrite` returns for it,
+/// leaving the link untouched when it returns `None`.
+fn rewrite_links(html: &str, mut rewrite: impl FnMut(&str) -> Option<String>) -> String {
+    let lowercase = html.to_ascii_lowercase();
+    let mut rewritten = String::with_capacity(html.len());
+    let mut copied_up_to = 0;
+    let mut search_from = 0;
+    while let Some(i) = lowercase[search_from..].find("<a") {
+        let tag_start = search_from + i;
+        search_from = tag_start + 2;
+        // `<a` must be the whole tag name, not the start of `<abbr>` or `<aside>`
+        if !lowercase[search_from..].starts_with(|c: char| c.is_ascii_whitespace()) {
+            continue;
+        }
+        let tag_end = lowercase[tag_start..]
+            .find('>')
+            .map_or(html.len(), |len| tag_start + len);
+        if let Some((start, end, target)) = href_value(&html[tag_start..tag_end]) {
+            if let Some(new_target) = rewrite(&target) {
+                rewritten.push_str(&html[copied_up_to..tag_start + start]);
+                rewritten.push_str(&format!("\"{}\"", encode_minimal(&new_target)));
+                copied_up_to = tag_start + end;
+            }
+        }
+        search_from = tag_end;
+    }
+    rewritten.push_str(&html[copied_up_to..]);
+    rewritten
+}
+
+/// Finds the `href` attribute of a tag: the range of its (possibly quoted) value,
+/// and the value itself with the html entities decoded
+fn href_value(tag: &str) -> Option<(usize, usize, String)> {
+    let lowercase = tag.to_ascii_lowercase();
+    let mut search_from = 0;
+    while let Some(i) = lowercase[search_from..].find("href") {
+        let name_start = search_from + i;
+        search_from = name_start + 4;
+        // Skip `data-href` and the like
+        if !lowercase[..name_start].ends_with(|c: char| c.is_ascii_whitespace()) {
+            continue;
+        }
+        let Some(after_equal) = lowercase[search_from..].trim_start().strip_prefix('=') else {
+            continue;
+        };
+        let start = tag.len() - after_equal.trim_start().len();
+        let (value, end) = match tag[start..].chars().next() {
+            Some(quote @ ('"' | '\'')) => {
+                let value = &tag[start + 1..];
+                let len = value.find(quote).unwrap_or(value.len());
+                (&value[..len], (start + len + 2).min(tag.len()))
+            }
+            _ => {
+                let value = &tag[start..];
+                let len = value
+                    .find(|c: char| c.is_ascii_whitespace())
+                    .unwrap_or(value.len());
+                (&value[..len], start + len)
+            }
+        };
+        let value = decode_html(value).unwrap_or_else(|_| value.to_owned());
+        return Some((start, end, value));
+    }
+    None
+}
+
 struct NewsletterIssue {
     title: String,
     text_content: String,
     html_content: String,
+    track_opens: bool,
+    /// Sender address of the list the issue belongs to, if it has its own
+    sender_email: Option<String>,
 }
 
 type PgTransaction = Transaction<'static, Postgres>;
@@ -91,10 +392,11 @@ async fn get_issue(pool: &PgPool, issue_id: Uuid) -> Result<NewsletterIssue, any
     let issue = sqlx::query_as!(
         NewsletterIssue,
         r#"
-    SELECT title, text_content, html_content
-    FROM newsletter_issues
+    SELECT i.title, i.text_content, i.html_content, i.track_opens, l.sender_email
+    FROM newsletter_issues i
+    JOIN lists l ON l.list_id = i.list_id
     WHERE
-    newsletter_issue_id = $1
+    i.newsletter_issue_id = $1
     "#,
         issue_id
     )
@@ -103,15 +405,51 @@ async fn get_issue(pool: &PgPool, issue_id: Uuid) -> Result<NewsletterIssue, any
     Ok(issue)
 }
 
+struct DeliveryTask {
+    newsletter_issue_id: Uuid,
+    subscriber_email: String,
+    n_retries: i16,
+}
+
+struct ConfirmedSubscriber {
+    id: Uuid,
+    name: String,
+}
+
+/// The subscriber, if they are confirmed and still on the list of the issue
+async fn get_confirmed_subscriber(
+    pool: &PgPool,
+    email: &SubscriberEmail,
+    issue_id: Uuid,
+) -> Result<Option<ConfirmedSubscriber>, anyhow::Error> {
+    let r = sqlx::query_as!(
+        ConfirmedSubscriber,
+        r#"
+    SELECT s.id, s.name
+    FROM subscriptions s
+    JOIN list_subscriptions ls ON ls.subscriber_id = s.id AND ls.status = 'confirmed'
+    JOIN newsletter_issues i ON i.list_id = ls.list_id
+    WHERE s.email = $1 AND s.status = 'confirmed' AND i.newsletter_issue_id = $2
+    "#,
+        email.as_ref(),
+        issue_id
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(r)
+}
+
 async fn dequeue_task(
     pool: &PgPool,
-) -> Result<Option<(PgTransaction, Uuid, String)>, anyhow::Error> {
+) -> Result<Option<(PgTransaction, DeliveryTask)>, anyhow::Error> {
     let mut transaction = pool.begin().await?;
 
-    let r = sqlx::query!(
+    let task = sqlx::query_as!(
+        DeliveryTask,
         r#"
-    SELECT newsletter_issue_id, subscriber_email
+    SELECT newsletter_issue_id, subscriber_email, n_retries
     FROM issue_delivery_queue
+    WHERE execute_after <= now()
     FOR UPDATE
     SKIP LOCKED
     LIMIT 1
@@ -120,34 +458,306 @@ async fn dequeue_task(
     .fetch_optional(transaction.as_mut())
     .await?;
 
-    if let Some(r) = r {
-        Ok(Some((
-            transaction,
-            r.newsletter_issue_id,
-            r.subscriber_email,
-        )))
-    } else {
-        Ok(None)
-    }
+    Ok(task.map(|t| (transaction, t)))
 }
 
 async fn delete_task(
     mut transaction: PgTransaction,
-    issue_id: &Uuid,
-    subscriber_email: &str,
+    task: &DeliveryTask,
 ) -> Result<(), anyhow::Error> {
     sqlx::query!(
         r#"
         DELETE FROM issue_delivery_queue
         WHERE newsletter_issue_id = $1 AND subscriber_email = $2;
         "#,
-        issue_id,
-        subscriber_email
+        task.newsletter_issue_id,
+        task.subscriber_email
     )
     .execute(transaction.as_mut())
     .await?;
 
+    mark_issue_as_sent_if_delivered(&mut transaction, task.newsletter_issue_id).await?;
+
     transaction.commit().await?;
 
     Ok(())
 }
+
+/// Flags an issue as `sent` once the last of its deliveries has left the queue
+pub(crate) async fn mark_issue_as_sent_if_delivered(
+    transaction: &mut PgTransaction,
+    newsletter_issue_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    // Serialise the workers finishing deliveries of the same issue: the check below
+    // runs with a fresh snapshot once the lock is acquired, so the last of them
+    // is guaranteed to see an empty queue.
+    sqlx::query!(
+        r#"
+        SELECT newsletter_issue_id
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+        FOR UPDATE
+        "#,
+        newsletter_issue_id
+    )
+    .fetch_optional(transaction.as_mut())
+    .await?;
+
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET status = 'sent'
+        WHERE newsletter_issue_id = $1
+            AND status = 'sending'
+            AND NOT EXISTS (
+                SELECT 1 FROM issue_delivery_queue WHERE newsletter_issue_id = $1
+            );
+        "#,
+        newsletter_issue_id
+    )
+    .execute(transaction.as_mut())
+    .await?;
+
+    Ok(())
+}
+
+/// Enqueues the delivery of an issue to the confirmed subscribers of its list,
+/// restricted to its segment if it has one.
+/// The issue is marked as `sending`, or straight away as `sent` if there
+/// is nobody to deliver it to.
+#[tracing::instrument(name = "Enqueue delivery tasks for newsletter", skip(transaction))]
+pub async fn enqueue_delivery_tasks(
+    transaction: &mut PgTransaction,
+    newsletter_issue_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    let issue = sqlx::query!(
+        "SELECT list_id, segment_id FROM newsletter_issues WHERE newsletter_issue_id = $1",
+        newsletter_issue_id
+    )
+    .fetch_one(transaction.as_mut())
+    .await?;
+    // Never fall back to the whole list if the segment can't be found
+    let segment = match issue.segment_id {
+        Some(segment_id) => Some(
+            get_segment(transaction.as_mut(), segment_id)
+                .await?
+                .with_context(|| format!("The segment {} of the issue is missing", segment_id))?,
+        ),
+        None => None,
+    };
+    let recipients = get_recipients(transaction.as_mut(), issue.list_id, segment.as_ref()).await?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email)
+        SELECT $1, unnest($2::text[]);
+        "#,
+        newsletter_issue_id,
+        &recipients
+    )
+    .execute(transaction.as_mut())
+    .await?;
+
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET status = CASE
+            WHEN EXISTS (SELECT 1 FROM issue_delivery_queue WHERE newsletter_issue_id = $1)
+            THEN 'sending'
+            ELSE 'sent'
+        END
+        WHERE newsletter_issue_id = $1;
+        "#,
+        newsletter_issue_id
+    )
+    .execute(transaction.as_mut())
+    .await?;
+
+    Ok(())
+}
+
+/// Result of a single delivery attempt
+enum DeliveryOutcome<'a> {
+    Sent {
+        message_id: Option<String>,
+    },
+    /// The attempt failed, the task goes back to the queue
+    Retrying {
+        error: &'a str,
+    },
+    /// The attempt failed and the retry budget is exhausted
+    Failed {
+        error: &'a str,
+    },
+    Skipped {
+        reason: &'a str,
+    },
+}
+
+/// Records a delivery attempt in `issue_deliveries`, which, unlike the queue,
+/// keeps track of every attempt once the task is done
+async fn log_delivery(
+    transaction: &mut PgTransaction,
+    issue_delivery_id: Uuid,
+    task: &DeliveryTask,
+    subscriber_id: Option<Uuid>,
+    outcome: DeliveryOutcome<'_>,
+) -> Result<(), anyhow::Error> {
+    let (outcome, provider_message_id, error) = match outcome {
+        DeliveryOutcome::Sent { message_id } => ("sent", message_id, None),
+        DeliveryOutcome::Retrying { error } => ("retrying", None, Some(error)),
+        DeliveryOutcome::Failed { error } => ("failed", None, Some(error)),
+        DeliveryOutcome::Skipped { reason } => ("skipped", None, Some(reason)),
+    };
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_deliveries (
+            issue_delivery_id, newsletter_issue_id, subscriber_email, subscriber_id,
+            attempted_at, outcome, provider_message_id, error
+        )
+        VALUES ($1, $2, $3, $4, now(), $5, $6, $7)
+        "#,
+        issue_delivery_id,
+        task.newsletter_issue_id,
+        task.subscriber_email,
+        subscriber_id,
+        outcome,
+        provider_message_id,
+        error
+    )
+    .execute(transaction.as_mut())
+    .await?;
+    Ok(())
+}
+
+async fn reschedule_task(
+    mut transaction: PgTransaction,
+    task: &DeliveryTask,
+    delay: Duration,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE issue_delivery_queue
+        SET
+            n_retries = n_retries + 1,
+            execute_after = now() + make_interval(secs => $3)
+        WHERE newsletter_issue_id = $1 AND subscriber_email = $2;
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email,
+        delay.as_secs_f64()
+    )
+    .execute(transaction.as_mut())
+    .await?;
+
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+async fn dead_letter_task(
+    mut transaction: PgTransaction,
+    task: &DeliveryTask,
+    error: &str,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_dead_letters
+        (newsletter_issue_id, subscriber_email, n_retries, last_error, failed_at)
+        VALUES ($1, $2, $3, $4, now())
+        ON CONFLICT (newsletter_issue_id, subscriber_email) DO UPDATE
+        SET n_retries = EXCLUDED.n_retries,
+            last_error = EXCLUDED.last_error,
+            failed_at = EXCLUDED.failed_at;
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email,
+        task.n_retries,
+        error
+    )
+    .execute(transaction.as_mut())
+    .await?;
+
+    delete_task(transaction, task).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{backoff_delay, rewrite_links, with_html_footer};
+
+    #[test]
+    fn backoff_delay_grows_exponentially() {
+        let base = Duration::from_secs(1);
+        let max = Duration::from_secs(3600);
+        for n_retries in 0..5 {
+            let expected = base * 2u32.pow(n_retries as u32);
+            let delay = backoff_delay(n_retries, base, max);
+            assert!(delay >= expected / 2 && delay <= expected);
+        }
+    }
+
+    #[test]
+    fn backoff_delay_is_capped_at_max() {
+        let base = Duration::from_secs(1);
+        let max = Duration::from_secs(60);
+        for n_retries in [10, 31, 100, i16::MAX] {
+            assert!(backoff_delay(n_retries, base, max) <= max);
+        }
+    }
+
+    #[test]
+    fn backoff_delay_is_zero_with_zero_base() {
+        let delay = backoff_delay(3, Duration::ZERO, Duration::from_secs(60));
+        assert_eq!(delay, Duration::ZERO);
+    }
+
+    #[test]
+    fn html_footer_is_inserted_before_the_end_of_the_body() {
+        let html = "<html><BODY><p>Hello</p></BODY></html>";
+        assert_eq!(
+            with_html_footer(html, "<p>Bye</p>"),
+            "<html><BODY><p>Hello</p><p>Bye</p></BODY></html>"
+        );
+    }
+
+    #[test]
+    fn html_footer_is_appended_to_a_fragment() {
+        assert_eq!(
+            with_html_footer("<p>Hello</p>", "<p>Bye</p>"),
+            "<p>Hello</p><p>Bye</p>"
+        );
+    }
+
+    fn track(url: &str) -> Option<String> {
+        url.starts_with("https://")
+            .then(|| format!("https://t.example/?{}", url))
+    }
+
+    #[test]
+    fn links_are_rewritten() {
+        let html = r#"<p>Read <a href="https://example.com/post?a=1&amp;b=2">this</a>
+            and <A class="x" HREF='https://example.com/more'>that</A></p>"#;
+        assert_eq!(
+            rewrite_links(html, track),
+            r#"<p>Read <a href="https://t.example/?https://example.com/post?a=1&amp;b=2">this</a>
+            and <A class="x" HREF="https://t.example/?https://example.com/more">that</A></p>"#
+        );
+    }
+
+    #[test]
+    fn links_can_be_left_untouched() {
+        let html = r#"<a href="mailto:me@example.com">me</a> <a href = https://example.com>x</a>"#;
+        assert_eq!(
+            rewrite_links(html, track),
+            r#"<a href="mailto:me@example.com">me</a> <a href = "https://t.example/?https://example.com">x</a>"#
+        );
+    }
+
+    #[test]
+    fn only_the_href_of_anchors_is_rewritten() {
+        let html = r#"<abbr href="https://example.com">x</abbr><a data-href="https://example.com">y</a><a name="top">z</a>"#;
+        assert_eq!(rewrite_links(html, track), html);
+    }
+}
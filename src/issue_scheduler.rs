@@ -0,0 +1,77 @@
+use std::time::Duration;
+
+use sqlx::PgPool;
+
+use crate::{
+    configuration::Settings,
+    issue_delivery_worker::{enqueue_delivery_tasks, ExecutionOutcome},
+    startup::get_connection_pool,
+};
+
+pub async fn run_scheduler_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
+    let connection_pool = get_connection_pool(&configuration.database);
+    scheduler_loop(connection_pool).await
+}
+
+async fn scheduler_loop(pool: PgPool) -> Result<(), anyhow::Error> {
+    loop {
+        match try_publish_scheduled_issue(&pool).await {
+            Ok(ExecutionOutcome::EmptyQueue) => {
+                tokio::time::sleep(Duration::from_secs(10)).await;
+            }
+            Err(_) => {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+            Ok(ExecutionOutcome::TaskCompleted) => {}
+        }
+    }
+}
+
+/// Publishes one of the scheduled issues whose time has come, enqueueing its deliveries
+#[tracing::instrument(
+    name = "Publish scheduled issue",
+    skip_all,
+    fields(newsletter_issue_id=tracing::field::Empty),
+    err
+)]
+pub async fn try_publish_scheduled_issue(pool: &PgPool) -> Result<ExecutionOutcome, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+
+    // The row lock makes a concurrent cancellation wait for us (and then find
+    // the issue already sent), or the other way around.
+    let issue = sqlx::query!(
+        r#"
+        SELECT newsletter_issue_id
+        FROM newsletter_issues
+        WHERE status = 'scheduled' AND scheduled_for <= now()
+        ORDER BY scheduled_for
+        FOR UPDATE
+        SKIP LOCKED
+        LIMIT 1
+        "#
+    )
+    .fetch_optional(transaction.as_mut())
+    .await?;
+    let Some(issue) = issue else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+    tracing::Span::current().record(
+        "newsletter_issue_id",
+        tracing::field::display(&issue.newsletter_issue_id),
+    );
+
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET published_at = now()
+        WHERE newsletter_issue_id = $1;
+        "#,
+        issue.newsletter_issue_id
+    )
+    .execute(transaction.as_mut())
+    .await?;
+    enqueue_delivery_tasks(&mut transaction, issue.newsletter_issue_id).await?;
+    transaction.commit().await?;
+
+    Ok(ExecutionOutcome::TaskCompleted)
+}
@@ -0,0 +1,183 @@
+use htmlescape::encode_minimal;
+
+/// Variables an issue can refer to, as `{{ name }}`
+pub const TEMPLATE_VARIABLES: [&str; 3] = ["name", "unsubscribe_url", "archive_url"];
+
+/// Values of the template variables for a single recipient
+pub struct TemplateVariables {
+    pub name: String,
+    pub unsubscribe_url: String,
+    pub archive_url: String,
+}
+
+impl TemplateVariables {
+    fn get(&self, variable: &str) -> Option<&str> {
+        match variable {
+            "name" => Some(&self.name),
+            "unsubscribe_url" => Some(&self.unsubscribe_url),
+            "archive_url" => Some(&self.archive_url),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum ContentFormat {
+    /// Values are HTML-escaped, so that they can't inject markup
+    Html,
+    Text,
+}
+
+enum Segment<'a> {
+    Literal(&'a str),
+    Variable { raw: &'a str, name: &'a str },
+    Unclosed(&'a str),
+}
+
+/// Splits a template into literal text and `{{ variable }}` placeholders
+fn segments(template: &str) -> Vec<Segment<'_>> {
+    let mut segments = vec![];
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        segments.push(Segment::Literal(&rest[..start]));
+        match rest[start..].find("}}") {
+            Some(len) => {
+                let raw = &rest[start..start + len + 2];
+                segments.push(Segment::Variable {
+                    raw,
+                    name: raw[2..raw.len() - 2].trim(),
+                });
+                rest = &rest[start + len + 2..];
+            }
+            None => {
+                segments.push(Segment::Unclosed(&rest[start..]));
+                rest = "";
+            }
+        }
+    }
+    segments.push(Segment::Literal(rest));
+    segments
+}
+
+/// Checks that a template only refers to known variables
+pub fn validate_template(template: &str) -> Result<(), String> {
+    for segment in segments(template) {
+        match segment {
+            Segment::Variable { raw, name } if !TEMPLATE_VARIABLES.contains(&name) => {
+                return Err(format!(
+                    "Unknown template variable \"{}\". Available variables are: {}.",
+                    raw,
+                    TEMPLATE_VARIABLES.join(", ")
+                ));
+            }
+            Segment::Unclosed(_) => {
+                return Err("A template variable is missing its closing \"}}\".".into());
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Checks the title and the contents of an issue, naming the offending part
+pub fn validate_issue(title: &str, text_content: &str, html_content: &str) -> Result<(), String> {
+    for (part, template) in [
+        ("title", title),
+        ("plain text content", text_content),
+        ("HTML content", html_content),
+    ] {
+        validate_template(template).map_err(|e| format!("Invalid {}: {}", part, e))?;
+    }
+    Ok(())
+}
+
+/// Replaces the known variables with their values.
+/// Anything else is left untouched, [`validate_template`] is in charge of rejecting it.
+pub fn render_template(
+    template: &str,
+    variables: &TemplateVariables,
+    format: ContentFormat,
+) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    for segment in segments(template) {
+        match segment {
+            Segment::Literal(s) | Segment::Unclosed(s) => rendered.push_str(s),
+            Segment::Variable { raw, name } => match (variables.get(name), format) {
+                (Some(value), ContentFormat::Html) => rendered.push_str(&encode_minimal(value)),
+                (Some(value), ContentFormat::Text) => rendered.push_str(value),
+                (None, _) => rendered.push_str(raw),
+            },
+        }
+    }
+    rendered
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok};
+
+    use super::{render_template, validate_template, ContentFormat, TemplateVariables};
+
+    fn variables() -> TemplateVariables {
+        TemplateVariables {
+            name: "Ursula <Le Guin>".into(),
+            unsubscribe_url: "https://example.com/unsubscribe?token=abc".into(),
+            archive_url: "https://example.com/issues/1".into(),
+        }
+    }
+
+    #[test]
+    fn known_variables_are_valid() {
+        assert_ok!(validate_template(
+            "Hi {{ name }}! {{archive_url}} {{  unsubscribe_url }}"
+        ));
+    }
+
+    #[test]
+    fn templates_without_variables_are_valid() {
+        assert_ok!(validate_template("<p>Hello { world }</p>"));
+    }
+
+    #[test]
+    fn unknown_variables_are_rejected() {
+        let error = validate_template("Hi {{ nmae }}!").unwrap_err();
+        assert!(error.contains("{{ nmae }}"));
+    }
+
+    #[test]
+    fn unclosed_variables_are_rejected() {
+        assert_err!(validate_template("Hi {{ name"));
+    }
+
+    #[test]
+    fn variables_are_replaced_in_text() {
+        let rendered = render_template(
+            "Hi {{ name }}, read it online: {{archive_url}}",
+            &variables(),
+            ContentFormat::Text,
+        );
+        assert_eq!(
+            rendered,
+            "Hi Ursula <Le Guin>, read it online: https://example.com/issues/1"
+        );
+    }
+
+    #[test]
+    fn variables_are_escaped_in_html() {
+        let rendered = render_template(
+            r#"<p>Hi {{ name }}</p><a href="{{ unsubscribe_url }}">x</a>"#,
+            &variables(),
+            ContentFormat::Html,
+        );
+        assert_eq!(
+            rendered,
+            r#"<p>Hi Ursula &lt;Le Guin&gt;</p><a href="https://example.com/unsubscribe?token=abc">x</a>"#
+        );
+    }
+
+    #[test]
+    fn unknown_variables_are_left_untouched() {
+        let rendered = render_template("{{ nope }} {{ name", &variables(), ContentFormat::Text);
+        assert_eq!(rendered, "{{ nope }} {{ name");
+    }
+}
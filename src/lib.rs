@@ -1,12 +1,22 @@
 pub mod authentication;
 pub mod configuration;
+pub mod confirmation_email_worker;
 pub mod domain;
 pub mod email_client;
 pub mod idempotency;
 pub mod issue_delivery_worker;
+pub mod issue_scheduler;
+pub mod issue_template;
+pub mod mailing_lists;
+pub mod markdown;
 pub mod routes;
+pub mod segments;
 pub mod session_state;
+pub mod signed_token;
 pub mod startup;
+pub mod subscriber_data;
+pub mod subscription_cleanup;
+pub mod suppression_list;
 pub mod telemetry;
 pub mod utils;
 
@@ -0,0 +1,81 @@
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+/// A newsletter published by this instance, with subscribers of its own
+#[derive(Debug, Clone)]
+pub struct MailingList {
+    pub list_id: Uuid,
+    pub name: String,
+    /// Issues are sent from this address instead of the configured sender, if set
+    pub sender_email: Option<String>,
+    pub description: String,
+}
+
+/// All the lists, oldest first: the first one is the default list
+pub async fn get_lists(executor: impl PgExecutor<'_>) -> Result<Vec<MailingList>, sqlx::Error> {
+    sqlx::query_as!(
+        MailingList,
+        r#"
+        SELECT list_id, name, sender_email, description
+        FROM lists
+        ORDER BY created_at, list_id
+        "#
+    )
+    .fetch_all(executor)
+    .await
+}
+
+pub async fn get_list(
+    executor: impl PgExecutor<'_>,
+    list_id: Uuid,
+) -> Result<Option<MailingList>, sqlx::Error> {
+    sqlx::query_as!(
+        MailingList,
+        "SELECT list_id, name, sender_email, description FROM lists WHERE list_id = $1",
+        list_id
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+/// The list people join when they don't pick one, and issues go to unless told otherwise
+pub async fn get_default_list_id(executor: impl PgExecutor<'_>) -> Result<Uuid, sqlx::Error> {
+    let list = sqlx::query!("SELECT list_id FROM lists ORDER BY created_at, list_id LIMIT 1")
+        .fetch_one(executor)
+        .await?;
+    Ok(list.list_id)
+}
+
+/// Adds a subscriber to a list, pending their confirmation.
+/// Confirmed memberships are left untouched.
+pub async fn join_list(
+    executor: impl PgExecutor<'_>,
+    list_id: Uuid,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO list_subscriptions (list_id, subscriber_id, status, subscribed_at)
+        VALUES ($1, $2, 'pending_confirmation', now())
+        ON CONFLICT (list_id, subscriber_id) DO UPDATE
+        SET status = 'pending_confirmation', subscribed_at = now()
+        WHERE list_subscriptions.status <> 'confirmed'
+        "#,
+        list_id,
+        subscriber_id
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// The chosen list if it exists, the default list if none was chosen
+pub async fn resolve_list(
+    pool: &PgPool,
+    list_id: Option<Uuid>,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    match list_id {
+        Some(list_id) => Ok(get_list(pool, list_id).await?.map(|l| l.list_id)),
+        None => Ok(Some(get_default_list_id(pool).await?)),
+    }
+}
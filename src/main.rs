@@ -2,8 +2,11 @@ use std::fmt::Display;
 
 use tokio::task::JoinError;
 use zero2prod::configuration::get_configuration;
+use zero2prod::confirmation_email_worker::run_confirmation_worker_until_stopped;
 use zero2prod::issue_delivery_worker::run_worker_until_stopped;
+use zero2prod::issue_scheduler::run_scheduler_until_stopped;
 use zero2prod::startup::Application;
+use zero2prod::subscription_cleanup::run_cleanup_until_stopped;
 use zero2prod::telemetry::{get_subscriber, init_subscriber};
 
 #[tokio::main]
@@ -16,11 +19,18 @@ async fn main() -> Result<(), anyhow::Error> {
     let application = Application::build(configuration.clone()).await?;
 
     let application_task = tokio::spawn(application.run_until_stopped());
-    let worker_task = tokio::spawn(run_worker_until_stopped(configuration));
+    let worker_task = tokio::spawn(run_worker_until_stopped(configuration.clone()));
+    let confirmation_task =
+        tokio::spawn(run_confirmation_worker_until_stopped(configuration.clone()));
+    let scheduler_task = tokio::spawn(run_scheduler_until_stopped(configuration.clone()));
+    let cleanup_task = tokio::spawn(run_cleanup_until_stopped(configuration));
 
     tokio::select! {
     o = application_task => report_exit("API", o),
     o = worker_task => report_exit("Background worker", o),
+    o = confirmation_task => report_exit("Confirmation email worker", o),
+    o = scheduler_task => report_exit("Issue scheduler", o),
+    o = cleanup_task => report_exit("Subscription cleanup", o),
     };
 
     Ok(())
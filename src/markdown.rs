@@ -0,0 +1,307 @@
+use pulldown_cmark::{Event, HeadingLevel, Parser, Tag, TagEnd};
+
+/// Bodies of an issue as submitted by its author
+pub struct IssueContents {
+    pub text_content: String,
+    pub html_content: String,
+    pub markdown_content: Option<String>,
+}
+
+impl IssueContents {
+    /// The Markdown source, when given, takes precedence over the hand-written bodies
+    pub fn from_form(text_content: String, html_content: String, markdown_content: String) -> Self {
+        if markdown_content.trim().is_empty() {
+            return Self {
+                text_content,
+                html_content,
+                markdown_content: None,
+            };
+        }
+        Self {
+            text_content: markdown_to_text(&markdown_content),
+            html_content: markdown_to_html(&markdown_content),
+            markdown_content: Some(markdown_content),
+        }
+    }
+}
+
+/// Renders Markdown to HTML, dropping anything unsafe (scripts, event handlers, ...)
+/// the author may have embedded
+pub fn markdown_to_html(source: &str) -> String {
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, Parser::new(source));
+    restore_template_variables(&ammonia::clean(&html))
+}
+
+/// Template variables used as link targets (e.g. `[Leave]({{unsubscribe_url}})`)
+/// come out of the renderer percent-encoded: turn them back into variables.
+fn restore_template_variables(html: &str) -> String {
+    let mut restored = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find("%7B%7B") {
+        let Some(len) = rest[start..].find("%7D%7D") else {
+            break;
+        };
+        restored.push_str(&rest[..start]);
+        restored.push_str("{{");
+        restored.push_str(&rest[start + 6..start + len].replace("%20", " "));
+        restored.push_str("}}");
+        rest = &rest[start + len + 6..];
+    }
+    restored.push_str(rest);
+    restored
+}
+
+/// Renders Markdown to a plain-text email body: headings are underlined,
+/// links become numbered footnotes and raw HTML is left out.
+pub fn markdown_to_text(source: &str) -> String {
+    let mut writer = PlainTextWriter::default();
+    for event in Parser::new(source) {
+        writer.handle(event);
+    }
+    writer.finish()
+}
+
+#[derive(Default)]
+struct PlainTextWriter {
+    output: String,
+    /// Inline content of the block being rendered
+    block: String,
+    /// Whether the next block must be separated from the previous one by an empty line
+    needs_blank_line: bool,
+    /// Next number of each open list, `None` for bullet lists
+    lists: Vec<Option<u64>>,
+    /// Marker of the list item the next block starts
+    bullet: Option<String>,
+    quote_depth: usize,
+    in_code_block: bool,
+    /// Where the text of each open link starts in `block`, and its destination
+    open_links: Vec<(usize, String)>,
+    footnotes: Vec<String>,
+}
+
+impl PlainTextWriter {
+    fn handle(&mut self, event: Event) {
+        match event {
+            Event::Start(Tag::List(start)) => {
+                self.flush_block();
+                self.lists.push(start);
+            }
+            Event::End(TagEnd::List(_)) => {
+                self.flush_block();
+                self.lists.pop();
+                if self.lists.is_empty() {
+                    self.needs_blank_line = true;
+                }
+            }
+            Event::Start(Tag::Item) => {
+                self.flush_block();
+                self.bullet = match self.lists.last_mut() {
+                    Some(Some(n)) => {
+                        *n += 1;
+                        Some(format!("{}. ", *n - 1))
+                    }
+                    _ => Some("- ".into()),
+                };
+            }
+            Event::End(TagEnd::Item) => self.flush_block(),
+            Event::End(TagEnd::Paragraph) => {
+                self.flush_block();
+                if self.lists.is_empty() {
+                    self.needs_blank_line = true;
+                }
+            }
+            Event::End(TagEnd::Heading(level)) => {
+                let underline = if level == HeadingLevel::H1 { '=' } else { '-' };
+                let width = self.block.trim().chars().count();
+                self.block = format!(
+                    "{}\n{}",
+                    self.block.trim(),
+                    underline.to_string().repeat(width)
+                );
+                self.flush_block();
+                self.needs_blank_line = true;
+            }
+            Event::Start(Tag::BlockQuote(_)) => {
+                self.flush_block();
+                self.quote_depth += 1;
+            }
+            Event::End(TagEnd::BlockQuote(_)) => {
+                self.flush_block();
+                self.quote_depth -= 1;
+                self.needs_blank_line = true;
+            }
+            Event::Start(Tag::CodeBlock(_)) => {
+                self.flush_block();
+                self.in_code_block = true;
+            }
+            Event::End(TagEnd::CodeBlock) => {
+                let code = self.block.trim_end().lines().map(|l| format!("    {}", l));
+                self.block = code.collect::<Vec<_>>().join("\n");
+                self.flush_block();
+                self.in_code_block = false;
+                self.needs_blank_line = true;
+            }
+            Event::Start(Tag::Link { dest_url, .. })
+            | Event::Start(Tag::Image { dest_url, .. }) => {
+                self.open_links
+                    .push((self.block.len(), dest_url.to_string()));
+            }
+            Event::End(TagEnd::Link) | Event::End(TagEnd::Image) => {
+                if let Some((start, url)) = self.open_links.pop() {
+                    // Autolinks already show their destination
+                    if self.block[start..] != url {
+                        self.footnotes.push(url);
+                        self.block.push_str(&format!(" [{}]", self.footnotes.len()));
+                    }
+                }
+            }
+            Event::Text(text) | Event::Code(text) => self.block.push_str(&text),
+            Event::SoftBreak | Event::HardBreak => self.block.push('\n'),
+            Event::Rule => {
+                self.flush_block();
+                self.block = "-".repeat(20);
+                self.flush_block();
+                self.needs_blank_line = true;
+            }
+            _ => {}
+        }
+    }
+
+    /// Writes the current block to the output, indented and prefixed
+    /// according to the enclosing lists and block quotes.
+    fn flush_block(&mut self) {
+        let block = std::mem::take(&mut self.block);
+        if block.trim().is_empty() {
+            return;
+        }
+        if self.needs_blank_line && !self.output.is_empty() {
+            self.output.push_str(">".repeat(self.quote_depth).as_str());
+            self.output.push('\n');
+        }
+        self.needs_blank_line = false;
+
+        let indent = "   ".repeat(self.lists.len().saturating_sub(1));
+        let continuation = if self.lists.is_empty() { "" } else { "   " };
+        let block = if self.in_code_block {
+            block.as_str()
+        } else {
+            block.trim()
+        };
+        for (i, line) in block.lines().enumerate() {
+            self.output.push_str(&"> ".repeat(self.quote_depth));
+            self.output.push_str(&indent);
+            match (i, self.bullet.take()) {
+                (0, Some(bullet)) => self.output.push_str(&bullet),
+                (0, None) => {}
+                _ => self.output.push_str(continuation),
+            }
+            self.output.push_str(line);
+            self.output.push('\n');
+        }
+    }
+
+    fn finish(mut self) -> String {
+        self.flush_block();
+        let mut text = self.output.trim_end().to_owned();
+        if !self.footnotes.is_empty() {
+            text.push_str("\n\n");
+            for (i, url) in self.footnotes.iter().enumerate() {
+                text.push_str(&format!("[{}] {}\n", i + 1, url));
+            }
+            text.truncate(text.trim_end().len());
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{markdown_to_html, markdown_to_text, IssueContents};
+
+    #[test]
+    fn headings_are_underlined() {
+        let text = markdown_to_text("# Title\n\n## Section\n\nSome text.");
+        assert_eq!(text, "Title\n=====\n\nSection\n-------\n\nSome text.");
+    }
+
+    #[test]
+    fn links_become_footnotes() {
+        let text = markdown_to_text(
+            "Read [the post](https://example.com/post) and [more](https://example.com/more).",
+        );
+        assert_eq!(
+            text,
+            "Read the post [1] and more [2].\n\n\
+            [1] https://example.com/post\n\
+            [2] https://example.com/more"
+        );
+    }
+
+    #[test]
+    fn autolinks_are_not_repeated_as_footnotes() {
+        let text = markdown_to_text("See <https://example.com>");
+        assert_eq!(text, "See https://example.com");
+    }
+
+    #[test]
+    fn lists_keep_their_markers() {
+        let text = markdown_to_text("Items:\n\n- one\n- two\n\n1. first\n2. second\n\nEnd");
+        assert_eq!(text, "Items:\n\n- one\n- two\n\n1. first\n2. second\n\nEnd");
+    }
+
+    #[test]
+    fn nested_lists_are_indented() {
+        let text = markdown_to_text("- one\n  - inner\n- two");
+        assert_eq!(text, "- one\n   - inner\n- two");
+    }
+
+    #[test]
+    fn quotes_and_code_blocks_are_preserved() {
+        let text = markdown_to_text("> quoted\n\n```\nlet x = 1;\n```");
+        assert_eq!(text, "> quoted\n\n    let x = 1;");
+    }
+
+    #[test]
+    fn raw_html_is_left_out_of_the_text() {
+        let text = markdown_to_text("Hello <b>world</b>\n\n<div>block</div>");
+        assert_eq!(text, "Hello world");
+    }
+
+    #[test]
+    fn html_is_sanitised() {
+        let html = markdown_to_html(
+            "Hi <script>alert(1)</script><a href=\"#\" onclick=\"steal()\">there</a>",
+        );
+        assert!(!html.contains("script"));
+        assert!(!html.contains("onclick"));
+        assert!(html.contains("there</a>"));
+    }
+
+    #[test]
+    fn template_variables_survive_as_link_targets() {
+        let html = markdown_to_html(
+            "Hi {{ name }}, [leave]({{unsubscribe_url}}) or [read](<{{ archive_url }}>)",
+        );
+        assert!(html.contains("Hi {{ name }}"));
+        assert!(html.contains(r#"href="{{unsubscribe_url}}""#));
+        assert!(html.contains(r#"href="{{ archive_url }}""#));
+    }
+
+    #[test]
+    fn markdown_takes_precedence_over_the_other_bodies() {
+        let contents =
+            IssueContents::from_form("text".into(), "<p>html</p>".into(), "**markdown**".into());
+        assert_eq!(contents.text_content, "markdown");
+        assert_eq!(contents.html_content, "<p><strong>markdown</strong></p>\n");
+        assert_eq!(contents.markdown_content.as_deref(), Some("**markdown**"));
+    }
+
+    #[test]
+    fn hand_written_bodies_are_kept_without_markdown() {
+        let contents = IssueContents::from_form("text".into(), "<p>html</p>".into(), "  ".into());
+        assert_eq!(contents.text_content, "text");
+        assert_eq!(contents.html_content, "<p>html</p>");
+        assert!(contents.markdown_content.is_none());
+    }
+}
@@ -0,0 +1,82 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::{encode_attribute, encode_minimal};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::utils::e500;
+
+struct DeadLetter {
+    newsletter_issue_id: Uuid,
+    title: String,
+    subscriber_email: String,
+    n_retries: i16,
+    last_error: String,
+    failed_at: DateTime<Utc>,
+}
+
+pub async fn failed_deliveries(
+    pool: web::Data<PgPool>,
+    messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let mut rows = String::new();
+    for d in get_dead_letters(&pool).await.map_err(e500)? {
+        writeln!(
+            rows,
+            r#"<tr>
+                <td>{title}</td>
+                <td>{email}</td>
+                <td>{n_retries}</td>
+                <td>{error}</td>
+                <td>{failed_at}</td>
+                <td>
+                    <form action="/admin/deliveries/failed/requeue" method="post">
+                        <input type="hidden" name="newsletter_issue_id" value="{issue_id}">
+                        <input type="hidden" name="subscriber_email" value="{email_attr}">
+                        <button type="submit">Requeue</button>
+                    </form>
+                </td>
+            </tr>"#,
+            title = encode_minimal(&d.title),
+            email = encode_minimal(&d.subscriber_email),
+            email_attr = encode_attribute(&d.subscriber_email),
+            n_retries = d.n_retries,
+            error = encode_minimal(&d.last_error),
+            failed_at = d.failed_at.to_rfc3339(),
+            issue_id = d.newsletter_issue_id,
+        )
+        .unwrap();
+    }
+
+    let body = include_str!("./failed_deliveries.html")
+        .replace("{messages}", &msg_html)
+        .replace("{rows}", &rows);
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
+
+async fn get_dead_letters(pool: &PgPool) -> Result<Vec<DeadLetter>, anyhow::Error> {
+    let dead_letters = sqlx::query_as!(
+        DeadLetter,
+        r#"
+        SELECT d.newsletter_issue_id, i.title, d.subscriber_email, d.n_retries, d.last_error, d.failed_at
+        FROM issue_delivery_dead_letters d
+        JOIN newsletter_issues i USING (newsletter_issue_id)
+        ORDER BY d.failed_at DESC
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the failed deliveries")?;
+    Ok(dead_letters)
+}
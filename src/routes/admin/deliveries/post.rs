@@ -0,0 +1,72 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::utils::{e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    newsletter_issue_id: Uuid,
+    subscriber_email: String,
+}
+
+#[tracing::instrument(name = "Requeue a failed delivery", skip(form, pool))]
+pub async fn requeue_failed_delivery(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let n_requeued = requeue(
+        &pool,
+        Some((form.newsletter_issue_id, &form.subscriber_email)),
+    )
+    .await
+    .context("Failed to requeue the delivery")
+    .map_err(e500)?;
+
+    if n_requeued == 0 {
+        FlashMessage::error("The delivery was not found among the failed ones.").send();
+    } else {
+        FlashMessage::info("The delivery has been requeued.").send();
+    }
+    Ok(see_other("/admin/deliveries/failed"))
+}
+
+#[tracing::instrument(name = "Requeue all failed deliveries", skip(pool))]
+pub async fn requeue_all_failed_deliveries(
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let n_requeued = requeue(&pool, None)
+        .await
+        .context("Failed to requeue the deliveries")
+        .map_err(e500)?;
+
+    FlashMessage::info(format!("{} deliveries have been requeued.", n_requeued)).send();
+    Ok(see_other("/admin/deliveries/failed"))
+}
+
+/// Moves dead letters back into the delivery queue with a fresh retry budget.
+/// Only the given delivery is moved if `task` is set, all of them otherwise.
+async fn requeue(pool: &PgPool, task: Option<(Uuid, &str)>) -> Result<u64, sqlx::Error> {
+    let (issue_id, subscriber_email) = task.unzip();
+    let requeued = sqlx::query!(
+        r#"
+        WITH requeued AS (
+            DELETE FROM issue_delivery_dead_letters
+            WHERE ($1::uuid IS NULL OR newsletter_issue_id = $1)
+                AND ($2::text IS NULL OR subscriber_email = $2)
+            RETURNING newsletter_issue_id, subscriber_email
+        )
+        INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email)
+        SELECT newsletter_issue_id, subscriber_email FROM requeued
+        ON CONFLICT DO NOTHING;
+        "#,
+        issue_id,
+        subscriber_email
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+    Ok(requeued)
+}
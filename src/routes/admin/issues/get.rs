@@ -0,0 +1,484 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::{encode_attribute, encode_minimal};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::{
+    issue_delivery_worker::template_variables,
+    issue_template::{render_template, validate_issue, ContentFormat},
+    mailing_lists::get_lists,
+    routes::admin::{lists::list_options, segments::segment_options},
+    segments::{get_recipients, get_segment, get_segments},
+    signed_token::TokenSigner,
+    startup::ApplicationBaseUrl,
+    utils::{e500, see_other},
+};
+
+struct IssueSummary {
+    newsletter_issue_id: Uuid,
+    list_name: String,
+    title: String,
+    status: String,
+    published_at: Option<DateTime<Utc>>,
+    scheduled_for: Option<DateTime<Utc>>,
+    subscribers_only: bool,
+}
+
+pub async fn list_issues(
+    pool: web::Data<PgPool>,
+    messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let issues = sqlx::query_as!(
+        IssueSummary,
+        r#"
+        SELECT i.newsletter_issue_id, l.name as list_name, i.title, i.status, i.published_at,
+            i.scheduled_for, i.subscribers_only
+        FROM newsletter_issues i
+        JOIN lists l ON l.list_id = i.list_id
+        ORDER BY i.published_at DESC NULLS FIRST, i.scheduled_for NULLS FIRST, i.title
+        "#
+    )
+    .fetch_all(pool.as_ref())
+    .await
+    .context("Failed to retrieve the newsletter issues")
+    .map_err(e500)?;
+
+    let mut rows = String::new();
+    for i in issues {
+        let actions = match i.status.as_str() {
+            "draft" => format!(
+                r#"<a href="/admin/issues/{issue_id}/edit">Edit</a>
+                    <form action="/admin/issues/{issue_id}/publish" method="post">
+                        <input type="hidden" name="idempotency_key" value="{idempotency_key}">
+                        <button type="submit">Publish</button>
+                    </form>
+                    {schedule_form}
+                    <form action="/admin/issues/{issue_id}/delete" method="post">
+                        <button type="submit">Delete</button>
+                    </form>"#,
+                issue_id = i.newsletter_issue_id,
+                idempotency_key = Uuid::new_v4(),
+                schedule_form = schedule_form(i.newsletter_issue_id, None),
+            ),
+            "scheduled" => format!(
+                r#"<a href="/admin/issues/{issue_id}/edit">Edit</a>
+                    {schedule_form}
+                    <form action="/admin/issues/{issue_id}/cancel_schedule" method="post">
+                        <button type="submit">Cancel schedule</button>
+                    </form>"#,
+                issue_id = i.newsletter_issue_id,
+                schedule_form = schedule_form(i.newsletter_issue_id, i.scheduled_for),
+            ),
+            _ => format!(
+                r#"<a href="/admin/issues/{}">Deliveries</a>"#,
+                i.newsletter_issue_id
+            ),
+        };
+        let (visibility, toggle) = if i.subscribers_only {
+            ("Subscribers only", "Show in archive")
+        } else {
+            ("Public", "Subscribers only")
+        };
+        let actions = format!(
+            r#"<a href="/admin/issues/{issue_id}/preview">Preview</a>
+                    <form action="/admin/issues/{issue_id}/visibility" method="post">
+                        <input type="hidden" name="subscribers_only" value="{subscribers_only}">
+                        <button type="submit">{toggle}</button>
+                    </form>
+                    {actions}"#,
+            issue_id = i.newsletter_issue_id,
+            subscribers_only = !i.subscribers_only,
+        );
+        writeln!(
+            rows,
+            r#"<tr>
+                <td>{title}</td>
+                <td>{list_name}</td>
+                <td>{status}</td>
+                <td>{published_at}</td>
+                <td>{scheduled_for}</td>
+                <td>{visibility}</td>
+                <td>
+                    {actions}
+                </td>
+            </tr>"#,
+            title = encode_minimal(&i.title),
+            list_name = encode_minimal(&i.list_name),
+            status = i.status,
+            published_at = i
+                .published_at
+                .map(|t| t.to_rfc3339())
+                .unwrap_or_else(|| "-".into()),
+            scheduled_for = i
+                .scheduled_for
+                .map(|t| t.to_rfc3339())
+                .unwrap_or_else(|| "-".into()),
+        )
+        .unwrap();
+    }
+
+    let body = include_str!("./issues.html")
+        .replace("{messages}", &msg_html)
+        .replace("{rows}", &rows);
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
+
+/// Form picking the (local) time an issue goes out at, prefilled with
+/// the current schedule if there is one
+fn schedule_form(newsletter_issue_id: Uuid, scheduled_for: Option<DateTime<Utc>>) -> String {
+    let (button, value) = match scheduled_for {
+        Some(t) => ("Reschedule", t.format("%Y-%m-%dT%H:%M").to_string()),
+        None => ("Schedule", String::new()),
+    };
+    format!(
+        r#"<form action="/admin/issues/{newsletter_issue_id}/schedule" method="post">
+                        <input type="datetime-local" name="scheduled_for" value="{value}" required>
+                        <input type="text" name="timezone" value="UTC" placeholder="e.g. Europe/Rome" required>
+                        <button type="submit">{button}</button>
+                    </form>"#
+    )
+}
+
+struct Draft {
+    list_id: Uuid,
+    segment_id: Option<Uuid>,
+    title: String,
+    text_content: String,
+    html_content: String,
+    markdown_content: Option<String>,
+    subscribers_only: bool,
+    track_opens: bool,
+}
+
+pub async fn edit_draft_form(
+    newsletter_issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    let draft = sqlx::query_as!(
+        Draft,
+        r#"
+        SELECT list_id, segment_id, title, text_content, html_content, markdown_content,
+            subscribers_only, track_opens
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1 AND status IN ('draft', 'scheduled')
+        "#,
+        newsletter_issue_id
+    )
+    .fetch_optional(pool.as_ref())
+    .await
+    .context("Failed to retrieve the draft")
+    .map_err(e500)?;
+
+    let Some(draft) = draft else {
+        FlashMessage::error("The draft was not found, it may have been published already.").send();
+        return Ok(see_other("/admin/issues"));
+    };
+
+    let mut msg_html = String::new();
+    for m in messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let lists = get_lists(pool.as_ref())
+        .await
+        .context("Failed to retrieve the mailing lists")
+        .map_err(e500)?;
+    let segments = get_segments(pool.as_ref())
+        .await
+        .context("Failed to retrieve the segments")
+        .map_err(e500)?;
+
+    let body = include_str!("./edit_draft.html")
+        .replace("{messages}", &msg_html)
+        .replace("{list_options}", &list_options(&lists, Some(draft.list_id)))
+        .replace(
+            "{segment_options}",
+            &segment_options(&segments, draft.segment_id),
+        )
+        .replace("{newsletter_issue_id}", &newsletter_issue_id.to_string())
+        .replace("{title}", &encode_attribute(&draft.title))
+        .replace(
+            "{subscribers_only}",
+            if draft.subscribers_only {
+                " checked"
+            } else {
+                ""
+            },
+        )
+        .replace(
+            "{track_opens}",
+            if draft.track_opens { " checked" } else { "" },
+        )
+        .replace(
+            "{markdown_content}",
+            &encode_minimal(draft.markdown_content.as_deref().unwrap_or_default()),
+        )
+        .replace("{text_content}", &encode_minimal(&draft.text_content))
+        .replace("{html_content}", &encode_minimal(&draft.html_content));
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
+
+struct Recipient {
+    id: Uuid,
+    name: String,
+    email: String,
+}
+
+/// Renders an issue as it would be received by a sample subscriber
+pub async fn preview_issue(
+    newsletter_issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    token_signer: web::Data<TokenSigner>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    let issue = sqlx::query_as!(
+        Draft,
+        r#"
+        SELECT list_id, segment_id, title, text_content, html_content, markdown_content,
+            subscribers_only, track_opens
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id
+    )
+    .fetch_optional(pool.as_ref())
+    .await
+    .context("Failed to retrieve the issue")
+    .map_err(e500)?;
+    let Some(issue) = issue else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+
+    // The longest standing confirmed subscriber of the list,
+    // or a made-up one if there is none yet
+    let recipient = sqlx::query_as!(
+        Recipient,
+        r#"
+        SELECT s.id, s.name, s.email
+        FROM subscriptions s
+        JOIN list_subscriptions ls ON ls.subscriber_id = s.id
+        WHERE s.status = 'confirmed' AND ls.status = 'confirmed' AND ls.list_id = $1
+        ORDER BY s.subscribed_at
+        LIMIT 1
+        "#,
+        issue.list_id
+    )
+    .fetch_optional(pool.as_ref())
+    .await
+    .context("Failed to retrieve a sample subscriber")
+    .map_err(e500)?
+    .unwrap_or_else(|| Recipient {
+        id: Uuid::nil(),
+        name: "Jane Doe".into(),
+        email: "jane.doe@example.com".into(),
+    });
+
+    let segment = match issue.segment_id {
+        Some(segment_id) => get_segment(pool.as_ref(), segment_id)
+            .await
+            .context("Failed to retrieve the segment of the issue")
+            .map_err(e500)?,
+        None => None,
+    };
+    let recipients = get_recipients(pool.as_ref(), issue.list_id, segment.as_ref())
+        .await
+        .context("Failed to retrieve the recipients of the issue")
+        .map_err(e500)?;
+    let audience = match &segment {
+        Some(segment) => format!(
+            "{} confirmed subscribers of the list in the {} segment",
+            recipients.len(),
+            encode_minimal(&segment.name)
+        ),
+        None => format!("{} confirmed subscribers of the list", recipients.len()),
+    };
+
+    let errors = match validate_issue(&issue.title, &issue.text_content, &issue.html_content) {
+        Ok(()) => String::new(),
+        Err(e) => format!("<p><i>{}</i></p>", encode_minimal(&e)),
+    };
+    let variables = template_variables(
+        &base_url.0,
+        &token_signer,
+        newsletter_issue_id,
+        recipient.id,
+        recipient.name,
+    );
+    let title = render_template(&issue.title, &variables, ContentFormat::Text);
+    let html_content = render_template(&issue.html_content, &variables, ContentFormat::Html);
+    let text_content = render_template(&issue.text_content, &variables, ContentFormat::Text);
+
+    let body = include_str!("./preview.html")
+        .replace("{errors}", &errors)
+        .replace("{audience}", &audience)
+        .replace("{name}", &encode_minimal(&variables.name))
+        .replace("{email}", &encode_minimal(&recipient.email))
+        .replace("{text_content}", &encode_minimal(&text_content))
+        .replace("{html_content}", &encode_attribute(&html_content))
+        .replace("{title}", &encode_minimal(&title));
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
+
+struct IssueStatus {
+    title: String,
+    status: String,
+    track_opens: bool,
+}
+
+struct DeliveryStats {
+    queued: i64,
+    sent: i64,
+    failed: i64,
+    skipped: i64,
+}
+
+/// Where the deliveries of an issue stand
+pub async fn issue_stats(
+    newsletter_issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    let issue = sqlx::query_as!(
+        IssueStatus,
+        "SELECT title, status, track_opens FROM newsletter_issues WHERE newsletter_issue_id = $1",
+        newsletter_issue_id
+    )
+    .fetch_optional(pool.as_ref())
+    .await
+    .context("Failed to retrieve the issue")
+    .map_err(e500)?;
+    let Some(issue) = issue else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+
+    // Subscribers still in the queue are counted as queued whatever happened before
+    // (e.g. a failed delivery that has been requeued), the others by their latest attempt.
+    let stats = sqlx::query_as!(
+        DeliveryStats,
+        r#"
+        SELECT
+            (SELECT COUNT(*) FROM issue_delivery_queue WHERE newsletter_issue_id = $1)
+                as "queued!",
+            COUNT(*) FILTER (WHERE outcome = 'sent') as "sent!",
+            COUNT(*) FILTER (WHERE outcome = 'failed') as "failed!",
+            COUNT(*) FILTER (WHERE outcome = 'skipped') as "skipped!"
+        FROM (
+            SELECT DISTINCT ON (subscriber_email) outcome
+            FROM issue_deliveries d
+            WHERE newsletter_issue_id = $1
+                AND NOT EXISTS (
+                    SELECT 1 FROM issue_delivery_queue q
+                    WHERE q.newsletter_issue_id = $1 AND q.subscriber_email = d.subscriber_email
+                )
+            ORDER BY subscriber_email, attempted_at DESC
+        ) latest_attempts
+        "#,
+        newsletter_issue_id
+    )
+    .fetch_one(pool.as_ref())
+    .await
+    .context("Failed to compute the delivery statistics")
+    .map_err(e500)?;
+
+    let opens = if issue.track_opens {
+        let opens = sqlx::query!(
+            r#"
+            SELECT COUNT(DISTINCT issue_delivery_id) as "unique!", COUNT(*) as "total!"
+            FROM issue_opens
+            WHERE newsletter_issue_id = $1
+            "#,
+            newsletter_issue_id
+        )
+        .fetch_one(pool.as_ref())
+        .await
+        .context("Failed to count the opens")
+        .map_err(e500)?;
+        format!("{} unique, {} total", opens.unique, opens.total)
+    } else {
+        "Not tracked".to_owned()
+    };
+
+    let top_links = sqlx::query!(
+        r#"
+        SELECT url, COUNT(DISTINCT issue_delivery_id) as "unique!", COUNT(*) as "total!"
+        FROM issue_clicks
+        WHERE newsletter_issue_id = $1
+        GROUP BY url
+        ORDER BY 2 DESC, 3 DESC, url
+        LIMIT 10
+        "#,
+        newsletter_issue_id
+    )
+    .fetch_all(pool.as_ref())
+    .await
+    .context("Failed to retrieve the most clicked links")
+    .map_err(e500)?;
+    let mut link_rows = String::new();
+    for l in top_links {
+        writeln!(
+            link_rows,
+            "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+            encode_minimal(&l.url),
+            l.unique,
+            l.total
+        )
+        .unwrap();
+    }
+    if link_rows.is_empty() {
+        link_rows.push_str(r#"<tr><td colspan="3">No clicks yet.</td></tr>"#);
+    }
+
+    let total = stats.queued + stats.sent + stats.failed + stats.skipped;
+    let done = total - stats.queued;
+    let (progress, refresh) = match issue.status.as_str() {
+        "draft" | "scheduled" => (
+            "<p>This issue has not been published yet.</p>".to_owned(),
+            "",
+        ),
+        "sending" => (
+            format!(
+                r#"<p><progress value="{done}" max="{total}">{percent}%</progress> {done} of {total} deliveries done</p>"#,
+                percent = done * 100 / total.max(1),
+            ),
+            // Keep the page up to date while the worker goes through the queue
+            r#"<meta http-equiv="refresh" content="5">"#,
+        ),
+        _ => (String::new(), ""),
+    };
+
+    let body = include_str!("./issue_stats.html")
+        .replace("{refresh}", refresh)
+        .replace("{status}", &issue.status)
+        .replace("{progress}", &progress)
+        .replace("{queued}", &stats.queued.to_string())
+        .replace("{sent}", &stats.sent.to_string())
+        .replace("{failed}", &stats.failed.to_string())
+        .replace("{skipped}", &stats.skipped.to_string())
+        .replace("{opens}", &opens)
+        .replace("{top_links}", &link_rows)
+        .replace("{title}", &encode_minimal(&issue.title));
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
@@ -0,0 +1,8 @@
+mod get;
+mod post;
+
+pub use get::{edit_draft_form, issue_stats, list_issues, preview_issue};
+pub use post::{
+    cancel_schedule, delete_draft, publish_draft, save_draft, schedule_issue, set_issue_visibility,
+    update_draft,
+};
@@ -0,0 +1,466 @@
+use actix_web::{
+    web::{self, ReqData},
+    HttpResponse,
+};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use chrono::Utc;
+use htmlescape::encode_minimal;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    authentication::UserId,
+    domain::ScheduledTime,
+    idempotency::{save_response, try_processing, IdempotencyKey, NextAction},
+    issue_delivery_worker::enqueue_delivery_tasks,
+    issue_template::validate_issue,
+    mailing_lists::resolve_list,
+    markdown::IssueContents,
+    segments::is_valid_segment,
+    utils::{e400, e500, empty_as_none, see_other},
+};
+
+#[derive(serde::Deserialize)]
+pub struct DraftData {
+    /// The default list when not given
+    list_id: Option<Uuid>,
+    /// The whole list when not given
+    #[serde(default, deserialize_with = "empty_as_none")]
+    segment_id: Option<Uuid>,
+    title: String,
+    #[serde(default)]
+    html_content: String,
+    #[serde(default)]
+    text_content: String,
+    #[serde(default)]
+    markdown_content: String,
+    #[serde(default)]
+    subscribers_only: bool,
+    #[serde(default)]
+    track_opens: bool,
+}
+
+struct IssueOptions {
+    list_id: Option<Uuid>,
+    segment_id: Option<Uuid>,
+    subscribers_only: bool,
+    track_opens: bool,
+}
+
+impl DraftData {
+    fn contents(self) -> (String, IssueContents, IssueOptions) {
+        let contents =
+            IssueContents::from_form(self.text_content, self.html_content, self.markdown_content);
+        let options = IssueOptions {
+            list_id: self.list_id,
+            segment_id: self.segment_id,
+            subscribers_only: self.subscribers_only,
+            track_opens: self.track_opens,
+        };
+        (self.title, contents, options)
+    }
+}
+
+#[tracing::instrument(name = "Saving a newsletter draft", skip(form, pool))]
+pub async fn save_draft(
+    form: web::Form<DraftData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (title, contents, options) = form.0.contents();
+    let Some(list_id) = resolve_list(&pool, options.list_id)
+        .await
+        .context("Failed to retrieve the list of the draft")
+        .map_err(e500)?
+    else {
+        unknown_list_message().send();
+        return Ok(see_other("/admin/newsletters"));
+    };
+    if !is_valid_segment(pool.as_ref(), options.segment_id)
+        .await
+        .context("Failed to retrieve the segment of the draft")
+        .map_err(e500)?
+    {
+        unknown_segment_message().send();
+        return Ok(see_other("/admin/newsletters"));
+    }
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues (
+            newsletter_issue_id, list_id, segment_id, title, text_content, html_content,
+            markdown_content, subscribers_only, track_opens, status
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, 'draft');
+        "#,
+        Uuid::new_v4(),
+        list_id,
+        options.segment_id,
+        title,
+        contents.text_content,
+        contents.html_content,
+        contents.markdown_content,
+        options.subscribers_only,
+        options.track_opens
+    )
+    .execute(pool.as_ref())
+    .await
+    .context("Failed to store the draft")
+    .map_err(e500)?;
+
+    FlashMessage::info("The draft has been saved.").send();
+    Ok(see_other("/admin/issues"))
+}
+
+#[tracing::instrument(name = "Updating a newsletter draft", skip(form, pool))]
+pub async fn update_draft(
+    newsletter_issue_id: web::Path<Uuid>,
+    form: web::Form<DraftData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let (title, contents, options) = form.0.contents();
+    let Some(list_id) = resolve_list(&pool, options.list_id)
+        .await
+        .context("Failed to retrieve the list of the draft")
+        .map_err(e500)?
+    else {
+        unknown_list_message().send();
+        return Ok(see_other(&format!(
+            "/admin/issues/{}/edit",
+            newsletter_issue_id
+        )));
+    };
+    if !is_valid_segment(pool.as_ref(), options.segment_id)
+        .await
+        .context("Failed to retrieve the segment of the draft")
+        .map_err(e500)?
+    {
+        unknown_segment_message().send();
+        return Ok(see_other(&format!(
+            "/admin/issues/{}/edit",
+            newsletter_issue_id
+        )));
+    }
+    let updated = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET list_id = $2, segment_id = $3, title = $4, text_content = $5, html_content = $6,
+            markdown_content = $7, subscribers_only = $8, track_opens = $9
+        WHERE newsletter_issue_id = $1 AND status IN ('draft', 'scheduled')
+        RETURNING status;
+        "#,
+        newsletter_issue_id,
+        list_id,
+        options.segment_id,
+        title,
+        contents.text_content,
+        contents.html_content,
+        contents.markdown_content,
+        options.subscribers_only,
+        options.track_opens
+    )
+    .fetch_optional(transaction.as_mut())
+    .await
+    .context("Failed to update the draft")
+    .map_err(e500)?;
+
+    let Some(updated) = updated else {
+        not_a_draft_message().send();
+        return Ok(see_other("/admin/issues"));
+    };
+    // Scheduled issues go out unattended, so they must stay valid
+    if updated.status == "scheduled" {
+        if let Err(e) = validate_issue(&title, &contents.text_content, &contents.html_content) {
+            FlashMessage::error(encode_minimal(&e)).send();
+            return Ok(see_other(&format!(
+                "/admin/issues/{}/edit",
+                newsletter_issue_id
+            )));
+        }
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the draft")
+        .map_err(e500)?;
+
+    FlashMessage::info("The draft has been saved.").send();
+    Ok(see_other(&format!(
+        "/admin/issues/{}/edit",
+        newsletter_issue_id
+    )))
+}
+
+#[tracing::instrument(name = "Deleting a newsletter draft", skip(pool))]
+pub async fn delete_draft(
+    newsletter_issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let deleted = sqlx::query!(
+        r#"
+        DELETE FROM newsletter_issues
+        WHERE newsletter_issue_id = $1 AND status = 'draft';
+        "#,
+        newsletter_issue_id.into_inner()
+    )
+    .execute(pool.as_ref())
+    .await
+    .context("Failed to delete the draft")
+    .map_err(e500)?
+    .rows_affected();
+
+    if deleted == 0 {
+        not_a_draft_message().send();
+    } else {
+        FlashMessage::info("The draft has been deleted.").send();
+    }
+    Ok(see_other("/admin/issues"))
+}
+
+#[derive(serde::Deserialize)]
+pub struct VisibilityData {
+    subscribers_only: bool,
+}
+
+/// Hides an issue from the public archive, or shows it again
+#[tracing::instrument(
+    name = "Changing the visibility of a newsletter issue",
+    skip(form, pool)
+)]
+pub async fn set_issue_visibility(
+    newsletter_issue_id: web::Path<Uuid>,
+    form: web::Form<VisibilityData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let updated = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET subscribers_only = $2
+        WHERE newsletter_issue_id = $1;
+        "#,
+        newsletter_issue_id.into_inner(),
+        form.subscribers_only
+    )
+    .execute(pool.as_ref())
+    .await
+    .context("Failed to change the visibility of the issue")
+    .map_err(e500)?
+    .rows_affected();
+
+    if updated == 0 {
+        FlashMessage::error("The issue was not found.").send();
+    } else if form.subscribers_only {
+        FlashMessage::info("The issue is now reserved to subscribers.").send();
+    } else {
+        FlashMessage::info("The issue is now visible in the public archive.").send();
+    }
+    Ok(see_other("/admin/issues"))
+}
+
+#[derive(serde::Deserialize)]
+pub struct ScheduleData {
+    scheduled_for: String,
+    timezone: String,
+}
+
+/// Schedules a draft for publication, or moves an already scheduled issue to a new time
+#[tracing::instrument(name = "Scheduling a newsletter issue", skip(form, pool))]
+pub async fn schedule_issue(
+    newsletter_issue_id: web::Path<Uuid>,
+    form: web::Form<ScheduleData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let scheduled_for = match ScheduledTime::parse(&form.scheduled_for, &form.timezone) {
+        Ok(scheduled_for) => scheduled_for,
+        Err(e) => {
+            FlashMessage::error(encode_minimal(&e)).send();
+            return Ok(see_other("/admin/issues"));
+        }
+    };
+    if *scheduled_for.as_ref() <= Utc::now() {
+        FlashMessage::error("The scheduled time must be in the future.").send();
+        return Ok(see_other("/admin/issues"));
+    }
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let content = sqlx::query_as!(
+        IssueContent,
+        r#"
+        UPDATE newsletter_issues
+        SET status = 'scheduled', scheduled_for = $2
+        WHERE newsletter_issue_id = $1 AND status IN ('draft', 'scheduled')
+        RETURNING title, text_content, html_content;
+        "#,
+        newsletter_issue_id.into_inner(),
+        scheduled_for.as_ref()
+    )
+    .fetch_optional(transaction.as_mut())
+    .await
+    .context("Failed to schedule the issue")
+    .map_err(e500)?;
+
+    match content.map(|c| c.validate()) {
+        None => not_a_draft_message().send(),
+        Some(Err(e)) => FlashMessage::error(encode_minimal(&e)).send(),
+        Some(Ok(())) => {
+            transaction
+                .commit()
+                .await
+                .context("Failed to commit the schedule")
+                .map_err(e500)?;
+            FlashMessage::info(format!(
+                "The issue has been scheduled for {}.",
+                scheduled_for.as_ref().to_rfc3339()
+            ))
+            .send();
+        }
+    }
+    Ok(see_other("/admin/issues"))
+}
+
+/// Moves a scheduled issue back to the drafts
+#[tracing::instrument(name = "Cancelling a scheduled newsletter issue", skip(pool))]
+pub async fn cancel_schedule(
+    newsletter_issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let cancelled = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET status = 'draft', scheduled_for = NULL
+        WHERE newsletter_issue_id = $1 AND status = 'scheduled';
+        "#,
+        newsletter_issue_id.into_inner()
+    )
+    .execute(pool.as_ref())
+    .await
+    .context("Failed to cancel the schedule")
+    .map_err(e500)?
+    .rows_affected();
+
+    if cancelled == 0 {
+        FlashMessage::error("The issue is not scheduled, it may have been sent already.").send();
+    } else {
+        FlashMessage::info("The issue has been moved back to the drafts.").send();
+    }
+    Ok(see_other("/admin/issues"))
+}
+
+#[derive(serde::Deserialize)]
+pub struct PublishData {
+    idempotency_key: String,
+}
+
+#[tracing::instrument(
+    name = "Publishing a newsletter draft",
+    skip(form, pool),
+    fields(user_id=%&*user_id)
+)]
+pub async fn publish_draft(
+    newsletter_issue_id: web::Path<Uuid>,
+    form: web::Form<PublishData>,
+    pool: web::Data<PgPool>,
+    user_id: ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+
+    // Make call idempotent
+    let idempotency_key: IdempotencyKey = form.0.idempotency_key.try_into().map_err(e400)?;
+    let mut transaction = match try_processing(&pool, &idempotency_key, *user_id)
+        .await
+        .map_err(e500)?
+    {
+        NextAction::StartProcessing(t) => t,
+        NextAction::ReturnSavedResponse(http_response) => {
+            success_message().send();
+            return Ok(http_response);
+        }
+    };
+
+    // Dropping the transaction rolls back the idempotency record as well,
+    // so there is nothing to save if the issue can't be published.
+    let Some(content) = mark_draft_as_published(&mut transaction, newsletter_issue_id)
+        .await
+        .context("Failed to publish the draft")
+        .map_err(e500)?
+    else {
+        not_a_draft_message().send();
+        return Ok(see_other("/admin/issues"));
+    };
+    if let Err(e) = content.validate() {
+        FlashMessage::error(encode_minimal(&e)).send();
+        return Ok(see_other("/admin/issues"));
+    }
+
+    enqueue_delivery_tasks(&mut transaction, newsletter_issue_id)
+        .await
+        .context("failed to enqueue delivery tasks")
+        .map_err(e500)?;
+
+    let response = see_other("/admin/issues");
+    let response = save_response(transaction, &idempotency_key, *user_id, response)
+        .await
+        .map_err(e500)?;
+
+    success_message().send();
+
+    Ok(response)
+}
+
+fn success_message() -> FlashMessage {
+    FlashMessage::info("The newsletter issue has been accepted!")
+}
+
+fn not_a_draft_message() -> FlashMessage {
+    FlashMessage::error("The draft was not found, it may have been published already.")
+}
+
+fn unknown_list_message() -> FlashMessage {
+    FlashMessage::error("The selected list does not exist.")
+}
+
+fn unknown_segment_message() -> FlashMessage {
+    FlashMessage::error("The selected segment does not exist.")
+}
+
+struct IssueContent {
+    title: String,
+    text_content: String,
+    html_content: String,
+}
+
+impl IssueContent {
+    fn validate(&self) -> Result<(), String> {
+        validate_issue(&self.title, &self.text_content, &self.html_content)
+    }
+}
+
+/// Moves a draft out of the `draft` status, returning `None` if the issue
+/// doesn't exist or is not a draft (e.g. it has been published concurrently).
+async fn mark_draft_as_published(
+    transaction: &mut Transaction<'static, Postgres>,
+    newsletter_issue_id: Uuid,
+) -> Result<Option<IssueContent>, sqlx::Error> {
+    sqlx::query_as!(
+        IssueContent,
+        r#"
+        UPDATE newsletter_issues
+        SET status = 'sending', published_at = now()
+        WHERE newsletter_issue_id = $1 AND status = 'draft'
+        RETURNING title, text_content, html_content;
+        "#,
+        newsletter_issue_id
+    )
+    .fetch_optional(transaction.as_mut())
+    .await
+}
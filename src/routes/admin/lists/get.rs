@@ -0,0 +1,91 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use htmlescape::{encode_attribute, encode_minimal};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::{mailing_lists::MailingList, utils::e500};
+
+struct ListSummary {
+    list_id: Uuid,
+    name: String,
+    sender_email: Option<String>,
+    description: String,
+    n_subscribers: i64,
+}
+
+pub async fn lists(
+    pool: web::Data<PgPool>,
+    messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let lists = sqlx::query_as!(
+        ListSummary,
+        r#"
+        SELECT l.list_id, l.name, l.sender_email, l.description,
+            count(s.id) as "n_subscribers!"
+        FROM lists l
+        LEFT JOIN list_subscriptions ls ON ls.list_id = l.list_id AND ls.status = 'confirmed'
+        LEFT JOIN subscriptions s ON s.id = ls.subscriber_id AND s.status = 'confirmed'
+        GROUP BY l.list_id
+        ORDER BY l.created_at, l.list_id
+        "#
+    )
+    .fetch_all(pool.as_ref())
+    .await
+    .context("Failed to retrieve the mailing lists")
+    .map_err(e500)?;
+
+    let mut items = String::new();
+    for l in lists {
+        writeln!(
+            items,
+            r#"<li>
+                <form action="/admin/lists/{list_id}" method="post">
+                    <input type="text" name="name" value="{name}">
+                    <input type="text" name="description" value="{description}" placeholder="Description">
+                    <input type="email" name="sender_email" value="{sender_email}" placeholder="Default sender">
+                    <button type="submit">Save</button>
+                </form>
+                {n_subscribers} confirmed subscribers
+            </li>"#,
+            list_id = l.list_id,
+            name = encode_attribute(&l.name),
+            description = encode_attribute(&l.description),
+            sender_email = encode_attribute(l.sender_email.as_deref().unwrap_or_default()),
+            n_subscribers = l.n_subscribers,
+        )
+        .unwrap();
+    }
+
+    let body = include_str!("./lists.html")
+        .replace("{messages}", &msg_html)
+        .replace("{lists}", &items);
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
+
+/// `<option>`s to pick one of `lists`, with `selected` (or the default list) pre-selected
+pub(crate) fn list_options(lists: &[MailingList], selected: Option<Uuid>) -> String {
+    let mut options = String::new();
+    for (i, l) in lists.iter().enumerate() {
+        let is_selected = selected.map_or(i == 0, |selected| selected == l.list_id);
+        writeln!(
+            options,
+            r#"<option value="{}"{}>{}</option>"#,
+            l.list_id,
+            if is_selected { " selected" } else { "" },
+            encode_minimal(&l.name)
+        )
+        .unwrap();
+    }
+    options
+}
@@ -0,0 +1,6 @@
+mod get;
+mod post;
+
+pub(crate) use get::list_options;
+pub use get::lists;
+pub use post::{create_list, update_list};
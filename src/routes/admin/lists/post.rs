@@ -0,0 +1,130 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    domain::SubscriberEmail,
+    utils::{e500, see_other},
+};
+
+#[derive(serde::Deserialize)]
+pub struct ListData {
+    name: String,
+    #[serde(default)]
+    description: String,
+    /// Empty to send from the configured sender
+    #[serde(default)]
+    sender_email: String,
+}
+
+struct ValidList {
+    name: String,
+    description: String,
+    sender_email: Option<SubscriberEmail>,
+}
+
+impl TryFrom<ListData> for ValidList {
+    type Error = String;
+
+    fn try_from(value: ListData) -> Result<Self, Self::Error> {
+        let name = value.name.trim();
+        if name.is_empty() {
+            return Err("The list needs a name.".into());
+        }
+        if name.chars().count() > 256 {
+            return Err("The name of the list is too long.".into());
+        }
+        let sender_email = match value.sender_email.trim() {
+            "" => None,
+            sender_email => Some(SubscriberEmail::parse(sender_email)?),
+        };
+        Ok(Self {
+            name: name.into(),
+            description: value.description.trim().into(),
+            sender_email,
+        })
+    }
+}
+
+#[tracing::instrument(name = "Create a mailing list", skip(form, pool))]
+pub async fn create_list(
+    form: web::Form<ListData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let list: ValidList = match form.0.try_into() {
+        Ok(list) => list,
+        Err(e) => {
+            FlashMessage::error(encode_minimal(&e)).send();
+            return Ok(see_other("/admin/lists"));
+        }
+    };
+    let created = sqlx::query!(
+        r#"
+        INSERT INTO lists (list_id, name, sender_email, description, created_at)
+        VALUES ($1, $2, $3, $4, now())
+        ON CONFLICT (name) DO NOTHING
+        "#,
+        Uuid::new_v4(),
+        list.name,
+        list.sender_email.as_ref().map(|e| e.as_ref()),
+        list.description
+    )
+    .execute(pool.as_ref())
+    .await
+    .context("Failed to create the mailing list")
+    .map_err(e500)?
+    .rows_affected();
+
+    if created == 0 {
+        FlashMessage::error("There is already a list with this name.").send();
+    } else {
+        FlashMessage::info("The list has been created.").send();
+    }
+    Ok(see_other("/admin/lists"))
+}
+
+#[tracing::instrument(name = "Update a mailing list", skip(form, pool))]
+pub async fn update_list(
+    list_id: web::Path<Uuid>,
+    form: web::Form<ListData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let list: ValidList = match form.0.try_into() {
+        Ok(list) => list,
+        Err(e) => {
+            FlashMessage::error(encode_minimal(&e)).send();
+            return Ok(see_other("/admin/lists"));
+        }
+    };
+    let updated = sqlx::query!(
+        r#"
+        UPDATE lists SET name = $2, sender_email = $3, description = $4
+        WHERE list_id = $1
+        "#,
+        list_id.into_inner(),
+        list.name,
+        list.sender_email.as_ref().map(|e| e.as_ref()),
+        list.description
+    )
+    .execute(pool.as_ref())
+    .await;
+
+    match updated {
+        Ok(r) if r.rows_affected() == 0 => {
+            FlashMessage::error("The list was not found.").send();
+        }
+        Ok(_) => FlashMessage::info("The list has been updated.").send(),
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            FlashMessage::error("There is already a list with this name.").send();
+        }
+        Err(e) => {
+            return Err(e500(
+                anyhow::Error::from(e).context("Failed to update the mailing list"),
+            ))
+        }
+    }
+    Ok(see_other("/admin/lists"))
+}
@@ -1,9 +1,25 @@
 mod dashboard;
+mod deliveries;
+mod issues;
+mod lists;
 mod logout;
 mod newsletter;
+mod outbox;
 mod password;
+mod segments;
+mod subscribers;
+mod suppressions;
+mod tags;
 
 pub use dashboard::admin_dashboard;
+pub use deliveries::*;
+pub use issues::*;
+pub use lists::*;
 pub use logout::*;
 pub use newsletter::*;
+pub use outbox::*;
 pub use password::*;
+pub use segments::*;
+pub use subscribers::*;
+pub use suppressions::*;
+pub use tags::*;
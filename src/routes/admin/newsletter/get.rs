@@ -1,19 +1,120 @@
-use actix_web::{http::header::ContentType, HttpResponse};
+use actix_web::{http::header::ContentType, web, HttpResponse};
 use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use htmlescape::{encode_attribute, encode_minimal};
+use sqlx::PgPool;
 use std::fmt::Write;
 
+use super::post::BodyData;
+use crate::{
+    mailing_lists::{get_lists, resolve_list},
+    routes::admin::{lists::list_options, segments::segment_options},
+    segments::{get_recipients, get_segment, get_segments},
+    utils::e500,
+};
+
 pub async fn newsletter_form(
+    pool: web::Data<PgPool>,
     messages: IncomingFlashMessages,
 ) -> Result<HttpResponse, actix_web::Error> {
     let mut error_msg = String::new();
     for m in messages.iter() {
         writeln!(error_msg, "<p><i>{}</i></p>", m.content()).unwrap();
     }
+    let issue = BodyData {
+        list_id: None,
+        segment_id: None,
+        title: String::new(),
+        html_content: String::new(),
+        text_content: String::new(),
+        markdown_content: String::new(),
+        subscribers_only: false,
+        track_opens: false,
+        idempotency_key: uuid::Uuid::new_v4().to_string(),
+    };
+    render_newsletter_form(&pool, &error_msg, &issue).await
+}
+
+/// Shows how many subscribers the issue being written would reach,
+/// giving the form back as it was filled in
+#[tracing::instrument(name = "Counting the recipients of an issue", skip(form, pool))]
+pub async fn preview_recipients(
+    form: web::Form<BodyData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let list_id = resolve_list(&pool, form.list_id)
+        .await
+        .context("Failed to retrieve the list of the issue")
+        .map_err(e500)?;
+    let segment = match form.segment_id {
+        Some(segment_id) => get_segment(pool.as_ref(), segment_id)
+            .await
+            .context("Failed to retrieve the segment of the issue")
+            .map_err(e500)?
+            .map(Some),
+        None => Some(None),
+    };
+    let message = match (list_id, segment) {
+        (None, _) => "The selected list does not exist.".to_owned(),
+        (_, None) => "The selected segment does not exist.".to_owned(),
+        (Some(list_id), Some(segment)) => {
+            let recipients = get_recipients(pool.as_ref(), list_id, segment.as_ref())
+                .await
+                .context("Failed to retrieve the recipients of the issue")
+                .map_err(e500)?;
+            format!(
+                "This issue would reach {} confirmed subscribers.",
+                recipients.len()
+            )
+        }
+    };
+    render_newsletter_form(&pool, &format!("<p><i>{}</i></p>", message), &form).await
+}
+
+async fn render_newsletter_form(
+    pool: &PgPool,
+    messages: &str,
+    issue: &BodyData,
+) -> Result<HttpResponse, actix_web::Error> {
+    let lists = get_lists(pool)
+        .await
+        .context("Failed to retrieve the mailing lists")
+        .map_err(e500)?;
+    let segments = get_segments(pool)
+        .await
+        .context("Failed to retrieve the segments")
+        .map_err(e500)?;
 
-    let idempotency_key = uuid::Uuid::new_v4().to_string();
     let body = include_str!("./newsletter_form.html")
-        .replace("{messages}", &error_msg)
-        .replace("{idempotency_key}", &idempotency_key);
+        .replace("{messages}", messages)
+        .replace("{list_options}", &list_options(&lists, issue.list_id))
+        .replace(
+            "{segment_options}",
+            &segment_options(&segments, issue.segment_id),
+        )
+        .replace(
+            "{idempotency_key}",
+            &encode_attribute(&issue.idempotency_key),
+        )
+        .replace("{title}", &encode_attribute(&issue.title))
+        .replace(
+            "{subscribers_only}",
+            if issue.subscribers_only {
+                " checked"
+            } else {
+                ""
+            },
+        )
+        .replace(
+            "{track_opens}",
+            if issue.track_opens { " checked" } else { "" },
+        )
+        .replace(
+            "{markdown_content}",
+            &encode_minimal(&issue.markdown_content),
+        )
+        .replace("{text_content}", &encode_minimal(&issue.text_content))
+        .replace("{html_content}", &encode_minimal(&issue.html_content));
 
     Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
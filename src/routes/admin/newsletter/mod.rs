@@ -1,5 +1,5 @@
 mod get;
 mod post;
 
-pub use get::newsletter_form;
+pub use get::{newsletter_form, preview_recipients};
 pub use post::publish_newsletter;
@@ -4,21 +4,40 @@ use actix_web::{
 };
 use actix_web_flash_messages::FlashMessage;
 use anyhow::Context;
+use htmlescape::encode_minimal;
 use sqlx::{PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
 use crate::{
     authentication::UserId,
     idempotency::{save_response, try_processing, IdempotencyKey, NextAction},
-    utils::{e400, e500, see_other},
+    issue_delivery_worker::enqueue_delivery_tasks,
+    issue_template::validate_issue,
+    mailing_lists::resolve_list,
+    markdown::IssueContents,
+    segments::is_valid_segment,
+    utils::{e400, e500, empty_as_none, see_other},
 };
 
 #[derive(serde::Deserialize)]
 pub struct BodyData {
-    title: String,
-    html_content: String,
-    text_content: String,
-    idempotency_key: String,
+    /// The default list when not given
+    pub(super) list_id: Option<Uuid>,
+    /// The whole list when not given
+    #[serde(default, deserialize_with = "empty_as_none")]
+    pub(super) segment_id: Option<Uuid>,
+    pub(super) title: String,
+    #[serde(default)]
+    pub(super) html_content: String,
+    #[serde(default)]
+    pub(super) text_content: String,
+    #[serde(default)]
+    pub(super) markdown_content: String,
+    #[serde(default)]
+    pub(super) subscribers_only: bool,
+    #[serde(default)]
+    pub(super) track_opens: bool,
+    pub(super) idempotency_key: String,
 }
 
 #[tracing::instrument(
@@ -33,11 +52,42 @@ pub async fn publish_newsletter(
 ) -> Result<HttpResponse, actix_web::Error> {
     let user_id = user_id.into_inner();
     let BodyData {
+        list_id,
+        segment_id,
         title,
         html_content,
         text_content,
+        markdown_content,
+        subscribers_only,
+        track_opens,
         idempotency_key,
     } = form.0;
+    let contents = IssueContents::from_form(text_content, html_content, markdown_content);
+
+    if let Err(e) = validate_issue(&title, &contents.text_content, &contents.html_content) {
+        FlashMessage::error(encode_minimal(&e)).send();
+        return Ok(see_other("/admin/newsletters"));
+    }
+
+    let list_id = match resolve_list(&pool, list_id)
+        .await
+        .context("Failed to retrieve the list of the issue")
+        .map_err(e500)?
+    {
+        Some(list_id) => list_id,
+        None => {
+            FlashMessage::error("The selected list does not exist.").send();
+            return Ok(see_other("/admin/newsletters"));
+        }
+    };
+    if !is_valid_segment(pool.as_ref(), segment_id)
+        .await
+        .context("Failed to retrieve the segment of the issue")
+        .map_err(e500)?
+    {
+        FlashMessage::error("The selected segment does not exist.").send();
+        return Ok(see_other("/admin/newsletters"));
+    }
 
     // Make call idempotent
     let idempotency_key: IdempotencyKey = idempotency_key.try_into().map_err(e400)?;
@@ -52,10 +102,18 @@ pub async fn publish_newsletter(
         }
     };
 
-    let issue_id = insert_newsletter_issue(&mut transaction, &title, &text_content, &html_content)
-        .await
-        .context("failed to store newsletter issue details")
-        .map_err(e500)?;
+    let issue_id = insert_newsletter_issue(
+        &mut transaction,
+        list_id,
+        segment_id,
+        &title,
+        &contents,
+        subscribers_only,
+        track_opens,
+    )
+    .await
+    .context("failed to store newsletter issue details")
+    .map_err(e500)?;
 
     enqueue_delivery_tasks(&mut transaction, issue_id)
         .await
@@ -76,49 +134,36 @@ fn success_message() -> FlashMessage {
     FlashMessage::info("The newsletter issue has been accepted!")
 }
 
-#[tracing::instrument(
-    name = "Creating newsletter issue",
-    skip(transaction, text_content, html_content)
-)]
+#[tracing::instrument(name = "Creating newsletter issue", skip(transaction, contents))]
 async fn insert_newsletter_issue(
     transaction: &mut Transaction<'static, Postgres>,
+    list_id: Uuid,
+    segment_id: Option<Uuid>,
     title: &str,
-    text_content: &str,
-    html_content: &str,
+    contents: &IssueContents,
+    subscribers_only: bool,
+    track_opens: bool,
 ) -> Result<Uuid, anyhow::Error> {
     let newsletter_issue_id = Uuid::new_v4();
 
     sqlx::query!(
         r#"
         INSERT INTO newsletter_issues
-        (newsletter_issue_id,title,text_content,html_content,published_at)
-        VALUES ($1, $2, $3, $4, now());
+        (newsletter_issue_id,list_id,segment_id,title,text_content,html_content,markdown_content,subscribers_only,track_opens,published_at,status)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, now(), 'sending');
         "#,
         newsletter_issue_id,
+        list_id,
+        segment_id,
         title,
-        text_content,
-        html_content
+        contents.text_content,
+        contents.html_content,
+        contents.markdown_content,
+        subscribers_only,
+        track_opens
     )
     .execute(transaction.as_mut())
     .await?;
 
     Ok(newsletter_issue_id)
 }
-
-#[tracing::instrument(name = "Enqueue delivery tasks for newsletter", skip(transaction))]
-async fn enqueue_delivery_tasks(
-    transaction: &mut Transaction<'_, Postgres>,
-    newsletter_issue_id: Uuid,
-) -> Result<(), sqlx::Error> {
-    sqlx::query!(
-        r#"
-        INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email)
-        SELECT $1, email FROM subscriptions WHERE status = 'confirmed';
-        "#,
-        newsletter_issue_id
-    )
-    .execute(transaction.as_mut())
-    .await?;
-
-    Ok(())
-}
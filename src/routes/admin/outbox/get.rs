@@ -0,0 +1,103 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::{encode_attribute, encode_minimal};
+use sqlx::{types::Json, PgPool};
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::{email_client::EmailHeader, utils::e500};
+
+pub async fn outbox(pool: web::Data<PgPool>) -> Result<HttpResponse, actix_web::Error> {
+    let emails = sqlx::query!(
+        r#"
+        SELECT captured_email_id, recipient, subject, captured_at
+        FROM captured_emails
+        ORDER BY captured_at DESC
+        LIMIT 100
+        "#
+    )
+    .fetch_all(pool.as_ref())
+    .await
+    .context("Failed to retrieve the captured emails")
+    .map_err(e500)?;
+
+    let mut rows = String::new();
+    for e in emails {
+        writeln!(
+            rows,
+            r#"<tr><td>{}</td><td>{}</td><td><a href="/admin/outbox/{}">{}</a></td></tr>"#,
+            e.captured_at.to_rfc3339(),
+            encode_minimal(&e.recipient),
+            e.captured_email_id,
+            encode_minimal(&e.subject),
+        )
+        .unwrap();
+    }
+
+    let body = include_str!("./outbox.html").replace("{rows}", &rows);
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
+
+struct CapturedEmail {
+    sender: String,
+    recipient: String,
+    subject: String,
+    html_body: String,
+    text_body: String,
+    headers: Json<Vec<EmailHeader>>,
+    captured_at: DateTime<Utc>,
+}
+
+pub async fn outbox_email(
+    captured_email_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = sqlx::query_as!(
+        CapturedEmail,
+        r#"
+        SELECT
+            sender, recipient, subject, html_body, text_body,
+            headers as "headers: Json<Vec<EmailHeader>>",
+            captured_at
+        FROM captured_emails
+        WHERE captured_email_id = $1
+        "#,
+        captured_email_id.into_inner()
+    )
+    .fetch_optional(pool.as_ref())
+    .await
+    .context("Failed to retrieve the captured email")
+    .map_err(e500)?;
+
+    let Some(email) = email else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+
+    let mut headers = String::new();
+    for h in email.headers.iter() {
+        writeln!(
+            headers,
+            "<li><b>{}</b>: {}</li>",
+            encode_minimal(&h.name),
+            encode_minimal(&h.value)
+        )
+        .unwrap();
+    }
+
+    // The html body is rendered in a sandboxed iframe, so that it can neither
+    // run scripts nor mess with the rest of the page.
+    let body = include_str!("./outbox_email.html")
+        .replace("{subject}", &encode_minimal(&email.subject))
+        .replace("{sender}", &encode_minimal(&email.sender))
+        .replace("{recipient}", &encode_minimal(&email.recipient))
+        .replace("{captured_at}", &email.captured_at.to_rfc3339())
+        .replace("{headers}", &headers)
+        .replace("{text_body}", &encode_minimal(&email.text_body))
+        .replace("{html_body}", &encode_attribute(&email.html_body));
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
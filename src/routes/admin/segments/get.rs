@@ -0,0 +1,114 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::{
+    segments::{get_segments, get_subscriber_profiles, Segment, SUBSCRIBER_STATUSES},
+    utils::e500,
+};
+
+pub async fn segments(
+    pool: web::Data<PgPool>,
+    messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let segments = get_segments(pool.as_ref())
+        .await
+        .context("Failed to retrieve the segments")
+        .map_err(e500)?;
+    let subscribers = get_subscriber_profiles(pool.as_ref())
+        .await
+        .context("Failed to retrieve the subscribers")
+        .map_err(e500)?;
+
+    let mut rows = String::new();
+    for s in &segments {
+        let n_matching = subscribers.iter().filter(|p| s.matches(p)).count();
+        writeln!(
+            rows,
+            r#"<tr>
+                <td>{name}</td>
+                <td>{tag_expression}</td>
+                <td>{subscribed_from}</td>
+                <td>{subscribed_before}</td>
+                <td>{status}</td>
+                <td>{n_matching}</td>
+                <td>
+                    <form action="/admin/segments/{segment_id}/delete" method="post">
+                        <button type="submit">Delete</button>
+                    </form>
+                </td>
+            </tr>"#,
+            name = encode_minimal(&s.name),
+            tag_expression = match s.tag_expression.trim() {
+                "" => "Any tags".to_owned(),
+                e => encode_minimal(e),
+            },
+            subscribed_from = s
+                .subscribed_from
+                .map(|d| d.to_string())
+                .unwrap_or_else(|| "-".into()),
+            subscribed_before = s
+                .subscribed_before
+                .map(|d| d.to_string())
+                .unwrap_or_else(|| "-".into()),
+            status = s.status.as_deref().unwrap_or("Any"),
+            segment_id = s.segment_id,
+        )
+        .unwrap();
+    }
+
+    let mut status_options = String::new();
+    for status in SUBSCRIBER_STATUSES {
+        writeln!(
+            status_options,
+            r#"<option value="{0}">{0}</option>"#,
+            status
+        )
+        .unwrap();
+    }
+
+    let body = include_str!("./segments.html")
+        .replace("{messages}", &msg_html)
+        .replace("{rows}", &rows)
+        .replace("{status_options}", &status_options);
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
+
+/// `<option>`s to restrict an issue to one of `segments`,
+/// the blank one sending it to the whole list
+pub(crate) fn segment_options(segments: &[Segment], selected: Option<Uuid>) -> String {
+    let mut options = String::new();
+    writeln!(
+        options,
+        r#"<option value=""{}>Whole list</option>"#,
+        if selected.is_none() { " selected" } else { "" }
+    )
+    .unwrap();
+    for s in segments {
+        writeln!(
+            options,
+            r#"<option value="{}"{}>{}</option>"#,
+            s.segment_id,
+            if selected == Some(s.segment_id) {
+                " selected"
+            } else {
+                ""
+            },
+            encode_minimal(&s.name)
+        )
+        .unwrap();
+    }
+    options
+}
@@ -0,0 +1,6 @@
+mod get;
+mod post;
+
+pub(crate) use get::segment_options;
+pub use get::segments;
+pub use post::{create_segment, delete_segment};
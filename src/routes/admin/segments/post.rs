@@ -0,0 +1,145 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use chrono::NaiveDate;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    domain::TagExpression,
+    segments::SUBSCRIBER_STATUSES,
+    utils::{e500, empty_as_none, see_other},
+};
+
+#[derive(serde::Deserialize)]
+pub struct SegmentData {
+    name: String,
+    #[serde(default)]
+    tag_expression: String,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    subscribed_from: Option<NaiveDate>,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    subscribed_before: Option<NaiveDate>,
+    /// Any status when not given
+    #[serde(default, deserialize_with = "empty_as_none")]
+    status: Option<String>,
+}
+
+impl SegmentData {
+    fn validate(&self) -> Result<(), String> {
+        let name = self.name.trim();
+        if name.is_empty() {
+            return Err("The segment needs a name.".into());
+        }
+        if name.chars().count() > 256 {
+            return Err("The name of the segment is too long.".into());
+        }
+        TagExpression::parse(&self.tag_expression)?;
+        if let (Some(from), Some(before)) = (self.subscribed_from, self.subscribed_before) {
+            if from >= before {
+                return Err("The subscription dates don't leave any day to match.".into());
+            }
+        }
+        if let Some(status) = &self.status {
+            if !SUBSCRIBER_STATUSES.contains(&status.as_str()) {
+                return Err(format!("\"{}\" is not a subscriber status.", status));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[tracing::instrument(name = "Create a segment", skip(form, pool))]
+pub async fn create_segment(
+    form: web::Form<SegmentData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if let Err(e) = form.validate() {
+        FlashMessage::error(encode_minimal(&e)).send();
+        return Ok(see_other("/admin/segments"));
+    }
+    let created = sqlx::query!(
+        r#"
+        INSERT INTO segments (
+            segment_id, name, tag_expression, subscribed_from, subscribed_before, status, created_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, now())
+        ON CONFLICT (name) DO NOTHING
+        "#,
+        Uuid::new_v4(),
+        form.name.trim(),
+        form.tag_expression.trim(),
+        form.subscribed_from,
+        form.subscribed_before,
+        form.status
+    )
+    .execute(pool.as_ref())
+    .await
+    .context("Failed to create the segment")
+    .map_err(e500)?
+    .rows_affected();
+
+    if created == 0 {
+        FlashMessage::error("There is already a segment with this name.").send();
+    } else {
+        FlashMessage::info("The segment has been created.").send();
+    }
+    Ok(see_other("/admin/segments"))
+}
+
+/// Deletes a segment, unless an issue waiting to go out is restricted to it
+#[tracing::instrument(name = "Delete a segment", skip(pool))]
+pub async fn delete_segment(
+    segment_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let segment_id = segment_id.into_inner();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    // Sent issues keep their deliveries, they only forget the segment
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues SET segment_id = NULL
+        WHERE segment_id = $1 AND status IN ('sending', 'sent')
+        "#,
+        segment_id
+    )
+    .execute(transaction.as_mut())
+    .await
+    .context("Failed to detach the segment from the sent issues")
+    .map_err(e500)?;
+    let deleted = match sqlx::query!("DELETE FROM segments WHERE segment_id = $1", segment_id)
+        .execute(transaction.as_mut())
+        .await
+    {
+        Ok(result) => result.rows_affected(),
+        // Drafts and scheduled issues still restricted to it
+        Err(sqlx::Error::Database(e)) if e.is_foreign_key_violation() => 0,
+        Err(e) => {
+            return Err(e500(
+                anyhow::Error::new(e).context("Failed to delete the segment"),
+            ))
+        }
+    };
+    if deleted > 0 {
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit SQL transaction to delete a segment")
+            .map_err(e500)?;
+    }
+
+    if deleted == 0 {
+        FlashMessage::error(
+            "The segment was not found or a draft or scheduled issue is restricted to it.",
+        )
+        .send();
+    } else {
+        FlashMessage::info("The segment has been deleted.").send();
+    }
+    Ok(see_other("/admin/segments"))
+}
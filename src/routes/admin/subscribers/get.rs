@@ -0,0 +1,460 @@
+use actix_web::{
+    http::header::{ContentDisposition, ContentType, DispositionParam, DispositionType},
+    web, HttpResponse,
+};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::{
+    mailing_lists::get_lists,
+    routes::admin::lists::list_options,
+    segments::SUBSCRIBER_STATUSES,
+    subscriber_data::{export_subscriber_data, get_all_subscribers, SubscriberDataExport},
+    utils::{e500, empty_as_none, see_other},
+};
+
+const SUBSCRIBERS_PER_PAGE: i64 = 50;
+
+#[derive(serde::Deserialize)]
+pub struct SubscribersQuery {
+    /// Part of the address or name
+    #[serde(default)]
+    q: String,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    status: Option<String>,
+    page: Option<u32>,
+}
+
+impl SubscribersQuery {
+    fn page_link(&self, page: u32) -> String {
+        let query = serde_urlencoded::to_string([
+            ("q", self.q.as_str()),
+            ("status", self.status.as_deref().unwrap_or_default()),
+            ("page", &page.to_string()),
+        ])
+        .unwrap();
+        format!("/admin/subscribers?{}", query)
+    }
+}
+
+struct SubscriberRow {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+}
+
+pub async fn subscribers(
+    query: web::Query<SubscribersQuery>,
+    pool: web::Data<PgPool>,
+    messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let page = query.page.unwrap_or(1).max(1);
+    let search = query.q.trim();
+    // Wildcards typed in the search box are matched literally
+    let pattern = (!search.is_empty()).then(|| {
+        format!(
+            "%{}%",
+            search
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_")
+        )
+    });
+
+    let n_matching = sqlx::query!(
+        r#"
+        SELECT COUNT(*) as "count!"
+        FROM subscriptions
+        WHERE ($1::text IS NULL OR email ILIKE $1 OR name ILIKE $1)
+            AND ($2::text IS NULL OR status = $2)
+        "#,
+        pattern,
+        query.status
+    )
+    .fetch_one(pool.as_ref())
+    .await
+    .context("Failed to count the subscribers")
+    .map_err(e500)?
+    .count;
+    let subscribers = sqlx::query_as!(
+        SubscriberRow,
+        r#"
+        SELECT id, email, name, status, subscribed_at
+        FROM subscriptions
+        WHERE ($1::text IS NULL OR email ILIKE $1 OR name ILIKE $1)
+            AND ($2::text IS NULL OR status = $2)
+        ORDER BY subscribed_at DESC, email
+        LIMIT $3 OFFSET $4
+        "#,
+        pattern,
+        query.status,
+        SUBSCRIBERS_PER_PAGE,
+        (i64::from(page) - 1) * SUBSCRIBERS_PER_PAGE
+    )
+    .fetch_all(pool.as_ref())
+    .await
+    .context("Failed to retrieve the subscribers")
+    .map_err(e500)?;
+
+    let mut rows = String::new();
+    for s in subscribers {
+        writeln!(
+            rows,
+            r#"<tr>
+                <td><a href="/admin/subscribers/{}">{}</a></td>
+                <td>{}</td>
+                <td>{}</td>
+                <td>{}</td>
+            </tr>"#,
+            s.id,
+            encode_minimal(&s.email),
+            encode_minimal(&s.name),
+            s.status,
+            s.subscribed_at.to_rfc3339(),
+        )
+        .unwrap();
+    }
+    if rows.is_empty() {
+        rows.push_str(r#"<tr><td colspan="4">No subscribers match.</td></tr>"#);
+    }
+
+    let mut status_options = String::new();
+    for status in SUBSCRIBER_STATUSES {
+        writeln!(
+            status_options,
+            r#"<option value="{0}"{1}>{0}</option>"#,
+            status,
+            if query.status.as_deref() == Some(status) {
+                " selected"
+            } else {
+                ""
+            }
+        )
+        .unwrap();
+    }
+
+    let mut pagination = vec![];
+    if page > 1 {
+        pagination.push(format!(
+            r#"<a href="{}">&lt;- Previous page</a>"#,
+            encode_minimal(&query.page_link(page - 1))
+        ));
+    }
+    if i64::from(page) * SUBSCRIBERS_PER_PAGE < n_matching {
+        pagination.push(format!(
+            r#"<a href="{}">Next page -&gt;</a>"#,
+            encode_minimal(&query.page_link(page + 1))
+        ));
+    }
+
+    let body = include_str!("./subscribers.html")
+        .replace("{messages}", &msg_html)
+        .replace("{q}", &encode_minimal(&query.q))
+        .replace("{status_options}", &status_options)
+        .replace("{count}", &format!("{} subscribers match.", n_matching))
+        .replace("{rows}", &rows)
+        .replace("{pagination}", &pagination.join(" | "));
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
+
+pub async fn subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let subscriber_id = subscriber_id.into_inner();
+    let email = sqlx::query!(
+        "SELECT email FROM subscriptions WHERE id = $1",
+        subscriber_id
+    )
+    .fetch_optional(pool.as_ref())
+    .await
+    .context("Failed to retrieve the subscriber")
+    .map_err(e500)?;
+    let Some(email) = email else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let Some(data) = export_subscriber_data(&pool, &email.email)
+        .await
+        .context("Failed to collect the data of the subscriber")
+        .map_err(e500)?
+    else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    // Deleted in the meantime
+    let Some(subscription) = &data.subscription else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+
+    let mut actions = String::new();
+    if subscription.status == "pending_confirmation" {
+        writeln!(
+            actions,
+            r#"<form action="/admin/subscribers/{}/confirm" method="post">
+                <button type="submit">Confirm</button>
+            </form>"#,
+            subscriber_id
+        )
+        .unwrap();
+    }
+    if subscription.status != "unsubscribed" {
+        writeln!(
+            actions,
+            r#"<form action="/admin/subscribers/{}/unsubscribe" method="post">
+                <button type="submit">Unsubscribe</button>
+            </form>"#,
+            subscriber_id
+        )
+        .unwrap();
+    }
+
+    let mut list_rows = String::new();
+    for l in &data.lists {
+        writeln!(
+            list_rows,
+            "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+            encode_minimal(&l.list),
+            l.status,
+            l.subscribed_at.to_rfc3339()
+        )
+        .unwrap();
+    }
+    if list_rows.is_empty() {
+        list_rows.push_str(r#"<tr><td colspan="3">Not on any list.</td></tr>"#);
+    }
+
+    let mut history_rows = String::new();
+    for (at, event) in history(&data) {
+        writeln!(
+            history_rows,
+            "<tr><td>{}</td><td>{}</td></tr>",
+            at.to_rfc3339(),
+            encode_minimal(&event)
+        )
+        .unwrap();
+    }
+    let tags = data
+        .tags
+        .iter()
+        .map(|t| t.tag.as_str())
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let body = include_str!("./subscriber.html")
+        .replace("{messages}", &msg_html)
+        .replace("{subscriber_id}", &subscriber_id.to_string())
+        .replace(
+            "{email_query}",
+            &encode_minimal(
+                &serde_urlencoded::to_string([("email", &subscription.email)]).unwrap(),
+            ),
+        )
+        .replace("{email}", &encode_minimal(&subscription.email))
+        .replace("{name}", &encode_minimal(&subscription.name))
+        .replace("{status}", &subscription.status)
+        .replace("{subscribed_at}", &subscription.subscribed_at.to_rfc3339())
+        .replace("{tags}", &encode_minimal(&tags))
+        .replace("{actions}", &actions)
+        .replace("{list_rows}", &list_rows)
+        .replace("{history_rows}", &history_rows);
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
+
+/// What happened to a subscriber, most recent first
+fn history(data: &SubscriberDataExport) -> Vec<(DateTime<Utc>, String)> {
+    let mut events = vec![];
+    if let Some(subscription) = &data.subscription {
+        events.push((subscription.subscribed_at, "Subscribed".to_owned()));
+    }
+    for t in &data.confirmation_tokens {
+        events.push((t.created_at, "Confirmation email sent".to_owned()));
+        if let Some(used_at) = t.used_at {
+            events.push((used_at, "Confirmed".to_owned()));
+        }
+    }
+    for t in &data.tags {
+        events.push((t.tagged_at, format!("Tagged {}", t.tag)));
+    }
+    for d in &data.deliveries {
+        events.push((
+            d.attempted_at,
+            format!("Delivery of \"{}\": {}", d.issue_title, d.outcome),
+        ));
+    }
+    for d in &data.pending_deliveries {
+        events.push((
+            d.execute_after,
+            format!("Delivery of \"{}\": queued", d.issue_title),
+        ));
+    }
+    for d in &data.failed_deliveries {
+        events.push((
+            d.failed_at,
+            format!(
+                "Delivery of \"{}\": gave up after {} retries ({})",
+                d.issue_title, d.n_retries, d.last_error
+            ),
+        ));
+    }
+    for o in &data.opens {
+        events.push((o.opened_at, format!("Opened \"{}\"", o.issue_title)));
+    }
+    for c in &data.clicks {
+        events.push((
+            c.clicked_at,
+            format!("Clicked {} in \"{}\"", c.url, c.issue_title),
+        ));
+    }
+    for e in &data.email_events {
+        events.push((
+            e.received_at,
+            format!(
+                "{} ({}) reported by the email provider",
+                e.record_type, e.event_type
+            ),
+        ));
+    }
+    events.sort_by_key(|(at, _)| std::cmp::Reverse(*at));
+    events
+}
+
+pub async fn import_subscribers_form(
+    pool: web::Data<PgPool>,
+    messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let lists = get_lists(pool.as_ref())
+        .await
+        .context("Failed to retrieve the mailing lists")
+        .map_err(e500)?;
+
+    let body = include_str!("./import_form.html")
+        .replace("{messages}", &msg_html)
+        .replace("{list_options}", &list_options(&lists, None));
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
+
+pub async fn subscriber_data(
+    messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let body = include_str!("./data.html").replace("{messages}", &msg_html);
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
+
+#[derive(serde::Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+#[derive(serde::Deserialize)]
+pub struct ExportQuery {
+    format: ExportFormat,
+}
+
+fn attachment(filename: &str) -> ContentDisposition {
+    ContentDisposition {
+        disposition: DispositionType::Attachment,
+        parameters: vec![DispositionParam::Filename(filename.into())],
+    }
+}
+
+pub async fn export_subscribers(
+    query: web::Query<ExportQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscribers = get_all_subscribers(&pool)
+        .await
+        .context("Failed to retrieve the subscribers")
+        .map_err(e500)?;
+
+    match query.format {
+        ExportFormat::Json => Ok(HttpResponse::Ok()
+            .insert_header(attachment("subscribers.json"))
+            .json(subscribers)),
+        ExportFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(vec![]);
+            writer
+                .write_record(["email", "name", "status", "subscribed_at", "lists", "tags"])
+                .map_err(e500)?;
+            for s in subscribers {
+                writer
+                    .write_record([
+                        s.email,
+                        s.name,
+                        s.status,
+                        s.subscribed_at.to_rfc3339(),
+                        s.lists.join(", "),
+                        s.tags.join(", "),
+                    ])
+                    .map_err(e500)?;
+            }
+            let body = writer.into_inner().map_err(e500)?;
+
+            Ok(HttpResponse::Ok()
+                .content_type("text/csv; charset=utf-8")
+                .insert_header(attachment("subscribers.csv"))
+                .body(body))
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct SubscriberQuery {
+    email: String,
+}
+
+/// Everything stored about one address, to answer a data access request
+#[tracing::instrument(name = "Answer a data access request", skip_all)]
+pub async fn export_data_of_subscriber(
+    query: web::Query<SubscriberQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(export) = export_subscriber_data(&pool, query.email.trim())
+        .await
+        .context("Failed to collect the data of the subscriber")
+        .map_err(e500)?
+    else {
+        FlashMessage::error("Nothing is stored about this address.").send();
+        return Ok(see_other("/admin/subscribers/data"));
+    };
+
+    Ok(HttpResponse::Ok()
+        .insert_header(attachment("subscriber-data.json"))
+        .json(export))
+}
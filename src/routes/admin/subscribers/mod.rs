@@ -0,0 +1,11 @@
+mod get;
+mod post;
+
+pub use get::{
+    export_data_of_subscriber, export_subscribers, import_subscribers_form, subscriber,
+    subscriber_data, subscribers,
+};
+pub use post::{
+    confirm_subscriber, delete_subscriber, erase_subscriber, import_subscribers,
+    unsubscribe_subscriber_manually,
+};
@@ -0,0 +1,444 @@
+use std::collections::HashMap;
+
+use actix_multipart::form::{bytes::Bytes, text::Text, MultipartForm};
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use htmlescape::encode_minimal;
+use sqlx::{PgPool, Postgres, Transaction};
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::{
+    confirmation_email_worker::enqueue_confirmation_email,
+    domain::{NewSubscriber, SubscriberEmail, SubscriberName, SubscriberTag},
+    mailing_lists::{join_list, resolve_list},
+    routes::{mark_subscriber_confirmed, unsubscribe_subscriber},
+    segments::tag_subscriber,
+    subscriber_data::{erase_subscriber_data, remove_subscriber},
+    suppression_list::is_suppressed,
+    utils::{e500, see_other},
+};
+
+#[derive(serde::Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportMode {
+    /// The subscribers opted in elsewhere
+    Confirmed,
+    /// The subscribers are asked to confirm, by the background worker
+    SendConfirmation,
+}
+
+#[derive(MultipartForm)]
+pub struct ImportForm {
+    file: Bytes,
+    /// The default list when not given
+    list_id: Option<Text<Uuid>>,
+    mode: Text<ImportMode>,
+}
+
+#[derive(serde::Deserialize)]
+struct ImportedRow {
+    email: String,
+    name: String,
+    #[serde(default)]
+    tags: String,
+}
+
+struct ValidRow {
+    subscriber: NewSubscriber,
+    tags: Vec<SubscriberTag>,
+}
+
+impl TryFrom<ImportedRow> for ValidRow {
+    type Error = String;
+
+    fn try_from(row: ImportedRow) -> Result<Self, Self::Error> {
+        let subscriber = NewSubscriber {
+            email: SubscriberEmail::parse(&row.email)?,
+            name: SubscriberName::parse(&row.name)?,
+        };
+        let tags = SubscriberTag::parse_list(&row.tags)?;
+        Ok(Self { subscriber, tags })
+    }
+}
+
+/// A row that was not imported, and why
+struct SkippedRow {
+    line: u64,
+    email: String,
+    reason: String,
+}
+
+/// Adds subscribers in bulk from a CSV file, reporting the rows that were skipped
+#[tracing::instrument(name = "Import subscribers", skip(form, pool), fields(mode = ?form.mode.0))]
+pub async fn import_subscribers(
+    MultipartForm(form): MultipartForm<ImportForm>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(list_id) = resolve_list(&pool, form.list_id.map(|l| l.0))
+        .await
+        .context("Failed to retrieve the list to import into")
+        .map_err(e500)?
+    else {
+        FlashMessage::error("The selected list does not exist.").send();
+        return Ok(see_other("/admin/subscribers/import"));
+    };
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(form.file.data.as_ref());
+    let headers = reader.headers().cloned().unwrap_or_default();
+    if !["email", "name"]
+        .iter()
+        .all(|column| headers.iter().any(|c| c == *column))
+    {
+        FlashMessage::error("The CSV file must have a header with email and name columns.").send();
+        return Ok(see_other("/admin/subscribers/import"));
+    }
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let mut skipped = vec![];
+    let mut first_lines: HashMap<String, u64> = HashMap::new();
+    let (mut n_imported, mut n_added, mut n_existing) = (0, 0, 0);
+    for record in reader.records() {
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                skipped.push(SkippedRow {
+                    line: e.position().map_or(0, |p| p.line()),
+                    email: String::new(),
+                    reason: "The row is malformed.".into(),
+                });
+                continue;
+            }
+        };
+        let line = record.position().map_or(0, |p| p.line());
+        let row: ImportedRow = match record.deserialize(Some(&headers)) {
+            Ok(row) => row,
+            Err(_) => {
+                skipped.push(SkippedRow {
+                    line,
+                    email: String::new(),
+                    reason: "The row is malformed.".into(),
+                });
+                continue;
+            }
+        };
+        let email = row.email.clone();
+        let row = match ValidRow::try_from(row) {
+            Ok(row) => row,
+            Err(reason) => {
+                skipped.push(SkippedRow {
+                    line,
+                    email,
+                    reason,
+                });
+                continue;
+            }
+        };
+        if let Some(first_line) = first_lines.get(row.subscriber.email.as_ref()) {
+            skipped.push(SkippedRow {
+                line,
+                email,
+                reason: format!("The address is already on line {}.", first_line),
+            });
+            continue;
+        }
+        first_lines.insert(row.subscriber.email.as_ref().to_owned(), line);
+
+        if is_suppressed(&mut *transaction, row.subscriber.email.as_ref())
+            .await
+            .context("Failed to check the suppression list")
+            .map_err(e500)?
+        {
+            skipped.push(SkippedRow {
+                line,
+                email,
+                reason: "The address is on the suppression list.".into(),
+            });
+            continue;
+        }
+        let outcome = import_subscriber(&mut transaction, &row, list_id, form.mode.0)
+            .await
+            .context("Failed to import a subscriber")
+            .map_err(e500)?;
+        match outcome {
+            ImportOutcome::Imported => n_imported += 1,
+            ImportOutcome::AddedToList => n_added += 1,
+            ImportOutcome::AlreadyOnList => n_existing += 1,
+            ImportOutcome::Left(reason) => skipped.push(SkippedRow {
+                line,
+                email,
+                reason: reason.into(),
+            }),
+        }
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to import subscribers")
+        .map_err(e500)?;
+
+    let summary = format!(
+        "{} subscribers have been imported{}, {} existing subscribers have been added to the list, \
+        {} were already on it and {} rows were skipped.",
+        n_imported,
+        match form.mode.0 {
+            ImportMode::Confirmed => " as confirmed",
+            ImportMode::SendConfirmation => " and will receive a confirmation email",
+        },
+        n_added,
+        n_existing,
+        skipped.len()
+    );
+    let mut rows = String::new();
+    for s in skipped {
+        writeln!(
+            rows,
+            r#"<tr>
+                <td>{}</td>
+                <td>{}</td>
+                <td>{}</td>
+            </tr>"#,
+            s.line,
+            encode_minimal(&s.email),
+            encode_minimal(&s.reason),
+        )
+        .unwrap();
+    }
+    let body = include_str!("./import_report.html")
+        .replace("{summary}", &summary)
+        .replace("{rows}", &rows);
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
+
+enum ImportOutcome {
+    Imported,
+    /// The address was known, but not on the list
+    AddedToList,
+    AlreadyOnList,
+    /// The subscriber left or can't be reached: only they can sign up again
+    Left(&'static str),
+}
+
+struct ExistingSubscriber {
+    id: Uuid,
+    status: String,
+    /// Status of their membership in the list being imported into, if any
+    list_status: Option<String>,
+}
+
+/// Adds a subscriber to the list, creating them unless the address is already known
+async fn import_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    row: &ValidRow,
+    list_id: Uuid,
+    mode: ImportMode,
+) -> Result<ImportOutcome, anyhow::Error> {
+    let existing = sqlx::query_as!(
+        ExistingSubscriber,
+        r#"
+        SELECT s.id, s.status, ls.status as "list_status?"
+        FROM subscriptions s
+        LEFT JOIN list_subscriptions ls ON ls.subscriber_id = s.id AND ls.list_id = $2
+        WHERE s.email = $1
+        "#,
+        row.subscriber.email.as_ref(),
+        list_id
+    )
+    .fetch_optional(transaction.as_mut())
+    .await?;
+    let Some(existing) = existing else {
+        insert_imported_subscriber(transaction, row, list_id, mode).await?;
+        return Ok(ImportOutcome::Imported);
+    };
+
+    match (existing.status.as_str(), existing.list_status.as_deref()) {
+        ("unsubscribed", _) => return Ok(ImportOutcome::Left("The subscriber has unsubscribed.")),
+        ("bounced", _) => return Ok(ImportOutcome::Left("The address has bounced.")),
+        ("complained", _) => {
+            return Ok(ImportOutcome::Left(
+                "The subscriber has reported an issue as spam.",
+            ))
+        }
+        (_, Some("unsubscribed")) => {
+            return Ok(ImportOutcome::Left("The subscriber has left this list."))
+        }
+        (_, Some("confirmed")) => return Ok(ImportOutcome::AlreadyOnList),
+        (_, Some("pending_confirmation")) if mode == ImportMode::SendConfirmation => {
+            return Ok(ImportOutcome::AlreadyOnList)
+        }
+        _ => {}
+    }
+    match mode {
+        ImportMode::Confirmed => {
+            sqlx::query!(
+                r#"
+                INSERT INTO list_subscriptions (list_id, subscriber_id, status, subscribed_at)
+                VALUES ($1, $2, 'confirmed', now())
+                ON CONFLICT (list_id, subscriber_id) DO UPDATE SET status = 'confirmed'
+                "#,
+                list_id,
+                existing.id
+            )
+            .execute(transaction.as_mut())
+            .await?;
+            sqlx::query!(
+                "UPDATE subscriptions SET status = 'confirmed' WHERE id = $1",
+                existing.id
+            )
+            .execute(transaction.as_mut())
+            .await?;
+        }
+        ImportMode::SendConfirmation => {
+            join_list(transaction.as_mut(), list_id, existing.id).await?;
+            enqueue_confirmation_email(transaction.as_mut(), existing.id).await?;
+        }
+    }
+    tag_subscriber(transaction.as_mut(), existing.id, &row.tags).await?;
+    Ok(ImportOutcome::AddedToList)
+}
+
+async fn insert_imported_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    row: &ValidRow,
+    list_id: Uuid,
+    mode: ImportMode,
+) -> Result<(), anyhow::Error> {
+    let status = match mode {
+        ImportMode::Confirmed => "confirmed",
+        ImportMode::SendConfirmation => "pending_confirmation",
+    };
+    let subscriber_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, $2, $3, now(), $4)
+        "#,
+        subscriber_id,
+        row.subscriber.email.as_ref(),
+        row.subscriber.name.as_ref(),
+        status
+    )
+    .execute(transaction.as_mut())
+    .await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO list_subscriptions (list_id, subscriber_id, status, subscribed_at)
+        VALUES ($1, $2, $3, now())
+        "#,
+        list_id,
+        subscriber_id,
+        status
+    )
+    .execute(transaction.as_mut())
+    .await?;
+    tag_subscriber(transaction.as_mut(), subscriber_id, &row.tags).await?;
+    if mode == ImportMode::SendConfirmation {
+        enqueue_confirmation_email(transaction.as_mut(), subscriber_id).await?;
+    }
+    Ok(())
+}
+
+#[derive(serde::Deserialize)]
+pub struct ErasureData {
+    email: String,
+}
+
+/// Removes an address from every table, to answer an erasure request
+#[tracing::instrument(name = "Answer an erasure request", skip_all)]
+pub async fn erase_subscriber(
+    form: web::Form<ErasureData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let erased = erase_subscriber_data(&pool, form.email.trim())
+        .await
+        .context("Failed to erase the data of the subscriber")
+        .map_err(e500)?;
+
+    if erased {
+        FlashMessage::info("The address has been erased.").send();
+    } else {
+        FlashMessage::error("Nothing is stored about this address.").send();
+    }
+    Ok(see_other("/admin/subscribers/data"))
+}
+
+#[tracing::instrument(name = "Confirm a subscriber manually", skip(pool))]
+pub async fn confirm_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let page = format!("/admin/subscribers/{}", subscriber_id);
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let Some(status) = sqlx::query!(
+        "SELECT status FROM subscriptions WHERE id = $1 FOR UPDATE",
+        subscriber_id
+    )
+    .fetch_optional(transaction.as_mut())
+    .await
+    .context("Failed to retrieve the subscriber")
+    .map_err(e500)?
+    .map(|r| r.status) else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    // Someone who left must opt in again themselves
+    if status != "pending_confirmation" {
+        FlashMessage::error("Only subscribers waiting for confirmation can be confirmed.").send();
+        return Ok(see_other(&page));
+    }
+    mark_subscriber_confirmed(&mut transaction, subscriber_id)
+        .await
+        .context("Failed to update the subscriber status to 'confirmed'")
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to confirm a subscriber")
+        .map_err(e500)?;
+
+    FlashMessage::info("The subscriber has been confirmed.").send();
+    Ok(see_other(&page))
+}
+
+#[tracing::instrument(name = "Unsubscribe a subscriber manually", skip(pool))]
+pub async fn unsubscribe_subscriber_manually(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    unsubscribe_subscriber(&pool, subscriber_id).await?;
+
+    FlashMessage::info("The subscriber has been unsubscribed.").send();
+    Ok(see_other(&format!("/admin/subscribers/{}", subscriber_id)))
+}
+
+/// Removes the subscriber without suppressing their address, unlike an erasure request
+#[tracing::instrument(name = "Delete a subscriber", skip(pool))]
+pub async fn delete_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let removed = remove_subscriber(&pool, subscriber_id.into_inner())
+        .await
+        .context("Failed to delete the subscriber")
+        .map_err(e500)?;
+    if !removed {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+
+    FlashMessage::info("The subscriber has been deleted.").send();
+    Ok(see_other("/admin/subscribers"))
+}
@@ -0,0 +1,116 @@
+use actix_web::{
+    http::header::{ContentDisposition, ContentType, DispositionParam, DispositionType},
+    web, HttpResponse,
+};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::{encode_attribute, encode_minimal};
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::{suppression_list::SuppressionReason, utils::e500};
+
+struct Suppression {
+    email: String,
+    reason: String,
+    note: Option<String>,
+    created_at: DateTime<Utc>,
+}
+
+pub async fn suppressions(
+    pool: web::Data<PgPool>,
+    messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let mut reason_options = String::new();
+    for reason in SuppressionReason::ALL {
+        writeln!(
+            reason_options,
+            r#"<option value="{0}">{0}</option>"#,
+            reason.as_str()
+        )
+        .unwrap();
+    }
+
+    let mut rows = String::new();
+    for s in get_suppressions(&pool).await.map_err(e500)? {
+        writeln!(
+            rows,
+            r#"<tr>
+                <td>{email}</td>
+                <td>{reason}</td>
+                <td>{note}</td>
+                <td>{created_at}</td>
+                <td>
+                    <form action="/admin/suppressions/remove" method="post">
+                        <input type="hidden" name="email" value="{email_attr}">
+                        <button type="submit">Remove</button>
+                    </form>
+                </td>
+            </tr>"#,
+            email = encode_minimal(&s.email),
+            email_attr = encode_attribute(&s.email),
+            reason = encode_minimal(&s.reason),
+            note = encode_minimal(s.note.as_deref().unwrap_or_default()),
+            created_at = s.created_at.to_rfc3339(),
+        )
+        .unwrap();
+    }
+
+    let body = include_str!("./suppressions.html")
+        .replace("{messages}", &msg_html)
+        .replace("{reason_options}", &reason_options)
+        .replace("{rows}", &rows);
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
+
+pub async fn export_suppressions(
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer
+        .write_record(["email", "reason", "note", "created_at"])
+        .map_err(e500)?;
+    for s in get_suppressions(&pool).await.map_err(e500)? {
+        writer
+            .write_record([
+                s.email,
+                s.reason,
+                s.note.unwrap_or_default(),
+                s.created_at.to_rfc3339(),
+            ])
+            .map_err(e500)?;
+    }
+    let body = writer.into_inner().map_err(e500)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/csv; charset=utf-8")
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename("suppressions.csv".into())],
+        })
+        .body(body))
+}
+
+async fn get_suppressions(pool: &PgPool) -> Result<Vec<Suppression>, anyhow::Error> {
+    let suppressions = sqlx::query_as!(
+        Suppression,
+        r#"
+        SELECT email, reason, note, created_at
+        FROM suppressions
+        ORDER BY created_at DESC, email
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the suppression list")?;
+    Ok(suppressions)
+}
@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::{export_suppressions, suppressions};
+pub use post::{add_suppression, import_suppressions, remove_suppression};
@@ -0,0 +1,141 @@
+use actix_multipart::form::{bytes::Bytes, MultipartForm};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+
+use crate::{
+    domain::SubscriberEmail,
+    suppression_list::{suppress, unsuppress, SuppressionReason},
+    utils::{e500, see_other},
+};
+
+#[derive(serde::Deserialize)]
+pub struct SuppressionData {
+    email: String,
+    reason: SuppressionReason,
+    note: String,
+}
+
+#[tracing::instrument(name = "Add an address to the suppression list", skip(form, pool))]
+pub async fn add_suppression(
+    form: web::Form<SuppressionData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = match SubscriberEmail::parse(form.email.trim()) {
+        Ok(email) => email,
+        Err(_) => {
+            FlashMessage::error("The address is not a valid email.").send();
+            return Ok(see_other("/admin/suppressions"));
+        }
+    };
+    let added = suppress(pool.as_ref(), email.as_ref(), form.reason, Some(&form.note))
+        .await
+        .context("Failed to add the address to the suppression list")
+        .map_err(e500)?;
+
+    if added {
+        FlashMessage::info("The address has been added to the suppression list.").send();
+    } else {
+        FlashMessage::error("The address is already on the suppression list.").send();
+    }
+    Ok(see_other("/admin/suppressions"))
+}
+
+#[derive(serde::Deserialize)]
+pub struct RemovalData {
+    email: String,
+}
+
+#[tracing::instrument(name = "Remove an address from the suppression list", skip(form, pool))]
+pub async fn remove_suppression(
+    form: web::Form<RemovalData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let removed = unsuppress(pool.as_ref(), &form.email)
+        .await
+        .context("Failed to remove the address from the suppression list")
+        .map_err(e500)?;
+
+    if removed {
+        FlashMessage::info("The address has been removed from the suppression list.").send();
+    } else {
+        FlashMessage::error("The address was not on the suppression list.").send();
+    }
+    Ok(see_other("/admin/suppressions"))
+}
+
+#[derive(MultipartForm)]
+pub struct ImportForm {
+    file: Bytes,
+}
+
+#[derive(serde::Deserialize)]
+struct ImportedRow {
+    email: String,
+    reason: Option<SuppressionReason>,
+    note: Option<String>,
+}
+
+#[tracing::instrument(name = "Import a suppression list", skip(form, pool))]
+pub async fn import_suppressions(
+    MultipartForm(form): MultipartForm<ImportForm>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(form.file.data.as_ref());
+    let has_email_column = reader
+        .headers()
+        .map(|h| h.iter().any(|column| column == "email"))
+        .unwrap_or(false);
+    if !has_email_column {
+        FlashMessage::error("The CSV file must have a header with an email column.").send();
+        return Ok(see_other("/admin/suppressions"));
+    }
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let (mut n_added, mut n_existing, mut n_invalid) = (0, 0, 0);
+    for row in reader.deserialize::<ImportedRow>() {
+        // Unknown reasons, malformed lines and invalid addresses are skipped
+        let Ok(row) = row else {
+            n_invalid += 1;
+            continue;
+        };
+        let Ok(email) = SubscriberEmail::parse(&row.email) else {
+            n_invalid += 1;
+            continue;
+        };
+        let added = suppress(
+            &mut *transaction,
+            email.as_ref(),
+            row.reason.unwrap_or(SuppressionReason::Manual),
+            row.note.as_deref(),
+        )
+        .await
+        .context("Failed to add an address to the suppression list")
+        .map_err(e500)?;
+        if added {
+            n_added += 1;
+        } else {
+            n_existing += 1;
+        }
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to import the suppression list")
+        .map_err(e500)?;
+
+    FlashMessage::info(format!(
+        "{} addresses have been added to the suppression list, \
+        {} were already on it and {} invalid rows were skipped.",
+        n_added, n_existing, n_invalid
+    ))
+    .send();
+    Ok(see_other("/admin/suppressions"))
+}
@@ -0,0 +1,53 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::utils::e500;
+
+pub async fn tags(
+    pool: web::Data<PgPool>,
+    messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let tags = sqlx::query!(
+        r#"
+        SELECT tag, COUNT(*) as "n_subscribers!"
+        FROM subscriber_tags
+        GROUP BY tag
+        ORDER BY tag
+        "#
+    )
+    .fetch_all(pool.as_ref())
+    .await
+    .context("Failed to retrieve the tags")
+    .map_err(e500)?;
+
+    let mut rows = String::new();
+    for t in tags {
+        writeln!(
+            rows,
+            r#"<tr>
+                <td>{}</td>
+                <td>{}</td>
+            </tr>"#,
+            encode_minimal(&t.tag),
+            t.n_subscribers,
+        )
+        .unwrap();
+    }
+
+    let body = include_str!("./tags.html")
+        .replace("{messages}", &msg_html)
+        .replace("{rows}", &rows);
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::tags;
+pub use post::{add_tags, import_tags, remove_tag};
@@ -0,0 +1,174 @@
+use actix_multipart::form::{bytes::Bytes, MultipartForm};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use htmlescape::encode_minimal;
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+use crate::{
+    domain::{SubscriberEmail, SubscriberTag},
+    segments::{tag_subscriber, untag_subscriber},
+    utils::{e500, see_other},
+};
+
+async fn get_subscriber_id(
+    executor: impl PgExecutor<'_>,
+    email: &str,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let row = sqlx::query!(
+        "SELECT id FROM subscriptions WHERE email = $1",
+        SubscriberEmail::normalise(email)
+    )
+    .fetch_optional(executor)
+    .await?;
+    Ok(row.map(|r| r.id))
+}
+
+fn unknown_subscriber_message() -> FlashMessage {
+    FlashMessage::error("There is no subscriber with this address.")
+}
+
+#[derive(serde::Deserialize)]
+pub struct TagsData {
+    email: String,
+    tags: String,
+}
+
+#[tracing::instrument(name = "Tag a subscriber", skip(form, pool))]
+pub async fn add_tags(
+    form: web::Form<TagsData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let tags = match SubscriberTag::parse_list(&form.tags) {
+        Ok(tags) if tags.is_empty() => {
+            FlashMessage::error("Enter at least one tag.").send();
+            return Ok(see_other("/admin/tags"));
+        }
+        Ok(tags) => tags,
+        Err(e) => {
+            FlashMessage::error(encode_minimal(&e)).send();
+            return Ok(see_other("/admin/tags"));
+        }
+    };
+    let Some(subscriber_id) = get_subscriber_id(pool.as_ref(), &form.email)
+        .await
+        .context("Failed to retrieve the subscriber")
+        .map_err(e500)?
+    else {
+        unknown_subscriber_message().send();
+        return Ok(see_other("/admin/tags"));
+    };
+    tag_subscriber(pool.as_ref(), subscriber_id, &tags)
+        .await
+        .context("Failed to tag the subscriber")
+        .map_err(e500)?;
+
+    FlashMessage::info("The subscriber has been tagged.").send();
+    Ok(see_other("/admin/tags"))
+}
+
+#[derive(serde::Deserialize)]
+pub struct UntagData {
+    email: String,
+    tag: String,
+}
+
+#[tracing::instrument(name = "Untag a subscriber", skip(form, pool))]
+pub async fn remove_tag(
+    form: web::Form<UntagData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(subscriber_id) = get_subscriber_id(pool.as_ref(), &form.email)
+        .await
+        .context("Failed to retrieve the subscriber")
+        .map_err(e500)?
+    else {
+        unknown_subscriber_message().send();
+        return Ok(see_other("/admin/tags"));
+    };
+    let removed = untag_subscriber(pool.as_ref(), subscriber_id, &form.tag)
+        .await
+        .context("Failed to untag the subscriber")
+        .map_err(e500)?;
+
+    if removed {
+        FlashMessage::info("The tag has been removed.").send();
+    } else {
+        FlashMessage::error("The subscriber doesn't have this tag.").send();
+    }
+    Ok(see_other("/admin/tags"))
+}
+
+#[derive(MultipartForm)]
+pub struct ImportForm {
+    file: Bytes,
+}
+
+#[derive(serde::Deserialize)]
+struct ImportedRow {
+    email: String,
+    tags: String,
+}
+
+#[tracing::instrument(name = "Import subscriber tags", skip(form, pool))]
+pub async fn import_tags(
+    MultipartForm(form): MultipartForm<ImportForm>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(form.file.data.as_ref());
+    let has_columns = reader
+        .headers()
+        .map(|h| h.iter().any(|c| c == "email") && h.iter().any(|c| c == "tags"))
+        .unwrap_or(false);
+    if !has_columns {
+        FlashMessage::error("The CSV file must have a header with email and tags columns.").send();
+        return Ok(see_other("/admin/tags"));
+    }
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let (mut n_tagged, mut n_unknown, mut n_invalid) = (0, 0, 0);
+    for row in reader.deserialize::<ImportedRow>() {
+        // Malformed lines and invalid tags are skipped
+        let Ok(row) = row else {
+            n_invalid += 1;
+            continue;
+        };
+        let Ok(tags) = SubscriberTag::parse_list(&row.tags) else {
+            n_invalid += 1;
+            continue;
+        };
+        let Some(subscriber_id) = get_subscriber_id(&mut *transaction, &row.email)
+            .await
+            .context("Failed to retrieve a subscriber")
+            .map_err(e500)?
+        else {
+            n_unknown += 1;
+            continue;
+        };
+        tag_subscriber(&mut *transaction, subscriber_id, &tags)
+            .await
+            .context("Failed to tag a subscriber")
+            .map_err(e500)?;
+        n_tagged += 1;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to import the tags")
+        .map_err(e500)?;
+
+    FlashMessage::info(format!(
+        "{} subscribers have been tagged, \
+        {} rows didn't match any subscriber and {} invalid rows were skipped.",
+        n_tagged, n_unknown, n_invalid
+    ))
+    .send();
+    Ok(see_other("/admin/tags"))
+}
@@ -1,7 +1,98 @@
-use actix_web::{http::header::ContentType, HttpResponse};
+use actix_web::{http::header::ContentType, http::StatusCode, web, HttpResponse};
+use anyhow::Context;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
 
-pub async fn home() -> HttpResponse {
-    HttpResponse::Ok()
+use crate::{
+    mailing_lists::{get_lists, MailingList},
+    utils::e500,
+};
+
+pub async fn home(pool: web::Data<PgPool>) -> Result<HttpResponse, actix_web::Error> {
+    let lists = get_lists(pool.as_ref())
+        .await
+        .context("Failed to retrieve the mailing lists")
+        .map_err(e500)?;
+    Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
-        .body(include_str!("home.html"))
+        .body(home_page(None, &lists)))
+}
+
+/// The home page, with `error` shown right above the subscribe form
+pub(crate) fn home_page(error: Option<&str>, lists: &[MailingList]) -> String {
+    let error = error
+        .map(|e| format!("<p><i>{}</i></p>", encode_minimal(e)))
+        .unwrap_or_default();
+    include_str!("home.html")
+        .replace("{error}", &error)
+        .replace("{subscribe_form}", &subscribe_form(lists))
+}
+
+/// The subscribe form, letting people choose among `lists` if there is more than one.
+/// Without any list, subscribers join the default one.
+pub(crate) fn subscribe_form(lists: &[MailingList]) -> String {
+    let list_choice = match lists {
+        [] => String::new(),
+        [list] => format!(
+            r#"<input type="hidden" name="list_id" value="{}">"#,
+            list.list_id
+        ),
+        lists => {
+            let mut options = String::new();
+            for list in lists {
+                let label = if list.description.is_empty() {
+                    list.name.clone()
+                } else {
+                    format!("{} - {}", list.name, list.description)
+                };
+                write!(
+                    options,
+                    r#"<option value="{}">{}</option>"#,
+                    list.list_id,
+                    encode_minimal(&label)
+                )
+                .unwrap();
+            }
+            format!(
+                r#"<label>List:<br>
+            <select name="list_id">{}</select>
+        </label>
+        <br>"#,
+                options
+            )
+        }
+    };
+    include_str!("subscribe_form.html").replace("{list_choice}", &list_choice)
+}
+
+/// What a (would-be) subscriber sees along the way, from signing up to confirming
+#[derive(Clone, Copy)]
+pub(crate) enum SubscriberPage {
+    Subscribed,
+    Confirmed,
+    AlreadyConfirmed,
+    InvalidToken,
+    /// Has an `{email}` placeholder to pre-fill the resend form with
+    LinkExpired,
+    ConfirmationResent,
+}
+
+impl SubscriberPage {
+    pub(crate) fn template(self) -> &'static str {
+        match self {
+            SubscriberPage::Subscribed => include_str!("subscribed.html"),
+            SubscriberPage::Confirmed => include_str!("confirmed.html"),
+            SubscriberPage::AlreadyConfirmed => include_str!("already_confirmed.html"),
+            SubscriberPage::InvalidToken => include_str!("invalid_token.html"),
+            SubscriberPage::LinkExpired => include_str!("link_expired.html"),
+            SubscriberPage::ConfirmationResent => include_str!("confirmation_resent.html"),
+        }
+    }
+
+    pub(crate) fn response(self, status: StatusCode) -> HttpResponse {
+        HttpResponse::build(status)
+            .content_type(ContentType::html())
+            .body(self.template())
+    }
 }
@@ -0,0 +1,206 @@
+use std::time::{Duration, SystemTime};
+
+use actix_web::{
+    http::header::{
+        EntityTag, Header, HttpDate, IfModifiedSince, IfNoneMatch, CACHE_CONTROL, CONTENT_TYPE,
+        ETAG, IF_NONE_MATCH, LAST_MODIFIED,
+    },
+    web, HttpRequest, HttpResponse,
+};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::encode_minimal;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use super::render_for_the_public;
+use crate::{startup::ApplicationBaseUrl, utils::e500};
+
+const FEED_LENGTH: i64 = 20;
+
+struct FeedEntry {
+    newsletter_issue_id: Uuid,
+    title: String,
+    html_content: String,
+    published_at: DateTime<Utc>,
+}
+
+pub async fn rss_feed(
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let entries = get_feed_entries(&pool).await.map_err(e500)?;
+    let base_url = &base_url.0;
+
+    let mut items = String::new();
+    for e in &entries {
+        let (title, html_content) =
+            render_for_the_public(base_url, e.newsletter_issue_id, &e.title, &e.html_content);
+        writeln!(
+            items,
+            r#"<item>
+            <title>{title}</title>
+            <link>{link}</link>
+            <guid isPermaLink="false">urn:uuid:{issue_id}</guid>
+            <pubDate>{published_at}</pubDate>
+            <description>{content}</description>
+        </item>"#,
+            title = xml_escape(&title),
+            link = xml_escape(&format!("{}/issues/{}", base_url, e.newsletter_issue_id)),
+            issue_id = e.newsletter_issue_id,
+            published_at = e.published_at.to_rfc2822(),
+            content = xml_escape(&html_content),
+        )
+        .unwrap();
+    }
+    let last_build_date = entries
+        .first()
+        .map(|e| {
+            format!(
+                "<lastBuildDate>{}</lastBuildDate>",
+                e.published_at.to_rfc2822()
+            )
+        })
+        .unwrap_or_default();
+
+    let body = include_str!("./feed.rss")
+        .replace("{last_build_date}", &last_build_date)
+        .replace("{base_url}", &xml_escape(base_url))
+        .replace("{items}", &items);
+    Ok(feed_response(
+        &request,
+        "application/rss+xml; charset=utf-8",
+        body,
+        entries.first().map(|e| e.published_at),
+    ))
+}
+
+pub async fn atom_feed(
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let entries = get_feed_entries(&pool).await.map_err(e500)?;
+    let base_url = &base_url.0;
+
+    let mut items = String::new();
+    for e in &entries {
+        let (title, html_content) =
+            render_for_the_public(base_url, e.newsletter_issue_id, &e.title, &e.html_content);
+        writeln!(
+            items,
+            r#"<entry>
+        <title>{title}</title>
+        <link href="{link}"/>
+        <id>urn:uuid:{issue_id}</id>
+        <published>{published_at}</published>
+        <updated>{published_at}</updated>
+        <content type="html">{content}</content>
+    </entry>"#,
+            title = xml_escape(&title),
+            link = xml_escape(&format!("{}/issues/{}", base_url, e.newsletter_issue_id)),
+            issue_id = e.newsletter_issue_id,
+            published_at = e.published_at.to_rfc3339(),
+            content = xml_escape(&html_content),
+        )
+        .unwrap();
+    }
+    let updated = entries
+        .first()
+        .map_or(DateTime::UNIX_EPOCH, |e| e.published_at);
+
+    let body = include_str!("./feed.atom")
+        .replace("{updated}", &updated.to_rfc3339())
+        .replace("{base_url}", &xml_escape(base_url))
+        .replace("{entries}", &items);
+    Ok(feed_response(
+        &request,
+        "application/atom+xml; charset=utf-8",
+        body,
+        entries.first().map(|e| e.published_at),
+    ))
+}
+
+/// Latest issues in the public archive, newest first
+async fn get_feed_entries(pool: &PgPool) -> Result<Vec<FeedEntry>, anyhow::Error> {
+    let entries = sqlx::query_as!(
+        FeedEntry,
+        r#"
+        SELECT newsletter_issue_id, title, html_content, published_at as "published_at!"
+        FROM newsletter_issues
+        WHERE status IN ('sending', 'sent') AND NOT subscribers_only
+        ORDER BY published_at DESC
+        LIMIT $1
+        "#,
+        FEED_LENGTH
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the feed entries")?;
+    Ok(entries)
+}
+
+/// Escapes text for XML, dropping the control characters XML 1.0 doesn't allow
+fn xml_escape(s: &str) -> String {
+    let allowed: String = s
+        .chars()
+        .filter(|c| !c.is_control() || matches!(c, '\t' | '\n' | '\r'))
+        .collect();
+    encode_minimal(&allowed)
+}
+
+/// Wraps a feed with caching headers, answering `304 Not Modified`
+/// if the client already has the current version.
+fn feed_response(
+    request: &HttpRequest,
+    content_type: &str,
+    body: String,
+    last_modified: Option<DateTime<Utc>>,
+) -> HttpResponse {
+    // Issues can be edited or hidden after publication: the ETag follows the content itself
+    let digest = Sha256::digest(body.as_bytes());
+    let etag = EntityTag::new_strong(digest[..16].iter().fold(String::new(), |mut tag, b| {
+        write!(tag, "{:02x}", b).unwrap();
+        tag
+    }));
+    let last_modified = last_modified
+        .map(|t| SystemTime::UNIX_EPOCH + Duration::from_secs(t.timestamp().max(0) as u64));
+
+    // If-None-Match takes precedence over If-Modified-Since (RFC 9110)
+    let not_modified = if request.headers().contains_key(IF_NONE_MATCH) {
+        match IfNoneMatch::parse(request) {
+            Ok(IfNoneMatch::Any) => true,
+            Ok(IfNoneMatch::Items(tags)) => tags.iter().any(|t| t.weak_eq(&etag)),
+            Err(_) => false,
+        }
+    } else {
+        match (IfModifiedSince::parse(request), last_modified) {
+            (Ok(IfModifiedSince(since)), Some(last_modified)) => {
+                last_modified <= SystemTime::from(since)
+            }
+            _ => false,
+        }
+    };
+
+    let mut response = if not_modified {
+        HttpResponse::NotModified()
+    } else {
+        HttpResponse::Ok()
+    };
+    response
+        .insert_header((ETAG, etag.to_string()))
+        .insert_header((CACHE_CONTROL, "public, max-age=300"));
+    if let Some(last_modified) = last_modified {
+        response.insert_header((LAST_MODIFIED, HttpDate::from(last_modified).to_string()));
+    }
+    if not_modified {
+        response.finish()
+    } else {
+        response
+            .insert_header((CONTENT_TYPE, content_type))
+            .body(body)
+    }
+}
@@ -0,0 +1,154 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use super::render_for_the_public;
+use crate::{
+    mailing_lists::{get_list, get_lists},
+    routes::home::subscribe_form,
+    startup::ApplicationBaseUrl,
+    utils::e500,
+};
+
+const ISSUES_PER_PAGE: i64 = 20;
+
+#[derive(serde::Deserialize)]
+pub struct ArchiveQuery {
+    page: Option<u32>,
+}
+
+struct PublishedIssue {
+    newsletter_issue_id: Uuid,
+    title: String,
+    published_at: DateTime<Utc>,
+}
+
+pub async fn published_issues(
+    query: web::Query<ArchiveQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let page = query.page.unwrap_or(1).max(1);
+    // One more than needed, to know whether there is an older page
+    let mut issues = sqlx::query_as!(
+        PublishedIssue,
+        r#"
+        SELECT newsletter_issue_id, title, published_at as "published_at!"
+        FROM newsletter_issues
+        WHERE status IN ('sending', 'sent') AND NOT subscribers_only
+        ORDER BY published_at DESC
+        LIMIT $1 OFFSET $2
+        "#,
+        ISSUES_PER_PAGE + 1,
+        (i64::from(page) - 1) * ISSUES_PER_PAGE
+    )
+    .fetch_all(pool.as_ref())
+    .await
+    .context("Failed to retrieve the published issues")
+    .map_err(e500)?;
+    let has_older = issues.len() as i64 > ISSUES_PER_PAGE;
+    let lists = get_lists(pool.as_ref())
+        .await
+        .context("Failed to retrieve the mailing lists")
+        .map_err(e500)?;
+    issues.truncate(ISSUES_PER_PAGE as usize);
+
+    let mut items = String::new();
+    for i in issues {
+        writeln!(
+            items,
+            r#"<li>{} - <a href="/issues/{}">{}</a></li>"#,
+            i.published_at.format("%Y-%m-%d"),
+            i.newsletter_issue_id,
+            encode_minimal(&i.title),
+        )
+        .unwrap();
+    }
+    if items.is_empty() {
+        items.push_str("<li>No issues here yet.</li>");
+    }
+
+    let mut pagination = vec![];
+    if page > 1 {
+        pagination.push(format!(
+            r#"<a href="/issues?page={}">&lt;- Newer issues</a>"#,
+            page - 1
+        ));
+    }
+    if has_older {
+        pagination.push(format!(
+            r#"<a href="/issues?page={}">Older issues -&gt;</a>"#,
+            page + 1
+        ));
+    }
+
+    let body = include_str!("./issues.html")
+        .replace("{issues}", &items)
+        .replace("{pagination}", &pagination.join(" | "))
+        .replace("{subscribe_form}", &subscribe_form(&lists));
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
+
+struct IssueContent {
+    list_id: Uuid,
+    title: String,
+    html_content: String,
+    published_at: DateTime<Utc>,
+}
+
+pub async fn published_issue(
+    newsletter_issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    let issue = sqlx::query_as!(
+        IssueContent,
+        r#"
+        SELECT list_id, title, html_content, published_at as "published_at!"
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+            AND status IN ('sending', 'sent')
+            AND NOT subscribers_only
+        "#,
+        newsletter_issue_id
+    )
+    .fetch_optional(pool.as_ref())
+    .await
+    .context("Failed to retrieve the issue")
+    .map_err(e500)?;
+
+    let Some(issue) = issue else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+
+    // Readers of an issue subscribe to the list it was published in
+    let list = get_list(pool.as_ref(), issue.list_id)
+        .await
+        .context("Failed to retrieve the list of the issue")
+        .map_err(e500)?;
+
+    let (title, html_content) = render_for_the_public(
+        &base_url.0,
+        newsletter_issue_id,
+        &issue.title,
+        &issue.html_content,
+    );
+
+    let body = include_str!("./issue.html")
+        .replace("{title}", &encode_minimal(&title))
+        .replace(
+            "{published_at}",
+            &issue.published_at.format("%Y-%m-%d").to_string(),
+        )
+        .replace("{subscribe_form}", &subscribe_form(list.as_slice()))
+        .replace("{html_content}", &html_content);
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
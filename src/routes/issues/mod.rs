@@ -0,0 +1,46 @@
+mod feed;
+mod get;
+
+pub use feed::{atom_feed, rss_feed};
+pub use get::{published_issue, published_issues};
+
+use uuid::Uuid;
+
+use crate::issue_template::{render_template, ContentFormat, TemplateVariables};
+
+/// Renders the title and the HTML content of an issue for the archive and the feeds.
+/// Only the content of the `<body>` is kept, to be embedded in a page (or a feed entry).
+fn render_for_the_public(
+    base_url: &str,
+    newsletter_issue_id: Uuid,
+    title: &str,
+    html_content: &str,
+) -> (String, String) {
+    // Readers of the archive are not subscribers: there is nobody
+    // to greet by name, nor a subscription to cancel.
+    let variables = TemplateVariables {
+        name: "reader".into(),
+        unsubscribe_url: "#".into(),
+        archive_url: format!("{}/issues/{}", base_url, newsletter_issue_id),
+    };
+    let title = render_template(title, &variables, ContentFormat::Text);
+    let html_content = render_template(html_content, &variables, ContentFormat::Html);
+    (title, html_body(&html_content).to_owned())
+}
+
+/// Content of the `<body>` of an html document, or `html` itself if it is just a fragment
+fn html_body(html: &str) -> &str {
+    let lowercase = html.to_ascii_lowercase();
+    let start = lowercase
+        .find("<body")
+        .and_then(|i| lowercase[i..].find('>').map(|j| i + j + 1));
+    match start {
+        Some(start) => {
+            let end = lowercase[start..]
+                .rfind("</body>")
+                .map_or(html.len(), |i| start + i);
+            &html[start..end]
+        }
+        None => html,
+    }
+}
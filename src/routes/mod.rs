@@ -1,13 +1,23 @@
 mod admin;
 mod health_check;
 mod home;
+mod issues;
 mod login;
+mod preferences;
 mod subscriptions;
 mod subscriptions_confirm;
+mod tracking;
+mod unsubscribe;
+mod webhooks;
 
 pub use admin::*;
 pub use health_check::*;
 pub use home::*;
+pub use issues::*;
 pub use login::*;
+pub use preferences::*;
 pub use subscriptions::*;
 pub use subscriptions_confirm::*;
+pub use tracking::*;
+pub use unsubscribe::*;
+pub use webhooks::*;
@@ -0,0 +1,126 @@
+use actix_web::{
+    http::{header::ContentType, StatusCode},
+    web, HttpResponse,
+};
+use anyhow::Context;
+use chrono::{TimeDelta, Utc};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+
+use crate::{signed_token::TokenSigner, startup::ConfirmationTokenTtl};
+
+use super::{email_change_from_token, PreferencesError};
+
+#[derive(serde::Deserialize)]
+pub struct Parameters {
+    token: String,
+}
+
+#[tracing::instrument(
+    name = "Confirming an email change",
+    skip(parameters, pool, signer, ttl)
+)]
+pub async fn confirm_email_change(
+    parameters: web::Query<Parameters>,
+    pool: web::Data<PgPool>,
+    signer: web::Data<TokenSigner>,
+    ttl: web::Data<ConfirmationTokenTtl>,
+) -> Result<HttpResponse, PreferencesError> {
+    let change = email_change_from_token(&signer, &parameters.token)?;
+    let expires_at = TimeDelta::from_std(ttl.0)
+        .ok()
+        .and_then(|ttl| change.issued_at.checked_add_signed(ttl));
+    if expires_at.is_some_and(|expires_at| expires_at < Utc::now()) {
+        return Ok(failure(
+            StatusCode::GONE,
+            "This link has expired. Request the change again from your preferences page.",
+        ));
+    }
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let current_email = sqlx::query!(
+        "SELECT email FROM subscriptions WHERE id = $1 FOR UPDATE",
+        change.subscriber_id
+    )
+    .fetch_optional(transaction.as_mut())
+    .await
+    .context("Failed to retrieve the subscriber associated with the given token")?
+    .ok_or(PreferencesError::SubscriberNotFound)?
+    .email;
+    // Following the link twice is fine
+    if current_email != change.new_email {
+        // Replaying an older link must not revert a later change
+        if current_email != change.old_email {
+            return Ok(failure(
+                StatusCode::GONE,
+                "This link is no longer valid: your address has changed since it was sent.",
+            ));
+        }
+        let taken = sqlx::query!(
+            "SELECT id FROM subscriptions WHERE email = $1",
+            change.new_email
+        )
+        .fetch_optional(transaction.as_mut())
+        .await
+        .context("Failed to look for a subscriber with the new email")?
+        .is_some();
+        if taken {
+            return Ok(already_subscribed());
+        }
+        let updated = sqlx::query!(
+            "UPDATE subscriptions SET email = $2 WHERE id = $1",
+            change.subscriber_id,
+            change.new_email
+        )
+        .execute(transaction.as_mut())
+        .await;
+        match updated {
+            Ok(_) => {}
+            // Someone signed up with the address in the meantime
+            Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+                return Ok(already_subscribed());
+            }
+            Err(e) => {
+                return Err(anyhow::Error::new(e)
+                    .context("Failed to update the email of the subscriber")
+                    .into())
+            }
+        }
+
+        // Issues still waiting to be delivered follow the subscriber to their new address
+        sqlx::query!(
+            "UPDATE issue_delivery_queue SET subscriber_email = $2 WHERE subscriber_email = $1",
+            current_email,
+            change.new_email
+        )
+        .execute(transaction.as_mut())
+        .await
+        .context("Failed to move the pending deliveries of the subscriber")?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to change the email of a subscriber")?;
+
+    let body =
+        include_str!("email_changed.html").replace("{email}", &encode_minimal(&change.new_email));
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
+
+fn already_subscribed() -> HttpResponse {
+    failure(
+        StatusCode::CONFLICT,
+        "This address is already subscribed to the newsletter.",
+    )
+}
+
+fn failure(status: StatusCode, reason: &str) -> HttpResponse {
+    HttpResponse::build(status)
+        .content_type(ContentType::html())
+        .body(include_str!("email_change_failed.html").replace("{reason}", reason))
+}
@@ -0,0 +1,121 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use htmlescape::{encode_attribute, encode_minimal};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::{routes::unsubscribe_link, signed_token::TokenSigner, startup::ApplicationBaseUrl};
+
+use super::{subscriber_id_from_token, PreferencesError};
+
+#[tracing::instrument(
+    name = "Showing the preferences of a subscriber",
+    skip(token, pool, signer, base_url, messages)
+)]
+pub async fn preferences_form(
+    token: web::Path<String>,
+    pool: web::Data<PgPool>,
+    signer: web::Data<TokenSigner>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    messages: IncomingFlashMessages,
+) -> Result<HttpResponse, PreferencesError> {
+    let subscriber_id = subscriber_id_from_token(&signer, &token)?;
+    let subscriber = sqlx::query!(
+        "SELECT email, name, status FROM subscriptions WHERE id = $1",
+        subscriber_id
+    )
+    .fetch_optional(pool.as_ref())
+    .await
+    .context("Failed to retrieve the subscriber associated with the given token")?
+    .ok_or(PreferencesError::SubscriberNotFound)?;
+
+    let mut msg_html = String::new();
+    for m in messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(m.content())).unwrap();
+    }
+    let email = encode_minimal(&subscriber.email);
+    let status = match subscriber.status.as_str() {
+        "confirmed" => format!("You receive our newsletter at <b>{}</b>.", email),
+        "pending_confirmation" => format!(
+            "Your subscription with <b>{}</b> is waiting for confirmation.",
+            email
+        ),
+        _ => format!("<b>{}</b> does not receive our newsletter anymore.", email),
+    };
+
+    let lists = if subscriber.status == "confirmed" {
+        list_choices(&pool, subscriber_id, &token).await?
+    } else {
+        "<p>Confirm your subscription to choose the lists you receive.</p>".into()
+    };
+
+    let body = include_str!("preferences.html")
+        .replace("{messages}", &msg_html)
+        .replace("{status}", &status)
+        .replace("{lists}", &lists)
+        .replace("{name}", &encode_attribute(&subscriber.name))
+        .replace("{token}", &encode_attribute(&token))
+        .replace(
+            "{unsubscribe_url}",
+            &encode_attribute(&unsubscribe_link(&base_url.0, &signer, subscriber_id)),
+        );
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
+
+/// A checkbox for each list, ticked for the lists the subscriber receives
+async fn list_choices(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    token: &str,
+) -> Result<String, PreferencesError> {
+    let lists = sqlx::query!(
+        r#"
+        SELECT l.list_id, l.name, l.description, ls.status as "status?"
+        FROM lists l
+        LEFT JOIN list_subscriptions ls ON ls.list_id = l.list_id AND ls.subscriber_id = $1
+        ORDER BY l.created_at, l.list_id
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the lists of the subscriber")?;
+
+    let mut choices = String::new();
+    for l in lists {
+        let description = if l.description.is_empty() {
+            String::new()
+        } else {
+            format!(" - {}", encode_minimal(&l.description))
+        };
+        writeln!(
+            choices,
+            r#"<label>
+            <input type="checkbox" name="{list_id}" value="on"{checked}>
+            {name}{description}
+        </label>
+        <br>"#,
+            list_id = l.list_id,
+            checked = if l.status.as_deref() == Some("confirmed") {
+                " checked"
+            } else {
+                ""
+            },
+            name = encode_minimal(&l.name),
+        )
+        .unwrap();
+    }
+    Ok(format!(
+        r#"<form action="/preferences/{}/lists" method="post">
+        {}
+        <button type="submit">Save</button>
+    </form>"#,
+        encode_attribute(token),
+        choices
+    ))
+}
@@ -0,0 +1,103 @@
+mod confirm_email;
+mod get;
+mod post;
+
+pub use confirm_email::confirm_email_change;
+pub use get::preferences_form;
+pub use post::{change_email, change_lists, change_name};
+
+use actix_web::ResponseError;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::{domain::SubscriberEmail, signed_token::TokenSigner};
+
+const PREFERENCES_TOKEN_PURPOSE: &str = "preferences";
+const EMAIL_CHANGE_TOKEN_PURPOSE: &str = "email-change";
+
+/// Personal link a subscriber can follow to manage their subscription, without logging in
+pub fn preferences_link(base_url: &str, signer: &TokenSigner, subscriber_id: Uuid) -> String {
+    let token = signer.sign(PREFERENCES_TOKEN_PURPOSE, &subscriber_id.to_string());
+    format!("{}/preferences/{}", base_url, token)
+}
+
+/// Link to confirm `new_email` as the address of a subscriber.
+/// It carries the time it was issued at, to expire like subscription confirmation links,
+/// and the address it replaces: once the address has changed, older links stop working.
+fn email_change_link(
+    base_url: &str,
+    signer: &TokenSigner,
+    subscriber_id: Uuid,
+    current_email: &str,
+    new_email: &SubscriberEmail,
+) -> String {
+    let payload = format!(
+        "{} {} {} {}",
+        subscriber_id,
+        Utc::now().timestamp(),
+        current_email,
+        new_email.as_ref()
+    );
+    let token = signer.sign(EMAIL_CHANGE_TOKEN_PURPOSE, &payload);
+    format!("{}/preferences/email/confirm?token={}", base_url, token)
+}
+
+struct EmailChange {
+    subscriber_id: Uuid,
+    issued_at: DateTime<Utc>,
+    old_email: String,
+    new_email: String,
+}
+
+fn email_change_from_token(
+    signer: &TokenSigner,
+    token: &str,
+) -> Result<EmailChange, PreferencesError> {
+    signer
+        .verify(EMAIL_CHANGE_TOKEN_PURPOSE, token)
+        .and_then(|payload| {
+            let mut parts = payload.splitn(4, ' ');
+            let (Some(id), Some(issued_at), Some(old_email), Some(new_email)) =
+                (parts.next(), parts.next(), parts.next(), parts.next())
+            else {
+                anyhow::bail!("The email change token is malformed");
+            };
+            Ok(EmailChange {
+                subscriber_id: Uuid::parse_str(id)?,
+                issued_at: DateTime::from_timestamp(issued_at.parse()?, 0)
+                    .ok_or_else(|| anyhow::anyhow!("The issue time is out of range"))?,
+                old_email: old_email.to_string(),
+                new_email: new_email.to_string(),
+            })
+        })
+        .map_err(PreferencesError::InvalidToken)
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum PreferencesError {
+    #[error("The preferences token is invalid.")]
+    InvalidToken(#[source] anyhow::Error),
+    #[error("No subscriber associated with the given token.")]
+    SubscriberNotFound,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl ResponseError for PreferencesError {
+    fn status_code(&self) -> actix_web::http::StatusCode {
+        match self {
+            PreferencesError::InvalidToken(_) => actix_web::http::StatusCode::BAD_REQUEST,
+            PreferencesError::SubscriberNotFound => actix_web::http::StatusCode::NOT_FOUND,
+            PreferencesError::UnexpectedError(_) => {
+                actix_web::http::StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+}
+
+fn subscriber_id_from_token(signer: &TokenSigner, token: &str) -> Result<Uuid, PreferencesError> {
+    signer
+        .verify(PREFERENCES_TOKEN_PURPOSE, token)
+        .and_then(|payload| Ok(Uuid::parse_str(&payload)?))
+        .map_err(PreferencesError::InvalidToken)
+}
@@ -0,0 +1,204 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::{
+    domain::{SubscriberEmail, SubscriberName},
+    email_client::EmailSender,
+    signed_token::TokenSigner,
+    startup::ApplicationBaseUrl,
+    suppression_list::is_suppressed,
+    utils::see_other,
+};
+
+use super::{email_change_link, subscriber_id_from_token, PreferencesError};
+
+#[derive(serde::Deserialize)]
+pub struct NameData {
+    name: String,
+}
+
+#[tracing::instrument(
+    name = "Changing the name of a subscriber",
+    skip(token, form, pool, signer)
+)]
+pub async fn change_name(
+    token: web::Path<String>,
+    form: web::Form<NameData>,
+    pool: web::Data<PgPool>,
+    signer: web::Data<TokenSigner>,
+) -> Result<HttpResponse, PreferencesError> {
+    let subscriber_id = subscriber_id_from_token(&signer, &token)?;
+    let preferences_url = format!("/preferences/{}", token);
+    let name = match SubscriberName::parse(&form.name) {
+        Ok(name) => name,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other(&preferences_url));
+        }
+    };
+
+    sqlx::query!(
+        "UPDATE subscriptions SET name = $2 WHERE id = $1 RETURNING id",
+        subscriber_id,
+        name.as_ref()
+    )
+    .fetch_optional(pool.as_ref())
+    .await
+    .context("Failed to update the name of the subscriber")?
+    .ok_or(PreferencesError::SubscriberNotFound)?;
+
+    FlashMessage::info("Your name has been updated.").send();
+    Ok(see_other(&preferences_url))
+}
+
+#[derive(serde::Deserialize)]
+pub struct EmailData {
+    email: String,
+}
+
+/// Sends a link to the new address: nothing changes until it is followed
+#[tracing::instrument(
+    name = "Requesting an email change",
+    skip(token, form, pool, signer, email_client, base_url)
+)]
+pub async fn change_email(
+    token: web::Path<String>,
+    form: web::Form<EmailData>,
+    pool: web::Data<PgPool>,
+    signer: web::Data<TokenSigner>,
+    email_client: web::Data<dyn EmailSender>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, PreferencesError> {
+    let subscriber_id = subscriber_id_from_token(&signer, &token)?;
+    let preferences_url = format!("/preferences/{}", token);
+    let new_email = match SubscriberEmail::parse(form.email.trim()) {
+        Ok(email) => email,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other(&preferences_url));
+        }
+    };
+
+    let current_email = sqlx::query!(
+        "SELECT email FROM subscriptions WHERE id = $1",
+        subscriber_id
+    )
+    .fetch_optional(pool.as_ref())
+    .await
+    .context("Failed to retrieve the subscriber associated with the given token")?
+    .ok_or(PreferencesError::SubscriberNotFound)?
+    .email;
+    if current_email == new_email.as_ref() {
+        FlashMessage::error("This is already your email address.").send();
+        return Ok(see_other(&preferences_url));
+    }
+
+    // Nothing is ever sent to suppressed addresses, but we don't say so
+    if !is_suppressed(pool.as_ref(), new_email.as_ref())
+        .await
+        .context("Failed to check the suppression list")?
+    {
+        let confirmation_link = email_change_link(
+            &base_url.0,
+            &signer,
+            subscriber_id,
+            &current_email,
+            &new_email,
+        );
+        email_client
+            .send_email(
+                &new_email,
+                "Confirm your new email address",
+                &format!(
+                    "Click <a href=\"{}\">here</a> to receive our newsletter at this address.",
+                    confirmation_link
+                ),
+                &format!(
+                    "Visit {} to receive our newsletter at this address.",
+                    confirmation_link
+                ),
+                &[],
+            )
+            .await
+            .context("Failed to send the email change confirmation")?;
+    }
+
+    FlashMessage::info("Follow the link we have sent to your new address to confirm the change.")
+        .send();
+    Ok(see_other(&preferences_url))
+}
+
+/// Lists to receive, as checkboxes named after the lists: unticked ones are left out
+#[tracing::instrument(
+    name = "Changing the lists of a subscriber",
+    skip(token, form, pool, signer)
+)]
+pub async fn change_lists(
+    token: web::Path<String>,
+    form: web::Form<HashMap<String, String>>,
+    pool: web::Data<PgPool>,
+    signer: web::Data<TokenSigner>,
+) -> Result<HttpResponse, PreferencesError> {
+    let subscriber_id = subscriber_id_from_token(&signer, &token)?;
+    let preferences_url = format!("/preferences/{}", token);
+    let list_ids: Vec<Uuid> = form
+        .keys()
+        .filter_map(|key| Uuid::parse_str(key).ok())
+        .collect();
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let status = sqlx::query!(
+        "SELECT status FROM subscriptions WHERE id = $1 FOR UPDATE",
+        subscriber_id
+    )
+    .fetch_optional(transaction.as_mut())
+    .await
+    .context("Failed to retrieve the subscriber associated with the given token")?
+    .ok_or(PreferencesError::SubscriberNotFound)?
+    .status;
+    // Their address has to be confirmed before it can join lists without another confirmation
+    if status != "confirmed" {
+        FlashMessage::error("Confirm your subscription to choose the lists you receive.").send();
+        return Ok(see_other(&preferences_url));
+    }
+
+    sqlx::query!(
+        r#"
+        INSERT INTO list_subscriptions (list_id, subscriber_id, status, subscribed_at)
+        SELECT list_id, $1, 'confirmed', now() FROM lists WHERE list_id = ANY($2)
+        ON CONFLICT (list_id, subscriber_id) DO UPDATE
+        SET status = 'confirmed', subscribed_at = now()
+        WHERE list_subscriptions.status <> 'confirmed'
+        "#,
+        subscriber_id,
+        &list_ids
+    )
+    .execute(transaction.as_mut())
+    .await
+    .context("Failed to add the subscriber to the lists they chose")?;
+    sqlx::query!(
+        r#"
+        UPDATE list_subscriptions SET status = 'unsubscribed'
+        WHERE subscriber_id = $1 AND NOT (list_id = ANY($2))
+        "#,
+        subscriber_id,
+        &list_ids
+    )
+    .execute(transaction.as_mut())
+    .await
+    .context("Failed to remove the subscriber from the lists they left")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to change the lists of a subscriber")?;
+
+    FlashMessage::info("Your lists have been updated.").send();
+    Ok(see_other(&preferences_url))
+}
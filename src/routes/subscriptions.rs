@@ -1,4 +1,7 @@
-use actix_web::{web, HttpResponse, ResponseError};
+use actix_web::{
+    http::{header::ContentType, StatusCode},
+    web, HttpResponse, ResponseError,
+};
 use anyhow::Context;
 use chrono::Utc;
 use rand::{distributions::Alphanumeric, thread_rng, Rng};
@@ -6,9 +9,13 @@ use sqlx::{PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
 use crate::{
-    domain::{NewSubscriber, SubscriberEmail, SubscriberName},
-    email_client::EmailClient,
+    domain::{NewSubscriber, SubscriberEmail, SubscriberName, SubscriberTag},
+    email_client::EmailSender,
+    mailing_lists::{get_lists, join_list, MailingList},
+    routes::home::{home_page, SubscriberPage},
+    segments::tag_subscriber,
     startup::ApplicationBaseUrl,
+    suppression_list::is_suppressed,
 };
 
 // Input data
@@ -16,6 +23,11 @@ use crate::{
 pub struct FormData {
     email: String,
     name: String,
+    /// The default list when not given
+    list_id: Option<Uuid>,
+    /// Set by hidden fields of the form, e.g. `webinar, early-adopter`
+    #[serde(default)]
+    tags: String,
 }
 
 impl TryFrom<FormData> for NewSubscriber {
@@ -31,8 +43,12 @@ impl TryFrom<FormData> for NewSubscriber {
 // Errors
 #[derive(thiserror::Error, Debug)]
 pub enum SubscribeError {
-    #[error("{0}")]
-    ValidationError(String),
+    /// Shown above the subscribe form, offering `lists` again
+    #[error("{message}")]
+    ValidationError {
+        message: String,
+        lists: Vec<MailingList>,
+    },
     #[error(transparent)]
     UnexpectedError(#[from] anyhow::Error),
 }
@@ -40,43 +56,201 @@ pub enum SubscribeError {
 impl ResponseError for SubscribeError {
     fn status_code(&self) -> actix_web::http::StatusCode {
         match self {
-            SubscribeError::ValidationError(_) => actix_web::http::StatusCode::BAD_REQUEST,
+            SubscribeError::ValidationError { .. } => actix_web::http::StatusCode::BAD_REQUEST,
             SubscribeError::UnexpectedError(_) => {
                 actix_web::http::StatusCode::INTERNAL_SERVER_ERROR
             }
         }
     }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            // Back to the form, with the reason it was rejected
+            SubscribeError::ValidationError { message, lists } => {
+                HttpResponse::build(self.status_code())
+                    .content_type(ContentType::html())
+                    .body(home_page(Some(message), lists))
+            }
+            SubscribeError::UnexpectedError(_) => HttpResponse::new(self.status_code()),
+        }
+    }
 }
 
 #[tracing::instrument(name = "Adding a new subscriber", skip(form, pool, email_client, base_url),fields(subscriber_email = %form.email, subscriber_name = %form.name))]
 pub async fn subscribe(
     form: web::Form<FormData>,
     pool: web::Data<PgPool>,
-    email_client: web::Data<EmailClient>,
+    email_client: web::Data<dyn EmailSender>,
     base_url: web::Data<ApplicationBaseUrl>,
 ) -> Result<HttpResponse, SubscribeError> {
-    let new_subscriber = form.0.try_into().map_err(SubscribeError::ValidationError)?;
+    let lists = get_lists(pool.as_ref())
+        .await
+        .context("Failed to retrieve the mailing lists.")?;
+    let list_id = match form.list_id {
+        Some(list_id) if lists.iter().any(|l| l.list_id == list_id) => list_id,
+        Some(_) => {
+            return Err(SubscribeError::ValidationError {
+                message: "The selected list does not exist.".into(),
+                lists,
+            })
+        }
+        None => {
+            lists
+                .first()
+                .context("There is no mailing list to subscribe to.")?
+                .list_id
+        }
+    };
+    let tags = match SubscriberTag::parse_list(&form.tags) {
+        Ok(tags) => tags,
+        Err(message) => return Err(SubscribeError::ValidationError { message, lists }),
+    };
+    let new_subscriber: NewSubscriber = match form.0.try_into() {
+        Ok(new_subscriber) => new_subscriber,
+        Err(message) => return Err(SubscribeError::ValidationError { message, lists }),
+    };
+
+    // Accepted as usual, so that the response doesn't tell whether the address is suppressed
+    if is_suppressed(pool.as_ref(), new_subscriber.email.as_ref())
+        .await
+        .context("Failed to check the suppression list.")?
+    {
+        tracing::info!("Ignoring a subscription from an address on the suppression list");
+        return Ok(SubscriberPage::Subscribed.response(StatusCode::OK));
+    }
 
     let mut transaction = pool
         .begin()
         .await
         .context("Failed to acquire a Postgres connection from the pool")?;
-    let subscriber_id = insert_subscriber(&mut transaction, &new_subscriber)
+    let (subscriber_id, tags) =
+        match get_existing_subscriber(&mut transaction, &new_subscriber, list_id)
+            .await
+            .context("Failed to look for an existing subscriber with the same email.")?
+        {
+            // Same answer as for a new subscriber, so that the response doesn't tell who is on the list
+            Some(existing)
+                if existing.status == "confirmed"
+                    && existing.list_status.as_deref() == Some("confirmed") =>
+            {
+                tracing::info!("Ignoring a subscription from an already confirmed subscriber");
+                return Ok(SubscriberPage::Subscribed.response(StatusCode::OK));
+            }
+            // Still pending, coming back after leaving or joining another list:
+            // confirm (again) with a fresh token
+            Some(existing) => {
+                restart_confirmation(&mut transaction, existing.id)
+                    .await
+                    .context("Failed to reset the confirmation of an existing subscriber.")?;
+                // Anyone can fill in the form with the address of a confirmed subscriber:
+                // they keep the tags they have. The others only count once they confirm.
+                let tags = if existing.status == "confirmed" {
+                    vec![]
+                } else {
+                    tags
+                };
+                (existing.id, tags)
+            }
+            None => (
+                insert_subscriber(&mut transaction, &new_subscriber)
+                    .await
+                    .context("Failed to insert new subscriber in the database.")?,
+                tags,
+            ),
+        };
+    join_list(transaction.as_mut(), list_id, subscriber_id)
+        .await
+        .context("Failed to add the subscriber to the list.")?;
+    tag_subscriber(transaction.as_mut(), subscriber_id, &tags)
         .await
-        .context("Failed to insert new subscriber in the database.")?;
+        .context("Failed to tag the subscriber.")?;
     let token = generate_subscription_token();
     store_token(&mut transaction, subscriber_id, &token)
         .await
         .context("Failed to store the confirmation token for a new subscriber.")?;
-    send_confirmation_email(&email_client, new_subscriber, &base_url.0, &token)
-        .await
-        .context("Failed to commit SQL transaction to store a new subscriber.")?;
+    send_confirmation_email(
+        email_client.as_ref(),
+        &new_subscriber.email,
+        &base_url.0,
+        &token,
+    )
+    .await
+    .context("Failed to send a confirmation email.")?;
     transaction
         .commit()
         .await
-        .context("Failed to send a confirmation email.")?;
+        .context("Failed to commit SQL transaction to store a new subscriber.")?;
 
-    Ok(HttpResponse::Ok().finish())
+    Ok(SubscriberPage::Subscribed.response(StatusCode::OK))
+}
+
+struct ExistingSubscriber {
+    id: Uuid,
+    status: String,
+    /// Status of their membership in the list they are signing up for, if any
+    list_status: Option<String>,
+}
+
+#[tracing::instrument(
+    name = "Looking for an existing subscriber",
+    skip(transaction, new_subscriber)
+)]
+async fn get_existing_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    new_subscriber: &NewSubscriber,
+    list_id: Uuid,
+) -> Result<Option<ExistingSubscriber>, sqlx::Error> {
+    sqlx::query_as!(
+        ExistingSubscriber,
+        r#"
+        SELECT s.id, s.status, ls.status as "list_status?"
+        FROM subscriptions s
+        LEFT JOIN list_subscriptions ls ON ls.subscriber_id = s.id AND ls.list_id = $2
+        WHERE s.email = $1
+        FOR UPDATE OF s
+        "#,
+        new_subscriber.email.as_ref(),
+        list_id
+    )
+    .fetch_optional(transaction.as_mut())
+    .await
+}
+
+/// Puts an existing subscriber back to pending confirmation, unless they are confirmed
+/// already and only joining another list.
+/// Links from previous confirmation emails stop working.
+#[tracing::instrument(name = "Resetting the confirmation of a subscriber", skip(transaction))]
+pub(crate) async fn restart_confirmation(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    // Coming back after leaving (or bouncing) must not restore the lists they were on
+    sqlx::query!(
+        r#"
+        UPDATE list_subscriptions SET status = 'unsubscribed'
+        WHERE subscriber_id = $1 AND status = 'confirmed'
+            AND (SELECT status FROM subscriptions WHERE id = $1) <> 'confirmed'
+        "#,
+        subscriber_id
+    )
+    .execute(transaction.as_mut())
+    .await?;
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions SET status = 'pending_confirmation', pending_since = now()
+        WHERE id = $1 AND status <> 'confirmed'
+        "#,
+        subscriber_id
+    )
+    .execute(transaction.as_mut())
+    .await?;
+    sqlx::query!(
+        "DELETE FROM subscription_tokens WHERE subscriber_id = $1",
+        subscriber_id
+    )
+    .execute(transaction.as_mut())
+    .await?;
+    Ok(())
 }
 
 #[tracing::instrument(
@@ -107,14 +281,14 @@ async fn insert_subscriber(
     name = "Store subscription token in the databsasae",
     skip(transaction, token)
 )]
-async fn store_token(
+pub(crate) async fn store_token(
     transaction: &mut Transaction<'_, Postgres>,
     subscriber_id: Uuid,
     token: &str,
 ) -> Result<(), sqlx::Error> {
     sqlx::query!(
-        r#"INSERT INTO subscription_tokens (subscription_token, subscriber_id)
-        VALUES($1, $2)"#,
+        r#"INSERT INTO subscription_tokens (subscription_token, subscriber_id, created_at)
+        VALUES($1, $2, now())"#,
         token,
         subscriber_id
     )
@@ -126,14 +300,14 @@ async fn store_token(
 
 #[tracing::instrument(
     name = "Sending confirmation email to subscriber",
-    skip(email_client, new_subscriber, base_url)
+    skip(email_client, subscriber_email, base_url)
 )]
-async fn send_confirmation_email(
-    email_client: &EmailClient,
-    new_subscriber: NewSubscriber,
+pub(crate) async fn send_confirmation_email(
+    email_client: &dyn EmailSender,
+    subscriber_email: &SubscriberEmail,
     base_url: &str,
     token: &str,
-) -> Result<(), reqwest::Error> {
+) -> Result<(), anyhow::Error> {
     let confirmation_link = format!("{}/subscriptions/confirm?token={}", base_url, token);
     let html_body = format!(
         "Welcome to the newsletter!<br/>\
@@ -146,11 +320,12 @@ async fn send_confirmation_email(
         confirmation_link
     );
     email_client
-        .send_email(&new_subscriber.email, "Welcome", &html_body, &text_body)
-        .await
+        .send_email(subscriber_email, "Welcome", &html_body, &text_body, &[])
+        .await?;
+    Ok(())
 }
 
-fn generate_subscription_token() -> String {
+pub(crate) fn generate_subscription_token() -> String {
     let mut rng = thread_rng();
     std::iter::repeat_with(|| rng.sample(Alphanumeric))
         .map(char::from)
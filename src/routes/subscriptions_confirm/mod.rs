@@ -0,0 +1,167 @@
+mod resend;
+
+pub use resend::resend_confirmation;
+
+use actix_web::{
+    http::{header::ContentType, StatusCode},
+    web, HttpResponse, ResponseError,
+};
+use anyhow::Context;
+use htmlescape::encode_attribute;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{routes::home::SubscriberPage, startup::ConfirmationTokenTtl};
+
+#[derive(serde::Deserialize)]
+pub struct Parameters {
+    pub token: String,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ConfirmationError {
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+    #[error("No subscriber associated with the given token.")]
+    TokenNotFound,
+    #[error("The confirmation token has expired.")]
+    TokenExpired { email: String },
+}
+
+impl ResponseError for ConfirmationError {
+    fn status_code(&self) -> actix_web::http::StatusCode {
+        match self {
+            ConfirmationError::UnexpectedError(_) => {
+                actix_web::http::StatusCode::INTERNAL_SERVER_ERROR
+            }
+            ConfirmationError::TokenNotFound => actix_web::http::StatusCode::NOT_FOUND,
+            ConfirmationError::TokenExpired { .. } => actix_web::http::StatusCode::GONE,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            ConfirmationError::TokenNotFound => {
+                SubscriberPage::InvalidToken.response(self.status_code())
+            }
+            ConfirmationError::TokenExpired { email } => {
+                let body = SubscriberPage::LinkExpired
+                    .template()
+                    .replace("{email}", &encode_attribute(email));
+                HttpResponse::build(self.status_code())
+                    .content_type(ContentType::html())
+                    .body(body)
+            }
+            ConfirmationError::UnexpectedError(_) => HttpResponse::new(self.status_code()),
+        }
+    }
+}
+
+#[tracing::instrument(name = "Confirming a pending subscriber", skip(_parameters, pool, ttl))]
+pub async fn confirm(
+    _parameters: web::Query<Parameters>,
+    pool: web::Data<PgPool>,
+    ttl: web::Data<ConfirmationTokenTtl>,
+) -> Result<HttpResponse, ConfirmationError> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let token = get_token(&mut transaction, &_parameters.token, &ttl)
+        .await
+        .context("Failed to retrieve the subscriber associated with the given token")?
+        .ok_or(ConfirmationError::TokenNotFound)?;
+    if token.used {
+        // A used token must not bring back someone who has left since
+        if token.status != "confirmed" {
+            return Err(ConfirmationError::TokenNotFound);
+        }
+        return Ok(SubscriberPage::AlreadyConfirmed.response(StatusCode::OK));
+    }
+    if token.expired {
+        return Err(ConfirmationError::TokenExpired { email: token.email });
+    }
+
+    confirm_subscriber(&mut transaction, &_parameters.token, token.subscriber_id)
+        .await
+        .context("Failed to update the subscriber status to 'confirmed'")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to confirm a subscriber")?;
+
+    Ok(SubscriberPage::Confirmed.response(StatusCode::OK))
+}
+
+struct ConfirmationToken {
+    subscriber_id: Uuid,
+    email: String,
+    status: String,
+    used: bool,
+    expired: bool,
+}
+
+#[tracing::instrument(name = "Finding subscriber using token", skip(transaction, token, ttl))]
+async fn get_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    token: &str,
+    ttl: &ConfirmationTokenTtl,
+) -> Result<Option<ConfirmationToken>, sqlx::Error> {
+    sqlx::query_as!(
+        ConfirmationToken,
+        r#"
+        SELECT t.subscriber_id, s.email, s.status,
+            t.used_at IS NOT NULL as "used!",
+            t.created_at < now() - make_interval(secs => $2) as "expired!"
+        FROM subscription_tokens t
+        JOIN subscriptions s ON s.id = t.subscriber_id
+        WHERE t.subscription_token = $1
+        FOR UPDATE OF t
+        "#,
+        token,
+        ttl.0.as_secs_f64()
+    )
+    .fetch_optional(transaction.as_mut())
+    .await
+}
+
+/// Confirms the subscriber, along with the lists they are waiting to join,
+/// and marks their token as used: confirmation links work only once
+#[tracing::instrument(name = "Mark subscriber as confirmed", skip(transaction, token))]
+async fn confirm_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    token: &str,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    mark_subscriber_confirmed(transaction, subscriber_id).await?;
+    sqlx::query!(
+        "UPDATE subscription_tokens SET used_at = now() WHERE subscription_token = $1",
+        token
+    )
+    .execute(transaction.as_mut())
+    .await?;
+    Ok(())
+}
+
+/// Confirms the subscriber along with the lists they are waiting to join
+pub(crate) async fn mark_subscriber_confirmed(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1"#,
+        subscriber_id
+    )
+    .execute(transaction.as_mut())
+    .await?;
+    sqlx::query!(
+        r#"
+        UPDATE list_subscriptions SET status = 'confirmed'
+        WHERE subscriber_id = $1 AND status = 'pending_confirmation'
+        "#,
+        subscriber_id
+    )
+    .execute(transaction.as_mut())
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,94 @@
+use actix_web::{http::StatusCode, web, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+
+use crate::{
+    domain::SubscriberEmail,
+    email_client::EmailSender,
+    mailing_lists::get_lists,
+    routes::home::SubscriberPage,
+    routes::subscriptions::{
+        generate_subscription_token, restart_confirmation, send_confirmation_email, store_token,
+        SubscribeError,
+    },
+    startup::ApplicationBaseUrl,
+    suppression_list::is_suppressed,
+};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    email: String,
+}
+
+/// Sends a new confirmation link to a subscriber whose link expired.
+/// The response is the same whether or not the address is waiting for confirmation.
+#[tracing::instrument(
+    name = "Resending a confirmation email",
+    skip(form, pool, email_client, base_url),
+    fields(subscriber_email = %form.email)
+)]
+pub async fn resend_confirmation(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<dyn EmailSender>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, SubscribeError> {
+    let email = match SubscriberEmail::parse(form.email.trim()) {
+        Ok(email) => email,
+        Err(message) => {
+            let lists = get_lists(pool.as_ref())
+                .await
+                .context("Failed to retrieve the mailing lists.")?;
+            return Err(SubscribeError::ValidationError { message, lists });
+        }
+    };
+
+    if is_suppressed(pool.as_ref(), email.as_ref())
+        .await
+        .context("Failed to check the suppression list.")?
+    {
+        return Ok(SubscriberPage::ConfirmationResent.response(StatusCode::OK));
+    }
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    // Confirmed subscribers may still have to confirm that they joined another list
+    let pending_subscriber = sqlx::query!(
+        r#"
+        SELECT id FROM subscriptions s
+        WHERE email = $1 AND (
+            status = 'pending_confirmation'
+            OR status = 'confirmed' AND EXISTS (
+                SELECT 1 FROM list_subscriptions ls
+                WHERE ls.subscriber_id = s.id AND ls.status = 'pending_confirmation'
+            )
+        )
+        FOR UPDATE
+        "#,
+        email.as_ref()
+    )
+    .fetch_optional(transaction.as_mut())
+    .await
+    .context("Failed to look for a pending subscriber.")?;
+
+    if let Some(subscriber) = pending_subscriber {
+        restart_confirmation(&mut transaction, subscriber.id)
+            .await
+            .context("Failed to reset the confirmation of a pending subscriber.")?;
+        let token = generate_subscription_token();
+        store_token(&mut transaction, subscriber.id, &token)
+            .await
+            .context("Failed to store the confirmation token for a pending subscriber.")?;
+        send_confirmation_email(email_client.as_ref(), &email, &base_url.0, &token)
+            .await
+            .context("Failed to send a confirmation email.")?;
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit SQL transaction to store a new confirmation token.")?;
+    }
+
+    Ok(SubscriberPage::ConfirmationResent.response(StatusCode::OK))
+}
@@ -0,0 +1,50 @@
+use actix_web::{http::header::LOCATION, web, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::CLICK_TOKEN_PURPOSE;
+use crate::{
+    signed_token::TokenSigner,
+    utils::{e400, e500},
+};
+
+/// Records a click on a link of an issue and sends the reader on to its destination.
+/// Only destinations we signed are followed, so this can't be used as an open redirect.
+#[tracing::instrument(name = "Recording a click on a link of an issue", skip_all)]
+pub async fn track_click(
+    token: web::Path<String>,
+    pool: web::Data<PgPool>,
+    signer: web::Data<TokenSigner>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (issue_delivery_id, url) = signer
+        .verify(CLICK_TOKEN_PURPOSE, &token)
+        .and_then(|payload| {
+            let (issue_delivery_id, url) = payload
+                .split_once(' ')
+                .context("The click token has no destination")?;
+            Ok((Uuid::parse_str(issue_delivery_id)?, url.to_owned()))
+        })
+        .map_err(e400)?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_clicks
+        (issue_click_id, issue_delivery_id, newsletter_issue_id, subscriber_id, url, clicked_at)
+        SELECT $1, issue_delivery_id, newsletter_issue_id, subscriber_id, $3, now()
+        FROM issue_deliveries
+        WHERE issue_delivery_id = $2
+        "#,
+        Uuid::new_v4(),
+        issue_delivery_id,
+        url
+    )
+    .execute(pool.as_ref())
+    .await
+    .context("Failed to record a click on a link")
+    .map_err(e500)?;
+
+    Ok(HttpResponse::Found()
+        .insert_header((LOCATION, url))
+        .finish())
+}
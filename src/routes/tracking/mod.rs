@@ -0,0 +1,32 @@
+mod click;
+mod open;
+
+pub use click::track_click;
+pub use open::track_open;
+
+use uuid::Uuid;
+
+use crate::signed_token::TokenSigner;
+
+const OPEN_TOKEN_PURPOSE: &str = "open";
+const CLICK_TOKEN_PURPOSE: &str = "click";
+
+/// Url of the invisible image reporting that a delivered issue has been opened
+pub fn open_pixel_link(base_url: &str, signer: &TokenSigner, issue_delivery_id: Uuid) -> String {
+    let token = signer.sign(OPEN_TOKEN_PURPOSE, &issue_delivery_id.to_string());
+    format!("{}/t/o/{}.gif", base_url, token)
+}
+
+/// Url recording a click on a link of a delivered issue before redirecting to `url`
+pub fn click_tracking_link(
+    base_url: &str,
+    signer: &TokenSigner,
+    issue_delivery_id: Uuid,
+    url: &str,
+) -> String {
+    let token = signer.sign(
+        CLICK_TOKEN_PURPOSE,
+        &format!("{} {}", issue_delivery_id, url),
+    );
+    format!("{}/t/c/{}", base_url, token)
+}
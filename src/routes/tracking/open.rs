@@ -0,0 +1,53 @@
+use actix_web::{http::header::CACHE_CONTROL, web, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::OPEN_TOKEN_PURPOSE;
+use crate::{
+    signed_token::TokenSigner,
+    utils::{e400, e500},
+};
+
+/// A transparent 1x1 GIF
+const PIXEL: &[u8] = &[
+    0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x01, 0x00, 0x01, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xff, 0xff, 0xff, 0x21, 0xf9, 0x04, 0x01, 0x00, 0x00, 0x00, 0x00, 0x2c, 0x00, 0x00, 0x00, 0x00,
+    0x01, 0x00, 0x01, 0x00, 0x00, 0x02, 0x02, 0x44, 0x01, 0x00, 0x3b,
+];
+
+#[tracing::instrument(name = "Recording the opening of an issue", skip_all)]
+pub async fn track_open(
+    token: web::Path<String>,
+    pool: web::Data<PgPool>,
+    signer: web::Data<TokenSigner>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_delivery_id = signer
+        .verify(OPEN_TOKEN_PURPOSE, &token)
+        .and_then(|payload| Ok(Uuid::parse_str(&payload)?))
+        .map_err(e400)?;
+
+    // Nothing is recorded for a delivery we don't know about, e.g. an attempt
+    // that was rolled back after the email went out
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_opens
+        (issue_open_id, issue_delivery_id, newsletter_issue_id, subscriber_id, opened_at)
+        SELECT $1, issue_delivery_id, newsletter_issue_id, subscriber_id, now()
+        FROM issue_deliveries
+        WHERE issue_delivery_id = $2
+        "#,
+        Uuid::new_v4(),
+        issue_delivery_id
+    )
+    .execute(pool.as_ref())
+    .await
+    .context("Failed to record the opening of an issue")
+    .map_err(e500)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("image/gif")
+        // Every opening has to reach us
+        .insert_header((CACHE_CONTROL, "no-store, private"))
+        .body(PIXEL))
+}
@@ -0,0 +1,42 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use anyhow::Context;
+use htmlescape::{encode_attribute, encode_minimal};
+use sqlx::PgPool;
+
+use crate::signed_token::TokenSigner;
+
+use super::{subscriber_id_from_token, UnsubscribeError};
+
+#[derive(serde::Deserialize)]
+pub struct Parameters {
+    token: String,
+}
+
+#[tracing::instrument(
+    name = "Showing the unsubscribe confirmation page",
+    skip(parameters, pool, signer)
+)]
+pub async fn unsubscribe_form(
+    parameters: web::Query<Parameters>,
+    pool: web::Data<PgPool>,
+    signer: web::Data<TokenSigner>,
+) -> Result<HttpResponse, UnsubscribeError> {
+    let subscriber_id = subscriber_id_from_token(&signer, &parameters.token)?;
+    let email = sqlx::query!(
+        "SELECT email FROM subscriptions WHERE id = $1",
+        subscriber_id
+    )
+    .fetch_optional(pool.as_ref())
+    .await
+    .context("Failed to retrieve the subscriber associated with the given token")?
+    .ok_or(UnsubscribeError::SubscriberNotFound)?
+    .email;
+
+    let body = include_str!("unsubscribe.html")
+        .replace("{email}", &encode_minimal(&email))
+        .replace("{token}", &encode_attribute(&parameters.token));
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
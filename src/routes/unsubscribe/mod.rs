@@ -0,0 +1,116 @@
+mod get;
+mod one_click;
+mod post;
+
+pub use get::unsubscribe_form;
+pub use one_click::unsubscribe_one_click;
+pub use post::unsubscribe;
+
+use actix_web::ResponseError;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::signed_token::TokenSigner;
+
+const UNSUBSCRIBE_TOKEN_PURPOSE: &str = "unsubscribe";
+
+/// Personal link a subscriber can follow to leave the newsletter
+pub fn unsubscribe_link(base_url: &str, signer: &TokenSigner, subscriber_id: Uuid) -> String {
+    let token = signer.sign(UNSUBSCRIBE_TOKEN_PURPOSE, &subscriber_id.to_string());
+    format!("{}/subscriptions/unsubscribe?token={}", base_url, token)
+}
+
+/// Link mail clients can POST to (RFC 8058) to unsubscribe without any interaction
+pub fn one_click_unsubscribe_link(
+    base_url: &str,
+    signer: &TokenSigner,
+    subscriber_id: Uuid,
+) -> String {
+    let token = signer.sign(UNSUBSCRIBE_TOKEN_PURPOSE, &subscriber_id.to_string());
+    format!(
+        "{}/subscriptions/unsubscribe/one-click?token={}",
+        base_url, token
+    )
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum UnsubscribeError {
+    #[error("The unsubscribe token is invalid.")]
+    InvalidToken(#[source] anyhow::Error),
+    #[error("No subscriber associated with the given token.")]
+    SubscriberNotFound,
+    #[error("The body of one-click unsubscriptions must be `List-Unsubscribe=One-Click`.")]
+    NotOneClick,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl ResponseError for UnsubscribeError {
+    fn status_code(&self) -> actix_web::http::StatusCode {
+        match self {
+            UnsubscribeError::InvalidToken(_) | UnsubscribeError::NotOneClick => {
+                actix_web::http::StatusCode::BAD_REQUEST
+            }
+            UnsubscribeError::SubscriberNotFound => actix_web::http::StatusCode::NOT_FOUND,
+            UnsubscribeError::UnexpectedError(_) => {
+                actix_web::http::StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+}
+
+fn subscriber_id_from_token(signer: &TokenSigner, token: &str) -> Result<Uuid, UnsubscribeError> {
+    signer
+        .verify(UNSUBSCRIBE_TOKEN_PURPOSE, token)
+        .and_then(|payload| Ok(Uuid::parse_str(&payload)?))
+        .map_err(UnsubscribeError::InvalidToken)
+}
+
+#[tracing::instrument(name = "Mark subscriber as unsubscribed", skip(pool))]
+pub(crate) async fn unsubscribe_subscriber(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<(), UnsubscribeError> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+
+    let email = sqlx::query!(
+        r#"
+        UPDATE subscriptions SET status = 'unsubscribed'
+        WHERE id = $1
+        RETURNING email
+        "#,
+        subscriber_id
+    )
+    .fetch_optional(transaction.as_mut())
+    .await
+    .context("Failed to update the subscriber status to 'unsubscribed'")?
+    .ok_or(UnsubscribeError::SubscriberNotFound)?
+    .email;
+
+    sqlx::query!(
+        "UPDATE list_subscriptions SET status = 'unsubscribed' WHERE subscriber_id = $1",
+        subscriber_id
+    )
+    .execute(transaction.as_mut())
+    .await
+    .context("Failed to remove the subscriber from their lists")?;
+
+    // Issues still waiting to be delivered must not reach them anymore
+    sqlx::query!(
+        "DELETE FROM issue_delivery_queue WHERE subscriber_email = $1",
+        email
+    )
+    .execute(transaction.as_mut())
+    .await
+    .context("Failed to remove the pending deliveries of the subscriber")?;
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to unsubscribe a subscriber.")?;
+    Ok(())
+}
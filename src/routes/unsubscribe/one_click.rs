@@ -0,0 +1,36 @@
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+
+use crate::signed_token::TokenSigner;
+
+use super::{subscriber_id_from_token, unsubscribe_subscriber, UnsubscribeError};
+
+#[derive(serde::Deserialize)]
+pub struct Parameters {
+    token: String,
+}
+
+/// Body sent by mail clients as per RFC 8058: `List-Unsubscribe=One-Click`
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    #[serde(rename = "List-Unsubscribe")]
+    list_unsubscribe: String,
+}
+
+#[tracing::instrument(
+    name = "Unsubscribing a subscriber with one click",
+    skip(parameters, form, pool, signer)
+)]
+pub async fn unsubscribe_one_click(
+    parameters: web::Query<Parameters>,
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    signer: web::Data<TokenSigner>,
+) -> Result<HttpResponse, UnsubscribeError> {
+    if form.list_unsubscribe != "One-Click" {
+        return Err(UnsubscribeError::NotOneClick);
+    }
+    let subscriber_id = subscriber_id_from_token(&signer, &parameters.token)?;
+    unsubscribe_subscriber(&pool, subscriber_id).await?;
+    Ok(HttpResponse::Ok().finish())
+}
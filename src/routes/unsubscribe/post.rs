@@ -0,0 +1,25 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use sqlx::PgPool;
+
+use crate::signed_token::TokenSigner;
+
+use super::{subscriber_id_from_token, unsubscribe_subscriber, UnsubscribeError};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    token: String,
+}
+
+#[tracing::instrument(name = "Unsubscribing a subscriber", skip(form, pool, signer))]
+pub async fn unsubscribe(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    signer: web::Data<TokenSigner>,
+) -> Result<HttpResponse, UnsubscribeError> {
+    let subscriber_id = subscriber_id_from_token(&signer, &form.token)?;
+    unsubscribe_subscriber(&pool, subscriber_id).await?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(include_str!("unsubscribed.html")))
+}
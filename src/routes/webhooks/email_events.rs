@@ -0,0 +1,140 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use anyhow::Context;
+use sqlx::{types::Json, PgPool};
+use uuid::Uuid;
+
+use crate::{
+    configuration::WebhookSettings,
+    domain::SubscriberEmail,
+    suppression_list::{suppress, SuppressionReason},
+};
+
+use super::{authenticate, WebhookError};
+
+/// A bounce or spam complaint as reported by Postmark's webhooks.
+/// Other kinds of events (deliveries, opens, ...) only need `RecordType`.
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct EmailEvent {
+    record_type: String,
+    #[serde(rename = "Type")]
+    event_type: Option<String>,
+    email: Option<String>,
+    #[serde(rename = "MessageID")]
+    message_id: Option<String>,
+    details: Option<String>,
+}
+
+impl EmailEvent {
+    /// Status the recipient ends up in, and why their address is suppressed,
+    /// if the event means we must stop writing to them
+    fn subscriber_status(&self) -> Option<(&'static str, SuppressionReason)> {
+        match (self.record_type.as_str(), self.event_type.as_deref()) {
+            ("Bounce", Some("HardBounce" | "BadEmailAddress")) => {
+                Some(("bounced", SuppressionReason::Bounce))
+            }
+            ("SpamComplaint", _) => Some(("complained", SuppressionReason::Complaint)),
+            _ => None,
+        }
+    }
+}
+
+/// Receives the bounces and spam complaints reported by the email provider.
+/// Hard bounces and complaints take the recipient off the mailing list for good.
+#[tracing::instrument(
+    name = "Receiving an email event",
+    skip_all,
+    fields(record_type=tracing::field::Empty, email=tracing::field::Empty)
+)]
+pub async fn email_events(
+    request: HttpRequest,
+    payload: web::Json<serde_json::Value>,
+    pool: web::Data<PgPool>,
+    settings: web::Data<WebhookSettings>,
+) -> Result<HttpResponse, WebhookError> {
+    authenticate(&request, &settings)?;
+
+    let payload = payload.into_inner();
+    let event: EmailEvent = serde_json::from_value(payload.clone())
+        .context("Unexpected email event payload")
+        .map_err(WebhookError::InvalidEvent)?;
+    tracing::Span::current().record("record_type", tracing::field::display(&event.record_type));
+    if !matches!(event.record_type.as_str(), "Bounce" | "SpamComplaint") {
+        // Not something we act upon, but it must not be sent again
+        return Ok(HttpResponse::Ok().finish());
+    }
+    let email = event
+        .email
+        .as_deref()
+        .map(SubscriberEmail::normalise)
+        .context("The event has no recipient")
+        .map_err(WebhookError::InvalidEvent)?;
+    tracing::Span::current().record("email", tracing::field::display(&email));
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    sqlx::query!(
+        r#"
+        INSERT INTO email_events (
+            email_event_id, record_type, event_type, email, provider_message_id,
+            issue_delivery_id, details, payload, received_at
+        )
+        VALUES (
+            $1, $2, $3, $4, $5,
+            (SELECT issue_delivery_id FROM issue_deliveries WHERE provider_message_id = $5 LIMIT 1),
+            $6, $7, now()
+        )
+        "#,
+        Uuid::new_v4(),
+        event.record_type,
+        event.event_type.as_deref().unwrap_or(&event.record_type),
+        &email,
+        event.message_id,
+        event.details,
+        Json(&payload) as _
+    )
+    .execute(transaction.as_mut())
+    .await
+    .context("Failed to record the email event")?;
+
+    if let Some((status, reason)) = event.subscriber_status() {
+        sqlx::query!(
+            r#"
+            UPDATE subscriptions SET status = $2
+            WHERE email = $1 AND status IN ('pending_confirmation', 'confirmed')
+            "#,
+            &email,
+            status
+        )
+        .execute(transaction.as_mut())
+        .await
+        .context("Failed to update the subscriber status")?;
+
+        // The address may come back through a new subscription: keep it out for good
+        suppress(
+            transaction.as_mut(),
+            &email,
+            reason,
+            event.details.as_deref(),
+        )
+        .await
+        .context("Failed to add the address to the suppression list")?;
+
+        // Issues still waiting to be delivered must not reach them anymore
+        sqlx::query!(
+            "DELETE FROM issue_delivery_queue WHERE subscriber_email = $1",
+            &email
+        )
+        .execute(transaction.as_mut())
+        .await
+        .context("Failed to remove the pending deliveries of the subscriber")?;
+    }
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the email event")?;
+    Ok(HttpResponse::Ok().finish())
+}
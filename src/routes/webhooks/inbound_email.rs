@@ -0,0 +1,61 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+
+use crate::{
+    configuration::WebhookSettings, domain::SubscriberEmail, routes::unsubscribe_subscriber,
+};
+
+use super::{authenticate, WebhookError};
+
+/// An email received by Postmark's inbound processing, reduced to what we act upon
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct InboundEmail {
+    from_full: Sender,
+    #[serde(default)]
+    subject: String,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Sender {
+    email: String,
+}
+
+/// Receives the emails sent to the `mailto:` entry of the `List-Unsubscribe` header.
+/// The sender is unsubscribed if the subject asks for it, as mail clients do,
+/// and any other email is ignored.
+#[tracing::instrument(
+    name = "Receiving an inbound email",
+    skip_all,
+    fields(email=tracing::field::Empty)
+)]
+pub async fn inbound_email(
+    request: HttpRequest,
+    payload: web::Json<serde_json::Value>,
+    pool: web::Data<PgPool>,
+    settings: web::Data<WebhookSettings>,
+) -> Result<HttpResponse, WebhookError> {
+    authenticate(&request, &settings)?;
+
+    let email: InboundEmail = serde_json::from_value(payload.into_inner())
+        .context("Unexpected inbound email payload")
+        .map_err(WebhookError::InvalidEvent)?;
+    let sender = SubscriberEmail::normalise(&email.from_full.email);
+    tracing::Span::current().record("email", tracing::field::display(&sender));
+    if !email.subject.to_lowercase().contains("unsubscribe") {
+        return Ok(HttpResponse::Ok().finish());
+    }
+
+    let subscriber = sqlx::query!("SELECT id FROM subscriptions WHERE email = $1", sender)
+        .fetch_optional(pool.as_ref())
+        .await
+        .context("Failed to look for the sender among the subscribers")?;
+    if let Some(subscriber) = subscriber {
+        unsubscribe_subscriber(&pool, subscriber.id)
+            .await
+            .context("Failed to unsubscribe the sender")?;
+    }
+    Ok(HttpResponse::Ok().finish())
+}
@@ -0,0 +1,94 @@
+mod email_events;
+mod inbound_email;
+
+pub use email_events::email_events;
+pub use inbound_email::inbound_email;
+
+use actix_web::{
+    http::{
+        header::{self, HeaderMap, HeaderValue},
+        StatusCode,
+    },
+    HttpRequest, HttpResponse, ResponseError,
+};
+use anyhow::Context;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+
+use crate::{authentication::Credentials, configuration::WebhookSettings};
+
+#[derive(thiserror::Error, Debug)]
+pub enum WebhookError {
+    #[error("Authentication failed")]
+    AuthError(#[source] anyhow::Error),
+    #[error("The event is malformed")]
+    InvalidEvent(#[source] anyhow::Error),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl ResponseError for WebhookError {
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            WebhookError::AuthError(_) => {
+                let mut response = HttpResponse::new(StatusCode::UNAUTHORIZED);
+                response.headers_mut().insert(
+                    header::WWW_AUTHENTICATE,
+                    HeaderValue::from_static(r#"Basic realm="webhooks""#),
+                );
+                response
+            }
+            WebhookError::InvalidEvent(_) => HttpResponse::new(StatusCode::BAD_REQUEST),
+            WebhookError::UnexpectedError(_) => {
+                HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR)
+            }
+        }
+    }
+}
+
+/// Checks the credentials the email provider puts in the webhook url
+fn authenticate(request: &HttpRequest, settings: &WebhookSettings) -> Result<(), WebhookError> {
+    let credentials = basic_authentication(request.headers()).map_err(WebhookError::AuthError)?;
+    check_credentials(&credentials, settings).map_err(WebhookError::AuthError)
+}
+
+fn basic_authentication(headers: &HeaderMap) -> Result<Credentials, anyhow::Error> {
+    let header_value = headers
+        .get("Authorization")
+        .context("The 'Authorization' header was missing")?
+        .to_str()
+        .context("The 'Authorization' header was not a valid UTF8 string.")?;
+    let base64encoded_segment = header_value
+        .strip_prefix("Basic ")
+        .context("The authorization scheme was not 'Basic'.")?;
+    let decoded_bytes = STANDARD
+        .decode(base64encoded_segment)
+        .context("Failed to base64-decode 'Basic' credentials.")?;
+    let decoded_credentials = String::from_utf8(decoded_bytes)
+        .context("The decoded credential string is not valid UTF8.")?;
+
+    let (username, password) = decoded_credentials
+        .split_once(':')
+        .context("A password must be provided in 'Basic' auth.")?;
+    Ok(Credentials {
+        username: username.to_owned(),
+        password: Secret::new(password.to_owned()),
+    })
+}
+
+fn check_credentials(
+    credentials: &Credentials,
+    settings: &WebhookSettings,
+) -> Result<(), anyhow::Error> {
+    // Comparing digests rather than the secrets themselves, so that the time
+    // it takes doesn't tell how much of the password was right
+    let digest = |s: &str| Sha256::digest(s.as_bytes());
+    if credentials.username == settings.username
+        && digest(credentials.password.expose_secret()) == digest(settings.password.expose_secret())
+    {
+        Ok(())
+    } else {
+        anyhow::bail!("Invalid webhook credentials")
+    }
+}
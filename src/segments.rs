@@ -0,0 +1,224 @@
+use std::collections::HashSet;
+
+use anyhow::Context;
+use chrono::{DateTime, NaiveDate, Utc};
+use sqlx::PgExecutor;
+use uuid::Uuid;
+
+use crate::domain::{SubscriberTag, TagExpression};
+
+/// Statuses a segment can filter subscribers on
+pub const SUBSCRIBER_STATUSES: [&str; 5] = [
+    "confirmed",
+    "pending_confirmation",
+    "unsubscribed",
+    "bounced",
+    "complained",
+];
+
+/// Saved selection of subscribers an issue can be restricted to.
+/// Every filter that is set must match.
+#[derive(Debug)]
+pub struct Segment {
+    pub segment_id: Uuid,
+    pub name: String,
+    /// As typed by the admin, for display
+    pub tag_expression: String,
+    pub expression: TagExpression,
+    /// Subscribed on this day or later
+    pub subscribed_from: Option<NaiveDate>,
+    /// Subscribed strictly before this day
+    pub subscribed_before: Option<NaiveDate>,
+    pub status: Option<String>,
+}
+
+/// What a segment looks at to select a subscriber
+pub struct SubscriberProfile {
+    pub email: String,
+    pub status: String,
+    pub subscribed_at: DateTime<Utc>,
+    pub tags: Vec<String>,
+}
+
+impl Segment {
+    pub fn matches(&self, subscriber: &SubscriberProfile) -> bool {
+        let subscribed_on = subscriber.subscribed_at.date_naive();
+        let tags: HashSet<&str> = subscriber.tags.iter().map(String::as_str).collect();
+        self.subscribed_from
+            .is_none_or(|from| subscribed_on >= from)
+            && self
+                .subscribed_before
+                .is_none_or(|before| subscribed_on < before)
+            && self
+                .status
+                .as_deref()
+                .is_none_or(|status| subscriber.status == status)
+            && self.expression.matches(&tags)
+    }
+}
+
+struct SegmentRow {
+    segment_id: Uuid,
+    name: String,
+    tag_expression: String,
+    subscribed_from: Option<NaiveDate>,
+    subscribed_before: Option<NaiveDate>,
+    status: Option<String>,
+}
+
+impl TryFrom<SegmentRow> for Segment {
+    type Error = anyhow::Error;
+
+    fn try_from(row: SegmentRow) -> Result<Self, Self::Error> {
+        // Checked when the segment was saved
+        let expression = TagExpression::parse(&row.tag_expression)
+            .map_err(anyhow::Error::msg)
+            .with_context(|| format!("Segment {} has an invalid tag expression", row.segment_id))?;
+        Ok(Self {
+            segment_id: row.segment_id,
+            name: row.name,
+            tag_expression: row.tag_expression,
+            expression,
+            subscribed_from: row.subscribed_from,
+            subscribed_before: row.subscribed_before,
+            status: row.status,
+        })
+    }
+}
+
+/// All the segments, by name
+pub async fn get_segments(executor: impl PgExecutor<'_>) -> Result<Vec<Segment>, anyhow::Error> {
+    let rows = sqlx::query_as!(
+        SegmentRow,
+        r#"
+        SELECT segment_id, name, tag_expression, subscribed_from, subscribed_before, status
+        FROM segments
+        ORDER BY name
+        "#
+    )
+    .fetch_all(executor)
+    .await?;
+    rows.into_iter().map(Segment::try_from).collect()
+}
+
+pub async fn get_segment(
+    executor: impl PgExecutor<'_>,
+    segment_id: Uuid,
+) -> Result<Option<Segment>, anyhow::Error> {
+    let row = sqlx::query_as!(
+        SegmentRow,
+        r#"
+        SELECT segment_id, name, tag_expression, subscribed_from, subscribed_before, status
+        FROM segments
+        WHERE segment_id = $1
+        "#,
+        segment_id
+    )
+    .fetch_optional(executor)
+    .await?;
+    row.map(Segment::try_from).transpose()
+}
+
+/// Every subscriber with their tags, whatever their status and lists
+pub async fn get_subscriber_profiles(
+    executor: impl PgExecutor<'_>,
+) -> Result<Vec<SubscriberProfile>, sqlx::Error> {
+    sqlx::query_as!(
+        SubscriberProfile,
+        r#"
+        SELECT s.email, s.status, s.subscribed_at,
+            array_remove(array_agg(t.tag ORDER BY t.tag), NULL) as "tags!"
+        FROM subscriptions s
+        LEFT JOIN subscriber_tags t ON t.subscriber_id = s.id
+        GROUP BY s.id
+        "#
+    )
+    .fetch_all(executor)
+    .await
+}
+
+/// Addresses of the confirmed members of a list, restricted to `segment` if there is one
+pub async fn get_recipients(
+    executor: impl PgExecutor<'_>,
+    list_id: Uuid,
+    segment: Option<&Segment>,
+) -> Result<Vec<String>, sqlx::Error> {
+    // Same filters as `Segment::matches`, days being taken in UTC
+    let recipients = sqlx::query!(
+        r#"
+        SELECT s.email
+        FROM subscriptions s
+        JOIN list_subscriptions ls ON ls.subscriber_id = s.id
+        WHERE ls.list_id = $1 AND ls.status = 'confirmed' AND s.status = 'confirmed'
+            AND ($2::text IS NULL OR s.status = $2)
+            AND ($3::date IS NULL OR (s.subscribed_at AT TIME ZONE 'UTC')::date >= $3)
+            AND ($4::date IS NULL OR (s.subscribed_at AT TIME ZONE 'UTC')::date < $4)
+            AND ($5::text IS NULL OR array_to_tsvector(ARRAY(
+                SELECT t.tag FROM subscriber_tags t WHERE t.subscriber_id = s.id
+            )) @@ $5::text::tsquery)
+        "#,
+        list_id,
+        segment.and_then(|s| s.status.as_deref()),
+        segment.and_then(|s| s.subscribed_from),
+        segment.and_then(|s| s.subscribed_before),
+        segment.and_then(|s| s.expression.to_tsquery())
+    )
+    .fetch_all(executor)
+    .await?;
+    Ok(recipients.into_iter().map(|r| r.email).collect())
+}
+
+/// Attaches `tags` to a subscriber, keeping the ones they already have
+pub async fn tag_subscriber(
+    executor: impl PgExecutor<'_>,
+    subscriber_id: Uuid,
+    tags: &[SubscriberTag],
+) -> Result<(), sqlx::Error> {
+    let tags: Vec<String> = tags.iter().map(|t| t.as_ref().to_owned()).collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_tags (subscriber_id, tag, tagged_at)
+        SELECT $1, unnest($2::text[]), now()
+        ON CONFLICT (subscriber_id, tag) DO NOTHING
+        "#,
+        subscriber_id,
+        &tags
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Removes a tag from a subscriber, returning whether they had it
+pub async fn untag_subscriber(
+    executor: impl PgExecutor<'_>,
+    subscriber_id: Uuid,
+    tag: &str,
+) -> Result<bool, sqlx::Error> {
+    let removed = sqlx::query!(
+        "DELETE FROM subscriber_tags WHERE subscriber_id = $1 AND tag = $2",
+        subscriber_id,
+        tag.trim().to_lowercase()
+    )
+    .execute(executor)
+    .await?
+    .rows_affected();
+    Ok(removed > 0)
+}
+
+/// Whether `segment_id` is a saved segment, or no segment at all
+pub async fn is_valid_segment(
+    executor: impl PgExecutor<'_>,
+    segment_id: Option<Uuid>,
+) -> Result<bool, sqlx::Error> {
+    let Some(segment_id) = segment_id else {
+        return Ok(true);
+    };
+    let exists = sqlx::query!(
+        "SELECT 1 as one FROM segments WHERE segment_id = $1",
+        segment_id
+    )
+    .fetch_optional(executor)
+    .await?;
+    Ok(exists.is_some())
+}
@@ -0,0 +1,102 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+
+/// Signs and verifies tokens carrying a small payload (e.g. a subscriber id),
+/// so that links sent by email can be trusted without storing them.
+#[derive(Clone)]
+pub struct TokenSigner {
+    secret: Secret<String>,
+}
+
+impl TokenSigner {
+    pub fn new(secret: Secret<String>) -> Self {
+        Self { secret }
+    }
+
+    /// Returns a url-safe token for `payload`.
+    ///
+    /// The `purpose` is part of the signature, so a token issued for one purpose
+    /// is rejected when verified for any other.
+    pub fn sign(&self, purpose: &str, payload: &str) -> String {
+        let signature = self.mac(purpose, payload).finalize().into_bytes();
+        format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(payload),
+            URL_SAFE_NO_PAD.encode(signature)
+        )
+    }
+
+    /// Returns the payload of `token` if it was signed by us for `purpose`
+    pub fn verify(&self, purpose: &str, token: &str) -> Result<String, anyhow::Error> {
+        let Some((payload, signature)) = token.split_once('.') else {
+            anyhow::bail!("The token is malformed");
+        };
+        let payload = String::from_utf8(URL_SAFE_NO_PAD.decode(payload)?)?;
+        let signature = URL_SAFE_NO_PAD.decode(signature)?;
+        self.mac(purpose, &payload)
+            .verify_slice(&signature)
+            .map_err(|_| anyhow::anyhow!("The token signature is invalid"))?;
+        Ok(payload)
+    }
+
+    fn mac(&self, purpose: &str, payload: &str) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.secret.expose_secret().as_bytes())
+            .expect("HMAC can take a key of any size");
+        mac.update(purpose.as_bytes());
+        mac.update(&[0]);
+        mac.update(payload.as_bytes());
+        mac
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+    use claims::{assert_err, assert_ok_eq};
+    use secrecy::Secret;
+
+    use super::TokenSigner;
+
+    fn signer(secret: &str) -> TokenSigner {
+        TokenSigner::new(Secret::new(secret.to_string()))
+    }
+
+    #[test]
+    fn a_signed_token_is_verified() {
+        let signer = signer("secret");
+        let token = signer.sign("unsubscribe", "payload");
+        assert_ok_eq!(signer.verify("unsubscribe", &token), "payload".to_string());
+    }
+
+    #[test]
+    fn a_token_with_a_tampered_payload_is_rejected() {
+        let signer = signer("secret");
+        let token = signer.sign("unsubscribe", "payload");
+        let (_, signature) = token.split_once('.').unwrap();
+        let forged = format!("{}.{}", URL_SAFE_NO_PAD.encode("other"), signature);
+        assert_err!(signer.verify("unsubscribe", &forged));
+    }
+
+    #[test]
+    fn a_token_signed_for_another_purpose_is_rejected() {
+        let signer = signer("secret");
+        let token = signer.sign("unsubscribe", "payload");
+        assert_err!(signer.verify("preferences", &token));
+    }
+
+    #[test]
+    fn a_token_signed_with_another_secret_is_rejected() {
+        let token = signer("secret").sign("unsubscribe", "payload");
+        assert_err!(signer("another secret").verify("unsubscribe", &token));
+    }
+
+    #[test]
+    fn a_malformed_token_is_rejected() {
+        let signer = signer("secret");
+        for token in ["", "no-separator", "!!.!!", "cGF5bG9hZA."] {
+            assert_err!(signer.verify("unsubscribe", token));
+        }
+    }
+}
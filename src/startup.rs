@@ -1,4 +1,4 @@
-use std::net::TcpListener;
+use std::{net::TcpListener, sync::Arc, time::Duration};
 
 use actix_session::{storage::RedisSessionStore, SessionMiddleware};
 use actix_web::{cookie::Key, dev::Server, middleware::from_fn, web, App, HttpServer};
@@ -9,12 +9,25 @@ use tracing_actix_web::TracingLogger;
 
 use crate::{
     authentication::reject_anonymous_users,
-    configuration::{DatabaseSettings, Settings},
-    email_client::EmailClient,
+    configuration::{ApplicationSettings, DatabaseSettings, Settings, WebhookSettings},
+    email_client::EmailSender,
     routes::{
-        admin_dashboard, change_password, change_password_form, confirm, health_check, home, login,
-        login_form, logout, newsletter_form, publish_newsletter, subscribe,
+        add_suppression, add_tags, admin_dashboard, atom_feed, cancel_schedule, change_email,
+        change_lists, change_name, change_password, change_password_form, confirm,
+        confirm_email_change, confirm_subscriber, create_list, create_segment, delete_draft,
+        delete_segment, delete_subscriber, edit_draft_form, email_events, erase_subscriber,
+        export_data_of_subscriber, export_subscribers, export_suppressions, failed_deliveries,
+        health_check, home, import_subscribers, import_subscribers_form, import_suppressions,
+        import_tags, inbound_email, issue_stats, list_issues, lists, login, login_form, logout,
+        newsletter_form, outbox, outbox_email, preferences_form, preview_issue, preview_recipients,
+        publish_draft, publish_newsletter, published_issue, published_issues, remove_suppression,
+        remove_tag, requeue_all_failed_deliveries, requeue_failed_delivery, resend_confirmation,
+        rss_feed, save_draft, schedule_issue, segments, set_issue_visibility, subscribe,
+        subscriber, subscriber_data, subscribers, suppressions, tags, track_click, track_open,
+        unsubscribe, unsubscribe_form, unsubscribe_one_click, unsubscribe_subscriber_manually,
+        update_draft, update_list,
     },
+    signed_token::TokenSigner,
 };
 
 pub struct Application {
@@ -25,7 +38,8 @@ pub struct Application {
 impl Application {
     pub async fn build(configuration: Settings) -> Result<Application, anyhow::Error> {
         let connection_pool = get_connection_pool(&configuration.database);
-        let email_client = configuration.email_client.client();
+        let email_webhook = configuration.email_client.webhook.clone();
+        let email_client = configuration.email_client.client(&connection_pool);
 
         let address = format!(
             "{}:{}",
@@ -37,9 +51,9 @@ impl Application {
             listener,
             connection_pool,
             email_client,
-            configuration.application.base_url,
-            configuration.application.hmac_secret,
+            configuration.application,
             configuration.redis_uri,
+            email_webhook,
         )
         .await?;
 
@@ -57,13 +71,16 @@ impl Application {
 
 pub struct ApplicationBaseUrl(pub String);
 
+/// How long confirmation links stay valid
+pub struct ConfirmationTokenTtl(pub Duration);
+
 async fn run(
     listener: TcpListener,
     db_pool: PgPool,
-    email_client: EmailClient,
-    base_url: String,
-    hmac_secret: Secret<String>,
+    email_client: Arc<dyn EmailSender>,
+    application: ApplicationSettings,
     redis_uri: Secret<String>,
+    email_webhook: WebhookSettings,
 ) -> Result<Server, anyhow::Error> {
     // Migrate db
     sqlx::migrate!("./migrations")
@@ -73,8 +90,13 @@ async fn run(
 
     // Start web server
     let pool = web::Data::new(db_pool);
-    let email_client = web::Data::new(email_client);
-    let base_url = web::Data::new(ApplicationBaseUrl(base_url));
+    let email_client: web::Data<dyn EmailSender> = web::Data::from(email_client);
+    let confirmation_token_ttl =
+        web::Data::new(ConfirmationTokenTtl(application.confirmation_token_ttl()));
+    let hmac_secret = application.hmac_secret;
+    let base_url = web::Data::new(ApplicationBaseUrl(application.base_url));
+    let token_signer = web::Data::new(TokenSigner::new(hmac_secret.clone()));
+    let email_webhook = web::Data::new(email_webhook);
 
     let secret_key = Key::from(hmac_secret.expose_secret().as_bytes());
 
@@ -96,13 +118,139 @@ async fn run(
             .route("/login", web::post().to(login))
             .route("/health_check", web::get().to(health_check))
             .route("/subscriptions", web::post().to(subscribe))
+            .route("/issues", web::get().to(published_issues))
+            .route(
+                "/issues/{newsletter_issue_id}",
+                web::get().to(published_issue),
+            )
+            .route("/feed.rss", web::get().to(rss_feed))
+            .route("/feed.atom", web::get().to(atom_feed))
+            .route("/t/o/{token}.gif", web::get().to(track_open))
+            .route("/t/c/{token}", web::get().to(track_click))
+            .route("/webhooks/email-events", web::post().to(email_events))
+            .route("/webhooks/inbound-email", web::post().to(inbound_email))
             .route("/subscriptions/confirm", web::get().to(confirm))
+            .route(
+                "/subscriptions/confirm/resend",
+                web::post().to(resend_confirmation),
+            )
+            .route(
+                "/subscriptions/unsubscribe",
+                web::get().to(unsubscribe_form),
+            )
+            .route("/subscriptions/unsubscribe", web::post().to(unsubscribe))
+            .route(
+                "/preferences/email/confirm",
+                web::get().to(confirm_email_change),
+            )
+            .route("/preferences/{token}", web::get().to(preferences_form))
+            .route("/preferences/{token}/name", web::post().to(change_name))
+            .route("/preferences/{token}/email", web::post().to(change_email))
+            .route("/preferences/{token}/lists", web::post().to(change_lists))
+            .route(
+                "/subscriptions/unsubscribe/one-click",
+                web::post().to(unsubscribe_one_click),
+            )
             .service(
                 web::scope("/admin")
                     .wrap(from_fn(reject_anonymous_users))
                     .route("/dashboard", web::get().to(admin_dashboard))
                     .route("/newsletters", web::get().to(newsletter_form))
                     .route("/newsletters", web::post().to(publish_newsletter))
+                    .route(
+                        "/newsletters/recipients",
+                        web::post().to(preview_recipients),
+                    )
+                    .route("/issues", web::get().to(list_issues))
+                    .route("/issues", web::post().to(save_draft))
+                    .route("/issues/{newsletter_issue_id}", web::get().to(issue_stats))
+                    .route(
+                        "/issues/{newsletter_issue_id}/edit",
+                        web::get().to(edit_draft_form),
+                    )
+                    .route(
+                        "/issues/{newsletter_issue_id}/edit",
+                        web::post().to(update_draft),
+                    )
+                    .route(
+                        "/issues/{newsletter_issue_id}/delete",
+                        web::post().to(delete_draft),
+                    )
+                    .route(
+                        "/issues/{newsletter_issue_id}/preview",
+                        web::get().to(preview_issue),
+                    )
+                    .route(
+                        "/issues/{newsletter_issue_id}/publish",
+                        web::post().to(publish_draft),
+                    )
+                    .route(
+                        "/issues/{newsletter_issue_id}/schedule",
+                        web::post().to(schedule_issue),
+                    )
+                    .route(
+                        "/issues/{newsletter_issue_id}/cancel_schedule",
+                        web::post().to(cancel_schedule),
+                    )
+                    .route(
+                        "/issues/{newsletter_issue_id}/visibility",
+                        web::post().to(set_issue_visibility),
+                    )
+                    .route("/deliveries/failed", web::get().to(failed_deliveries))
+                    .route(
+                        "/deliveries/failed/requeue",
+                        web::post().to(requeue_failed_delivery),
+                    )
+                    .route(
+                        "/deliveries/failed/requeue_all",
+                        web::post().to(requeue_all_failed_deliveries),
+                    )
+                    .route("/lists", web::get().to(lists))
+                    .route("/lists", web::post().to(create_list))
+                    .route("/lists/{list_id}", web::post().to(update_list))
+                    .route("/subscribers", web::get().to(subscribers))
+                    .route(
+                        "/subscribers/import",
+                        web::get().to(import_subscribers_form),
+                    )
+                    .route("/subscribers/import", web::post().to(import_subscribers))
+                    .route("/subscribers/export", web::get().to(export_subscribers))
+                    .route("/subscribers/data", web::get().to(subscriber_data))
+                    .route(
+                        "/subscribers/data/export",
+                        web::get().to(export_data_of_subscriber),
+                    )
+                    .route("/subscribers/data/erase", web::post().to(erase_subscriber))
+                    .route("/subscribers/{subscriber_id}", web::get().to(subscriber))
+                    .route(
+                        "/subscribers/{subscriber_id}/confirm",
+                        web::post().to(confirm_subscriber),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/unsubscribe",
+                        web::post().to(unsubscribe_subscriber_manually),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/delete",
+                        web::post().to(delete_subscriber),
+                    )
+                    .route("/tags", web::get().to(tags))
+                    .route("/tags", web::post().to(add_tags))
+                    .route("/tags/remove", web::post().to(remove_tag))
+                    .route("/tags/import", web::post().to(import_tags))
+                    .route("/segments", web::get().to(segments))
+                    .route("/segments", web::post().to(create_segment))
+                    .route(
+                        "/segments/{segment_id}/delete",
+                        web::post().to(delete_segment),
+                    )
+                    .route("/suppressions", web::get().to(suppressions))
+                    .route("/suppressions", web::post().to(add_suppression))
+                    .route("/suppressions/remove", web::post().to(remove_suppression))
+                    .route("/suppressions/import", web::post().to(import_suppressions))
+                    .route("/suppressions/export", web::get().to(export_suppressions))
+                    .route("/outbox", web::get().to(outbox))
+                    .route("/outbox/{captured_email_id}", web::get().to(outbox_email))
                     .route("/password", web::get().to(change_password_form))
                     .route("/password", web::post().to(change_password))
                     .route("/logout", web::post().to(logout)),
@@ -110,6 +258,9 @@ async fn run(
             .app_data(pool.clone())
             .app_data(email_client.clone())
             .app_data(base_url.clone())
+            .app_data(confirmation_token_ttl.clone())
+            .app_data(token_signer.clone())
+            .app_data(email_webhook.clone())
     })
     .listen(listener)?
     .run();
@@ -0,0 +1,489 @@
+//! What is stored about a subscriber, to answer data access and erasure requests.
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    domain::SubscriberEmail,
+    issue_delivery_worker::mark_issue_as_sent_if_delivered,
+    suppression_list::{add_erased_address, unsuppress},
+};
+
+/// A subscriber as listed in the full export
+#[derive(serde::Serialize)]
+pub struct SubscriberRecord {
+    pub email: String,
+    pub name: String,
+    pub status: String,
+    pub subscribed_at: DateTime<Utc>,
+    /// Names of the lists they are confirmed on
+    pub lists: Vec<String>,
+    pub tags: Vec<String>,
+}
+
+pub async fn get_all_subscribers(pool: &PgPool) -> Result<Vec<SubscriberRecord>, sqlx::Error> {
+    sqlx::query_as!(
+        SubscriberRecord,
+        r#"
+        SELECT s.email, s.name, s.status, s.subscribed_at,
+            ARRAY(
+                SELECT l.name
+                FROM list_subscriptions ls
+                JOIN lists l ON l.list_id = ls.list_id
+                WHERE ls.subscriber_id = s.id AND ls.status = 'confirmed'
+                ORDER BY l.name
+            ) as "lists!",
+            ARRAY(
+                SELECT t.tag FROM subscriber_tags t
+                WHERE t.subscriber_id = s.id
+                ORDER BY t.tag
+            ) as "tags!"
+        FROM subscriptions s
+        ORDER BY s.subscribed_at, s.email
+        "#
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[derive(serde::Serialize)]
+pub struct Subscription {
+    pub id: Uuid,
+    pub email: String,
+    pub name: String,
+    pub status: String,
+    pub subscribed_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize)]
+pub struct ListMembership {
+    pub list: String,
+    pub status: String,
+    pub subscribed_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize)]
+pub struct Tag {
+    pub tag: String,
+    pub tagged_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize)]
+pub struct Token {
+    pub subscription_token: String,
+    pub created_at: DateTime<Utc>,
+    pub used_at: Option<DateTime<Utc>>,
+}
+
+#[derive(serde::Serialize)]
+pub struct Delivery {
+    pub issue_title: String,
+    pub subscriber_email: String,
+    pub attempted_at: DateTime<Utc>,
+    pub outcome: String,
+    pub provider_message_id: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+pub struct PendingDelivery {
+    pub issue_title: String,
+    pub n_retries: i16,
+    pub execute_after: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize)]
+pub struct FailedDelivery {
+    pub issue_title: String,
+    pub n_retries: i16,
+    pub last_error: String,
+    pub failed_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize)]
+pub struct Open {
+    pub issue_title: String,
+    pub opened_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize)]
+pub struct Click {
+    pub issue_title: String,
+    pub url: String,
+    pub clicked_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize)]
+pub struct EmailEvent {
+    pub record_type: String,
+    pub event_type: String,
+    pub details: Option<String>,
+    pub payload: serde_json::Value,
+    pub received_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize)]
+pub struct Suppression {
+    pub reason: String,
+    pub note: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Everything stored about an address, as handed over on an access request
+#[derive(serde::Serialize)]
+pub struct SubscriberDataExport {
+    pub email: String,
+    pub subscription: Option<Subscription>,
+    pub lists: Vec<ListMembership>,
+    pub tags: Vec<Tag>,
+    pub confirmation_tokens: Vec<Token>,
+    pub deliveries: Vec<Delivery>,
+    pub pending_deliveries: Vec<PendingDelivery>,
+    pub failed_deliveries: Vec<FailedDelivery>,
+    pub opens: Vec<Open>,
+    pub clicks: Vec<Click>,
+    pub email_events: Vec<EmailEvent>,
+    pub suppression: Option<Suppression>,
+}
+
+impl SubscriberDataExport {
+    fn is_empty(&self) -> bool {
+        self.subscription.is_none()
+            && self.deliveries.is_empty()
+            && self.pending_deliveries.is_empty()
+            && self.failed_deliveries.is_empty()
+            && self.email_events.is_empty()
+            && self.suppression.is_none()
+    }
+}
+
+/// Collects what is stored about `email`, `None` if nothing is
+#[tracing::instrument(name = "Export the data of a subscriber", skip(pool, email))]
+pub async fn export_subscriber_data(
+    pool: &PgPool,
+    email: &str,
+) -> Result<Option<SubscriberDataExport>, sqlx::Error> {
+    let email = &SubscriberEmail::normalise(email);
+    let subscription = sqlx::query_as!(
+        Subscription,
+        "SELECT id, email, name, status, subscribed_at FROM subscriptions WHERE email = $1",
+        email
+    )
+    .fetch_optional(pool)
+    .await?;
+    // Deliveries made before a change of address are found through the subscriber id
+    let subscriber_id = subscription.as_ref().map(|s| s.id);
+
+    let lists = sqlx::query_as!(
+        ListMembership,
+        r#"
+        SELECT l.name as list, ls.status, ls.subscribed_at
+        FROM list_subscriptions ls
+        JOIN lists l ON l.list_id = ls.list_id
+        WHERE ls.subscriber_id = $1
+        ORDER BY ls.subscribed_at
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await?;
+    let tags = sqlx::query_as!(
+        Tag,
+        "SELECT tag, tagged_at FROM subscriber_tags WHERE subscriber_id = $1 ORDER BY tag",
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await?;
+    let confirmation_tokens = sqlx::query_as!(
+        Token,
+        r#"
+        SELECT subscription_token, created_at, used_at
+        FROM subscription_tokens
+        WHERE subscriber_id = $1
+        ORDER BY created_at
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await?;
+    let deliveries = sqlx::query_as!(
+        Delivery,
+        r#"
+        SELECT i.title as issue_title, d.subscriber_email, d.attempted_at, d.outcome,
+            d.provider_message_id, d.error
+        FROM issue_deliveries d
+        JOIN newsletter_issues i ON i.newsletter_issue_id = d.newsletter_issue_id
+        WHERE lower(d.subscriber_email) = $1 OR d.subscriber_id = $2
+        ORDER BY d.attempted_at
+        "#,
+        email,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await?;
+    let pending_deliveries = sqlx::query_as!(
+        PendingDelivery,
+        r#"
+        SELECT i.title as issue_title, q.n_retries, q.execute_after
+        FROM issue_delivery_queue q
+        JOIN newsletter_issues i ON i.newsletter_issue_id = q.newsletter_issue_id
+        WHERE lower(q.subscriber_email) = $1
+        ORDER BY q.execute_after
+        "#,
+        email
+    )
+    .fetch_all(pool)
+    .await?;
+    let failed_deliveries = sqlx::query_as!(
+        FailedDelivery,
+        r#"
+        SELECT i.title as issue_title, f.n_retries, f.last_error, f.failed_at
+        FROM issue_delivery_dead_letters f
+        JOIN newsletter_issues i ON i.newsletter_issue_id = f.newsletter_issue_id
+        WHERE lower(f.subscriber_email) = $1
+        ORDER BY f.failed_at
+        "#,
+        email
+    )
+    .fetch_all(pool)
+    .await?;
+    let opens = sqlx::query_as!(
+        Open,
+        r#"
+        SELECT i.title as issue_title, o.opened_at
+        FROM issue_opens o
+        JOIN newsletter_issues i ON i.newsletter_issue_id = o.newsletter_issue_id
+        JOIN issue_deliveries d ON d.issue_delivery_id = o.issue_delivery_id
+        WHERE lower(d.subscriber_email) = $1 OR d.subscriber_id = $2
+        ORDER BY o.opened_at
+        "#,
+        email,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await?;
+    let clicks = sqlx::query_as!(
+        Click,
+        r#"
+        SELECT i.title as issue_title, c.url, c.clicked_at
+        FROM issue_clicks c
+        JOIN newsletter_issues i ON i.newsletter_issue_id = c.newsletter_issue_id
+        JOIN issue_deliveries d ON d.issue_delivery_id = c.issue_delivery_id
+        WHERE lower(d.subscriber_email) = $1 OR d.subscriber_id = $2
+        ORDER BY c.clicked_at
+        "#,
+        email,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await?;
+    let email_events = sqlx::query_as!(
+        EmailEvent,
+        r#"
+        SELECT record_type, event_type, details, payload, received_at
+        FROM email_events
+        WHERE lower(email) = $1
+        ORDER BY received_at
+        "#,
+        email
+    )
+    .fetch_all(pool)
+    .await?;
+    let suppression = sqlx::query_as!(
+        Suppression,
+        "SELECT reason, note, created_at FROM suppressions WHERE email = $1",
+        email
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    let export = SubscriberDataExport {
+        email: email.to_owned(),
+        subscription,
+        lists,
+        tags,
+        confirmation_tokens,
+        deliveries,
+        pending_deliveries,
+        failed_deliveries,
+        opens,
+        clicks,
+        email_events,
+        suppression,
+    };
+    Ok((!export.is_empty()).then_some(export))
+}
+
+/// Removes `email` from every table, returning whether anything was stored about it.
+///
+/// The delivery log, opens, clicks and provider events are kept for the issue
+/// statistics: the address is replaced by a pseudonym which can't be traced back
+/// to the subscriber but still tells their deliveries apart from the others.
+///
+/// Only a SHA-256 hash of the address is retained, with no time limit, so that it
+/// is treated as suppressed from then on: a later import or sign-up can't email them
+/// again without the address itself being stored anywhere.
+#[tracing::instrument(name = "Erase the data of a subscriber", skip(pool, email), err)]
+pub async fn erase_subscriber_data(pool: &PgPool, email: &str) -> Result<bool, anyhow::Error> {
+    let email = &SubscriberEmail::normalise(email);
+    let mut transaction = pool.begin().await?;
+    let subscriber_id = sqlx::query!("SELECT id FROM subscriptions WHERE email = $1", email)
+        .fetch_optional(transaction.as_mut())
+        .await?
+        .map(|r| r.id);
+    let pseudonym = format!("erased-{}", Uuid::new_v4());
+    let mut n_rows = 0;
+
+    // Issues which were only waiting on this subscriber are done now
+    let pending_issues = sqlx::query!(
+        r#"
+        DELETE FROM issue_delivery_queue
+        WHERE lower(subscriber_email) = $1
+        RETURNING newsletter_issue_id
+        "#,
+        email
+    )
+    .fetch_all(transaction.as_mut())
+    .await?;
+    n_rows += pending_issues.len() as u64;
+    for issue in pending_issues {
+        mark_issue_as_sent_if_delivered(&mut transaction, issue.newsletter_issue_id).await?;
+    }
+
+    n_rows += sqlx::query!(
+        r#"
+        UPDATE issue_deliveries
+        SET subscriber_email = $3, subscriber_id = NULL, error = NULL
+        WHERE lower(subscriber_email) = $1 OR subscriber_id = $2
+        "#,
+        email,
+        subscriber_id,
+        pseudonym
+    )
+    .execute(transaction.as_mut())
+    .await?
+    .rows_affected();
+    for query in [
+        sqlx::query!(
+            r#"
+            UPDATE issue_opens SET subscriber_id = NULL
+            WHERE issue_delivery_id IN (
+                SELECT issue_delivery_id FROM issue_deliveries WHERE subscriber_email = $1
+            )
+            "#,
+            pseudonym
+        ),
+        sqlx::query!(
+            r#"
+            UPDATE issue_clicks SET subscriber_id = NULL
+            WHERE issue_delivery_id IN (
+                SELECT issue_delivery_id FROM issue_deliveries WHERE subscriber_email = $1
+            )
+            "#,
+            pseudonym
+        ),
+    ] {
+        query.execute(transaction.as_mut()).await?;
+    }
+    n_rows += sqlx::query!(
+        r#"
+        UPDATE email_events
+        SET email = $2, payload = '{}', details = NULL
+        WHERE lower(email) = $1
+        "#,
+        email,
+        pseudonym
+    )
+    .execute(transaction.as_mut())
+    .await?
+    .rows_affected();
+
+    for query in [
+        sqlx::query!(
+            "DELETE FROM issue_delivery_dead_letters WHERE lower(subscriber_email) = $1",
+            email
+        ),
+        sqlx::query!(
+            "DELETE FROM captured_emails WHERE lower(recipient) = $1",
+            email
+        ),
+    ] {
+        n_rows += query.execute(transaction.as_mut()).await?.rows_affected();
+    }
+    if let Some(subscriber_id) = subscriber_id {
+        delete_subscriber_rows(&mut transaction, subscriber_id).await?;
+        n_rows += 1;
+    }
+    if unsuppress(transaction.as_mut(), email).await? {
+        n_rows += 1;
+    }
+    if n_rows > 0 {
+        add_erased_address(transaction.as_mut(), email).await?;
+    }
+
+    transaction.commit().await?;
+    Ok(n_rows > 0)
+}
+
+/// Removes a subscriber, e.g. one added by mistake, returning whether they existed.
+/// Unlike an erasure, the delivery log keeps their address and they are free to sign up again.
+#[tracing::instrument(name = "Remove a subscriber", skip(pool), err)]
+pub async fn remove_subscriber(pool: &PgPool, subscriber_id: Uuid) -> Result<bool, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let Some(email) = sqlx::query!(
+        "SELECT email FROM subscriptions WHERE id = $1 FOR UPDATE",
+        subscriber_id
+    )
+    .fetch_optional(transaction.as_mut())
+    .await?
+    .map(|r| r.email) else {
+        return Ok(false);
+    };
+
+    // Issues which were only waiting on this subscriber are done now
+    let pending_issues = sqlx::query!(
+        r#"
+        DELETE FROM issue_delivery_queue
+        WHERE subscriber_email = $1
+        RETURNING newsletter_issue_id
+        "#,
+        email
+    )
+    .fetch_all(transaction.as_mut())
+    .await?;
+    for issue in pending_issues {
+        mark_issue_as_sent_if_delivered(&mut transaction, issue.newsletter_issue_id).await?;
+    }
+    delete_subscriber_rows(&mut transaction, subscriber_id).await?;
+
+    transaction.commit().await?;
+    Ok(true)
+}
+
+async fn delete_subscriber_rows(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    for query in [
+        sqlx::query!(
+            "DELETE FROM subscription_tokens WHERE subscriber_id = $1",
+            subscriber_id
+        ),
+        sqlx::query!(
+            "DELETE FROM list_subscriptions WHERE subscriber_id = $1",
+            subscriber_id
+        ),
+        sqlx::query!(
+            "DELETE FROM subscriber_tags WHERE subscriber_id = $1",
+            subscriber_id
+        ),
+        sqlx::query!(
+            "DELETE FROM confirmation_email_queue WHERE subscriber_id = $1",
+            subscriber_id
+        ),
+        sqlx::query!("DELETE FROM subscriptions WHERE id = $1", subscriber_id),
+    ] {
+        query.execute(transaction.as_mut()).await?;
+    }
+    Ok(())
+}
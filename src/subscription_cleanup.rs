@@ -0,0 +1,93 @@
+use std::time::Duration;
+
+use sqlx::PgPool;
+
+use crate::{configuration::Settings, startup::get_connection_pool};
+
+pub async fn run_cleanup_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
+    let connection_pool = get_connection_pool(&configuration.database);
+    cleanup_loop(
+        connection_pool,
+        configuration.application.confirmation_token_ttl(),
+        configuration.application.unconfirmed_subscriber_retention(),
+    )
+    .await
+}
+
+async fn cleanup_loop(
+    pool: PgPool,
+    token_ttl: Duration,
+    retention: Duration,
+) -> Result<(), anyhow::Error> {
+    loop {
+        // Failures are logged by the span, the next round will try again
+        let _ = purge_unconfirmed_subscriptions(&pool, token_ttl, retention).await;
+        tokio::time::sleep(Duration::from_secs(60 * 60)).await;
+    }
+}
+
+/// Deletes expired confirmation tokens, and the subscribers who didn't confirm
+/// within `retention` of signing up (or of signing up again, after leaving)
+#[tracing::instrument(name = "Purge unconfirmed subscriptions", skip(pool), err)]
+pub async fn purge_unconfirmed_subscriptions(
+    pool: &PgPool,
+    token_ttl: Duration,
+    retention: Duration,
+) -> Result<(), anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+
+    let n_subscribers = sqlx::query!(
+        r#"
+        WITH purged AS (
+            SELECT id FROM subscriptions
+            WHERE status = 'pending_confirmation'
+                AND pending_since < now() - make_interval(secs => $1)
+        ), purged_tokens AS (
+            DELETE FROM subscription_tokens
+            WHERE subscriber_id IN (SELECT id FROM purged)
+        ), purged_memberships AS (
+            DELETE FROM list_subscriptions
+            WHERE subscriber_id IN (SELECT id FROM purged)
+        ), purged_tags AS (
+            DELETE FROM subscriber_tags
+            WHERE subscriber_id IN (SELECT id FROM purged)
+        ), purged_queue AS (
+            DELETE FROM confirmation_email_queue
+            WHERE subscriber_id IN (SELECT id FROM purged)
+        )
+        DELETE FROM subscriptions WHERE id IN (SELECT id FROM purged)
+        "#,
+        retention.as_secs_f64()
+    )
+    .execute(transaction.as_mut())
+    .await?
+    .rows_affected();
+
+    // Confirmed subscribers who never confirmed joining another list
+    sqlx::query!(
+        r#"
+        DELETE FROM list_subscriptions
+        WHERE status = 'pending_confirmation'
+            AND subscribed_at < now() - make_interval(secs => $1)
+        "#,
+        retention.as_secs_f64()
+    )
+    .execute(transaction.as_mut())
+    .await?;
+
+    let n_tokens = sqlx::query!(
+        "DELETE FROM subscription_tokens WHERE created_at < now() - make_interval(secs => $1)",
+        token_ttl.as_secs_f64()
+    )
+    .execute(transaction.as_mut())
+    .await?
+    .rows_affected();
+
+    transaction.commit().await?;
+    tracing::info!(
+        "Purged {} unconfirmed subscribers and {} expired confirmation tokens",
+        n_subscribers,
+        n_tokens
+    );
+    Ok(())
+}
@@ -0,0 +1,116 @@
+use sha2::{Digest, Sha256};
+use sqlx::PgExecutor;
+
+use crate::domain::SubscriberEmail;
+
+/// Why an address is on the suppression list
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SuppressionReason {
+    /// Added by an administrator
+    Manual,
+    Bounce,
+    Complaint,
+    /// Required by law, e.g. an erasure request
+    Legal,
+}
+
+impl SuppressionReason {
+    pub const ALL: [SuppressionReason; 4] = [
+        SuppressionReason::Manual,
+        SuppressionReason::Bounce,
+        SuppressionReason::Complaint,
+        SuppressionReason::Legal,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SuppressionReason::Manual => "manual",
+            SuppressionReason::Bounce => "bounce",
+            SuppressionReason::Complaint => "complaint",
+            SuppressionReason::Legal => "legal",
+        }
+    }
+}
+
+/// Whether we must not send anything to `email`, whatever its subscription status
+pub async fn is_suppressed(
+    executor: impl PgExecutor<'_>,
+    email: &str,
+) -> Result<bool, sqlx::Error> {
+    let suppressed = sqlx::query!(
+        r#"
+        SELECT
+            EXISTS (SELECT 1 FROM suppressions WHERE email = $1)
+            OR EXISTS (SELECT 1 FROM erased_addresses WHERE email_hash = $2) as "suppressed!"
+        "#,
+        SubscriberEmail::normalise(email),
+        erased_address_hash(email)
+    )
+    .fetch_one(executor)
+    .await?
+    .suppressed;
+    Ok(suppressed)
+}
+
+/// Erased addresses are only known by a hash of their normalised form
+fn erased_address_hash(email: &str) -> String {
+    format!(
+        "{:x}",
+        Sha256::digest(SubscriberEmail::normalise(email).as_bytes())
+    )
+}
+
+/// Keeps an erased address suppressed for good, without storing the address itself
+pub async fn add_erased_address(
+    executor: impl PgExecutor<'_>,
+    email: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO erased_addresses (email_hash, erased_at)
+        VALUES ($1, now())
+        ON CONFLICT (email_hash) DO NOTHING
+        "#,
+        erased_address_hash(email)
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Adds an address to the suppression list.
+/// Returns `false` if it was already there, in which case the existing entry is kept.
+pub async fn suppress(
+    executor: impl PgExecutor<'_>,
+    email: &str,
+    reason: SuppressionReason,
+    note: Option<&str>,
+) -> Result<bool, sqlx::Error> {
+    let added = sqlx::query!(
+        r#"
+        INSERT INTO suppressions (email, reason, note, created_at)
+        VALUES ($1, $2, $3, now())
+        ON CONFLICT (email) DO NOTHING
+        "#,
+        SubscriberEmail::normalise(email),
+        reason.as_str(),
+        note.filter(|n| !n.trim().is_empty())
+    )
+    .execute(executor)
+    .await?
+    .rows_affected();
+    Ok(added > 0)
+}
+
+/// Takes an address off the suppression list. Returns `false` if it wasn't there.
+pub async fn unsuppress(executor: impl PgExecutor<'_>, email: &str) -> Result<bool, sqlx::Error> {
+    let removed = sqlx::query!(
+        "DELETE FROM suppressions WHERE email = $1",
+        SubscriberEmail::normalise(email)
+    )
+    .execute(executor)
+    .await?
+    .rows_affected();
+    Ok(removed > 0)
+}
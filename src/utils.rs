@@ -31,3 +31,19 @@ pub fn redirect_with_error<E: ToString>(to: &str, e: E) -> InternalError<E> {
 
     InternalError::from_response(e, response)
 }
+
+/// Deserializes an optional form field, treating an empty value
+/// (e.g. a `<select>` left on its blank option) as missing.
+/// Use with `#[serde(default, deserialize_with = "empty_as_none")]`.
+pub fn empty_as_none<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: std::str::FromStr,
+    T::Err: Display,
+{
+    let value: Option<String> = serde::Deserialize::deserialize(deserializer)?;
+    match value.as_deref().map(str::trim) {
+        None | Some("") => Ok(None),
+        Some(value) => value.parse().map(Some).map_err(serde::de::Error::custom),
+    }
+}
@@ -0,0 +1,217 @@
+use uuid::Uuid;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+/// Stores an issue straight in the database, published `age_in_days` ago
+async fn insert_issue(app: &TestApp, title: &str, status: &str, age_in_days: i32) -> String {
+    let newsletter_issue_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues
+        (newsletter_issue_id, list_id, title, text_content, html_content, status, published_at)
+        SELECT $1, list_id, $2, 'Text', $3, $4, now() - make_interval(days => $5)
+        FROM lists ORDER BY created_at LIMIT 1
+        "#,
+        newsletter_issue_id,
+        title,
+        format!(
+            "<html><head><title>x</title></head><body><p>Body of {}</p></body></html>",
+            title
+        ),
+        status,
+        age_in_days
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    newsletter_issue_id.to_string()
+}
+
+#[tokio::test]
+async fn the_archive_lists_published_issues_only() {
+    let app = spawn_app().await;
+    insert_issue(&app, "Sent issue", "sent", 1).await;
+    insert_issue(&app, "Issue being sent", "sending", 0).await;
+    let hidden_id = insert_issue(&app, "Hidden issue", "sent", 2).await;
+    sqlx::query!(
+        "UPDATE newsletter_issues SET subscribers_only = true WHERE newsletter_issue_id = $1",
+        Uuid::parse_str(&hidden_id).unwrap()
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues
+        (newsletter_issue_id, list_id, title, text_content, html_content, status)
+        SELECT $1, list_id, 'Draft issue', 'Text', 'Html', 'draft'
+        FROM lists ORDER BY created_at LIMIT 1
+        "#,
+        Uuid::new_v4()
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let html_page = app.get_archive_html(1).await;
+
+    assert!(html_page.contains("Sent issue"));
+    assert!(html_page.contains("Issue being sent"));
+    assert!(!html_page.contains("Hidden issue"));
+    assert!(!html_page.contains("Draft issue"));
+    // Newest first
+    assert!(html_page.find("Issue being sent") < html_page.find("Sent issue"));
+    assert!(html_page.contains(r#"<form action="/subscriptions" method="post">"#));
+}
+
+#[tokio::test]
+async fn the_archive_is_paginated() {
+    let app = spawn_app().await;
+    for day in 0..21 {
+        insert_issue(&app, &format!("Issue #{:02}", day), "sent", day).await;
+    }
+
+    let first_page = app.get_archive_html(1).await;
+    assert!(first_page.contains("Issue #00"));
+    assert!(first_page.contains("Issue #19"));
+    assert!(!first_page.contains("Issue #20"));
+    assert!(first_page.contains(r#"href="/issues?page=2""#));
+    assert!(!first_page.contains("Newer issues"));
+
+    let second_page = app.get_archive_html(2).await;
+    assert!(second_page.contains("Issue #20"));
+    assert!(!second_page.contains("Issue #19"));
+    assert!(second_page.contains(r#"href="/issues?page=1""#));
+    assert!(!second_page.contains("Older issues"));
+}
+
+#[tokio::test]
+async fn a_published_issue_is_rendered_in_the_archive_layout() {
+    let app = spawn_app().await;
+    let issue_id = insert_issue(&app, "Sent issue", "sent", 1).await;
+
+    let response = app.get_archived_issue(&issue_id).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("<h1>Sent issue</h1>"));
+    assert!(html_page.contains("<p>Body of Sent issue</p>"));
+    // Only the body of the issue document is embedded
+    assert!(!html_page.contains("<title>x</title>"));
+    assert!(html_page.contains(r#"<form action="/subscriptions" method="post">"#));
+}
+
+#[tokio::test]
+async fn drafts_hidden_and_unknown_issues_are_not_found() {
+    let app = spawn_app().await;
+    let hidden_id = insert_issue(&app, "Hidden issue", "sent", 1).await;
+    app.login_with_test_user().await;
+    let response = app.post_issue_visibility(&hidden_id, true).await;
+    assert_is_redirect_to(&response, "/admin/issues");
+    let draft_id = insert_issue(&app, "Draft issue", "draft", 0).await;
+
+    for issue_id in [hidden_id.clone(), draft_id, Uuid::new_v4().to_string()] {
+        let response = app.get_archived_issue(&issue_id).await;
+        assert_eq!(response.status().as_u16(), 404);
+    }
+
+    // Back in the archive
+    app.post_issue_visibility(&hidden_id, false).await;
+    let response = app.get_archived_issue(&hidden_id).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn issues_published_as_subscribers_only_stay_out_of_the_archive() {
+    let app = spawn_app().await;
+    app.login_with_test_user().await;
+    app.post_newsletter(&serde_json::json!({
+        "title": "Exclusive issue",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "subscribers_only": true,
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    }))
+    .await;
+
+    let html_page = app.get_archive_html(1).await;
+    assert!(!html_page.contains("Exclusive issue"));
+}
+
+#[tokio::test]
+async fn feeds_list_published_issues_with_stable_ids() {
+    let app = spawn_app().await;
+    let issue_id = insert_issue(&app, "Sent issue", "sent", 1).await;
+    insert_issue(&app, "Hidden issue", "sent", 2).await;
+    sqlx::query!(
+        "UPDATE newsletter_issues SET subscribers_only = true WHERE title = 'Hidden issue'"
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    insert_issue(&app, "Draft issue", "draft", 0).await;
+
+    for (feed, content_type) in [
+        ("feed.rss", "application/rss+xml; charset=utf-8"),
+        ("feed.atom", "application/atom+xml; charset=utf-8"),
+    ] {
+        let response = app.get_feed(feed, &[]).await;
+        assert_eq!(response.status().as_u16(), 200);
+        assert_eq!(response.headers()["Content-Type"], content_type);
+        assert!(response.headers().contains_key("ETag"));
+        assert!(response.headers().contains_key("Last-Modified"));
+
+        let body = response.text().await.unwrap();
+        assert!(body.contains("<title>Sent issue</title>"));
+        assert!(body.contains(&format!("urn:uuid:{}", issue_id)));
+        assert!(body.contains(&format!("/issues/{}", issue_id)));
+        // The content is escaped, and limited to the body of the issue
+        assert!(body.contains("&lt;p&gt;Body of Sent issue&lt;/p&gt;"));
+        assert!(!body.contains("&lt;title&gt;x&lt;/title&gt;"));
+        assert!(!body.contains("Hidden issue"));
+        assert!(!body.contains("Draft issue"));
+    }
+}
+
+#[tokio::test]
+async fn feed_titles_are_escaped() {
+    let app = spawn_app().await;
+    insert_issue(&app, "Tips & <tricks>", "sent", 1).await;
+
+    for feed in ["feed.rss", "feed.atom"] {
+        let body = app.get_feed(feed, &[]).await.text().await.unwrap();
+        assert!(body.contains("<title>Tips &amp; &lt;tricks&gt;</title>"));
+    }
+}
+
+#[tokio::test]
+async fn unchanged_feeds_are_not_sent_again() {
+    let app = spawn_app().await;
+    insert_issue(&app, "Sent issue", "sent", 1).await;
+
+    let mut etags = vec![];
+    for feed in ["feed.rss", "feed.atom"] {
+        let response = app.get_feed(feed, &[]).await;
+        let etag = response.headers()["ETag"].to_str().unwrap().to_owned();
+        let last_modified = response.headers()["Last-Modified"]
+            .to_str()
+            .unwrap()
+            .to_owned();
+
+        let response = app.get_feed(feed, &[("If-None-Match", &etag)]).await;
+        assert_eq!(response.status().as_u16(), 304);
+        let response = app
+            .get_feed(feed, &[("If-Modified-Since", &last_modified)])
+            .await;
+        assert_eq!(response.status().as_u16(), 304);
+        etags.push((feed, etag));
+    }
+
+    // A new issue changes the feeds
+    insert_issue(&app, "Newer issue", "sent", 0).await;
+    for (feed, etag) in etags {
+        let response = app.get_feed(feed, &[("If-None-Match", &etag)]).await;
+        assert_eq!(response.status().as_u16(), 200);
+        assert!(response.text().await.unwrap().contains("Newer issue"));
+    }
+}
@@ -0,0 +1,173 @@
+use wiremock::{matchers::any, Mock, ResponseTemplate};
+
+use crate::helpers::{assert_is_redirect_to, create_confirmed_subscriber, spawn_app, TestApp};
+
+/// A recorded Postmark webhook payload, about `email`
+fn postmark_event(payload: &str, email: &str) -> serde_json::Value {
+    let mut event: serde_json::Value = serde_json::from_str(payload).unwrap();
+    event["Email"] = email.into();
+    event
+}
+
+async fn subscriber(app: &TestApp) -> (String, String) {
+    let subscriber = sqlx::query!("SELECT email, status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    (subscriber.email, subscriber.status)
+}
+
+async fn recorded_events(app: &TestApp) -> Vec<String> {
+    sqlx::query!("SELECT event_type FROM email_events ORDER BY received_at")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|e| e.event_type)
+        .collect()
+}
+
+#[tokio::test]
+async fn email_events_must_be_authenticated() {
+    let app = spawn_app().await;
+    let event = postmark_event(
+        include_str!("fixtures/postmark/hard_bounce.json"),
+        "ursula@example.com",
+    );
+    let url = format!("{}/webhooks/email-events", app.address);
+
+    let no_credentials = app.api_client.post(&url).json(&event);
+    let wrong_password = app
+        .api_client
+        .post(&url)
+        .basic_auth(&app.email_webhook.username, Some("wrong password"))
+        .json(&event);
+    for request in [no_credentials, wrong_password] {
+        let response = request.send().await.unwrap();
+        assert_eq!(response.status().as_u16(), 401);
+        assert_eq!(
+            response.headers()["WWW-Authenticate"],
+            r#"Basic realm="webhooks""#
+        );
+    }
+    assert!(recorded_events(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn hard_bounces_stop_deliveries_to_the_subscriber() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let (email, _) = subscriber(&app).await;
+
+    let response = app
+        .post_email_event(&postmark_event(
+            include_str!("fixtures/postmark/hard_bounce.json"),
+            &email,
+        ))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber(&app).await.1, "bounced");
+    assert_eq!(recorded_events(&app).await, vec!["HardBounce"]);
+
+    // Nobody left to deliver to
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    app.login_with_test_user().await;
+    let response = app
+        .post_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": uuid::Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_pending_emails().await;
+}
+
+#[tokio::test]
+async fn spam_complaints_stop_deliveries_to_the_subscriber() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let (email, _) = subscriber(&app).await;
+
+    let response = app
+        .post_email_event(&postmark_event(
+            include_str!("fixtures/postmark/spam_complaint.json"),
+            &email,
+        ))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber(&app).await.1, "complained");
+    assert_eq!(recorded_events(&app).await, vec!["SpamComplaint"]);
+}
+
+#[tokio::test]
+async fn soft_bounces_are_recorded_but_keep_the_subscriber() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let (email, _) = subscriber(&app).await;
+
+    let response = app
+        .post_email_event(&postmark_event(
+            include_str!("fixtures/postmark/soft_bounce.json"),
+            &email,
+        ))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber(&app).await.1, "confirmed");
+    assert_eq!(recorded_events(&app).await, vec!["SoftBounce"]);
+}
+
+#[tokio::test]
+async fn other_events_are_acknowledged_and_ignored() {
+    let app = spawn_app().await;
+    let event: serde_json::Value =
+        serde_json::from_str(include_str!("fixtures/postmark/delivery.json")).unwrap();
+
+    let response = app.post_email_event(&event).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(recorded_events(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn bounces_are_linked_to_the_delivery_they_are_about() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let (email, _) = subscriber(&app).await;
+    app.login_with_test_user().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "MessageID": "883953f4-6105-42a2-a16a-77a8eac79483"
+        })))
+        .mount(&app.email_server)
+        .await;
+    app.post_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    }))
+    .await;
+    app.dispatch_pending_emails().await;
+
+    app.post_email_event(&postmark_event(
+        include_str!("fixtures/postmark/hard_bounce.json"),
+        &email,
+    ))
+    .await;
+
+    let event = sqlx::query!(
+        r#"
+        SELECT d.subscriber_email
+        FROM email_events e JOIN issue_deliveries d USING (issue_delivery_id)
+        "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(event.subscriber_email, email);
+}
@@ -1,16 +1,32 @@
-use std::sync::LazyLock;
+use std::{
+    sync::{Arc, LazyLock},
+    time::Duration,
+};
 
 use argon2::{password_hash::SaltString, Argon2, PasswordHasher};
+use fake::faker::internet::en::SafeEmail;
+use fake::faker::name::en::Name;
+use fake::Fake;
 use reqwest::{header::LOCATION, redirect, Response, Url};
-use secrecy::Secret;
+use secrecy::{ExposeSecret, Secret};
 use sqlx::{Connection, Executor, PgConnection, PgPool};
 use uuid::Uuid;
-use wiremock::MockServer;
+use wiremock::{
+    matchers::{method, path},
+    Mock, MockServer, ResponseTemplate,
+};
 use zero2prod::{
-    configuration::{get_configuration, DatabaseSettings},
-    email_client::EmailClient,
+    configuration::{
+        get_configuration, DatabaseSettings, EmailTransport, IssueDeliverySettings, WebhookSettings,
+    },
+    confirmation_email_worker::try_send_queued_confirmation,
+    email_client::EmailSender,
     issue_delivery_worker::{try_execute_task, ExecutionOutcome},
+    issue_scheduler::try_publish_scheduled_issue,
+    routes::preferences_link,
+    signed_token::TokenSigner,
     startup::{get_connection_pool, Application},
+    subscription_cleanup::purge_unconfirmed_subscriptions,
     telemetry::{get_subscriber, init_subscriber},
 };
 
@@ -38,7 +54,13 @@ pub struct TestApp {
     pub email_server: MockServer,
     pub test_user: TestUser,
     pub api_client: reqwest::Client,
-    pub email_client: EmailClient,
+    pub email_client: Arc<dyn EmailSender>,
+    pub issue_delivery: IssueDeliverySettings,
+    pub base_url: String,
+    pub token_signer: TokenSigner,
+    pub email_webhook: WebhookSettings,
+    pub confirmation_token_ttl: Duration,
+    pub unconfirmed_subscriber_retention: Duration,
 }
 
 impl TestApp {
@@ -52,6 +74,95 @@ impl TestApp {
             .expect("Failed to execute request")
     }
 
+    pub async fn post_resend_confirmation(&self, email: &str) -> reqwest::Response {
+        self.post_form(
+            "/subscriptions/confirm/resend",
+            &serde_json::json!({ "email": email }),
+        )
+        .await
+    }
+
+    pub async fn post_unsubscribe(&self, token: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/subscriptions/unsubscribe", &self.address))
+            .form(&serde_json::json!({ "token": token }))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    /// Extracts the unsubscribe link from a newsletter issue email
+    pub fn get_unsubscribe_link(&self, email_request: &wiremock::Request) -> reqwest::Url {
+        let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+        let links: Vec<_> = linkify::LinkFinder::new()
+            .links(body["HtmlBody"].as_str().unwrap())
+            .filter(|l| l.as_str().contains("/subscriptions/unsubscribe"))
+            .collect();
+        assert_eq!(links.len(), 1);
+        let mut unsubscribe_link = Url::parse(links[0].as_str()).unwrap();
+        assert_eq!(unsubscribe_link.host_str().unwrap(), "127.0.0.1");
+        unsubscribe_link.set_port(Some(self.port)).unwrap();
+        unsubscribe_link
+    }
+
+    /// Personal preferences page of a subscriber, as linked from issue emails
+    pub fn preferences_link(&self, subscriber_id: Uuid) -> reqwest::Url {
+        let mut link = Url::parse(&preferences_link(
+            &self.base_url,
+            &self.token_signer,
+            subscriber_id,
+        ))
+        .unwrap();
+        link.set_port(Some(self.port)).unwrap();
+        link
+    }
+
+    pub async fn post_preferences<Body>(
+        &self,
+        preferences_link: &reqwest::Url,
+        form: &str,
+        body: &Body,
+    ) -> Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/{}", preferences_link, form))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    /// Extracts the open tracking pixel from a newsletter issue email, if there is one
+    pub fn get_open_pixel_link(&self, email_request: &wiremock::Request) -> Option<reqwest::Url> {
+        let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+        let links: Vec<_> = linkify::LinkFinder::new()
+            .links(body["HtmlBody"].as_str().unwrap())
+            .filter(|l| l.as_str().contains("/t/o/"))
+            .collect();
+        assert!(links.len() <= 1);
+        let mut pixel_link = Url::parse(links.first()?.as_str()).unwrap();
+        assert_eq!(pixel_link.host_str().unwrap(), "127.0.0.1");
+        pixel_link.set_port(Some(self.port)).unwrap();
+        Some(pixel_link)
+    }
+
+    /// Extracts the click tracking links from a newsletter issue email
+    pub fn get_click_tracking_links(&self, email_request: &wiremock::Request) -> Vec<reqwest::Url> {
+        let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+        linkify::LinkFinder::new()
+            .links(body["HtmlBody"].as_str().unwrap())
+            .filter(|l| l.as_str().contains("/t/c/"))
+            .map(|l| {
+                let mut click_link = Url::parse(l.as_str()).unwrap();
+                assert_eq!(click_link.host_str().unwrap(), "127.0.0.1");
+                click_link.set_port(Some(self.port)).unwrap();
+                click_link
+            })
+            .collect()
+    }
+
     pub fn get_confirmation_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
         let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
         let get_link = |s: &str| {
@@ -151,6 +262,418 @@ impl TestApp {
             .expect("Failed to execute request")
     }
 
+    pub async fn get_issues(&self) -> Response {
+        self.get("/admin/issues").await
+    }
+
+    pub async fn get_issues_html(&self) -> String {
+        self.get_html("/admin/issues").await
+    }
+
+    pub async fn post_save_draft<Body>(&self, body: &Body) -> Response
+    where
+        Body: serde::Serialize,
+    {
+        self.post_form("/admin/issues", body).await
+    }
+
+    pub async fn get_edit_draft_html(&self, newsletter_issue_id: &str) -> String {
+        self.get_html(&format!("/admin/issues/{}/edit", newsletter_issue_id))
+            .await
+    }
+
+    pub async fn post_edit_draft<Body>(&self, newsletter_issue_id: &str, body: &Body) -> Response
+    where
+        Body: serde::Serialize,
+    {
+        self.post_form(&format!("/admin/issues/{}/edit", newsletter_issue_id), body)
+            .await
+    }
+
+    pub async fn post_delete_draft(&self, newsletter_issue_id: &str) -> Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/issues/{}/delete",
+                &self.address, newsletter_issue_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_publish_draft<Body>(&self, newsletter_issue_id: &str, body: &Body) -> Response
+    where
+        Body: serde::Serialize,
+    {
+        self.post_form(
+            &format!("/admin/issues/{}/publish", newsletter_issue_id),
+            body,
+        )
+        .await
+    }
+
+    pub async fn get_issue_stats_html(&self, newsletter_issue_id: &str) -> String {
+        self.get_html(&format!("/admin/issues/{}", newsletter_issue_id))
+            .await
+    }
+
+    pub async fn get_issue_preview_html(&self, newsletter_issue_id: &str) -> String {
+        self.get_html(&format!("/admin/issues/{}/preview", newsletter_issue_id))
+            .await
+    }
+
+    pub async fn post_schedule_issue<Body>(
+        &self,
+        newsletter_issue_id: &str,
+        body: &Body,
+    ) -> Response
+    where
+        Body: serde::Serialize,
+    {
+        self.post_form(
+            &format!("/admin/issues/{}/schedule", newsletter_issue_id),
+            body,
+        )
+        .await
+    }
+
+    pub async fn post_cancel_schedule(&self, newsletter_issue_id: &str) -> Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/issues/{}/cancel_schedule",
+                &self.address, newsletter_issue_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_issue_visibility(
+        &self,
+        newsletter_issue_id: &str,
+        subscribers_only: bool,
+    ) -> Response {
+        self.post_form(
+            &format!("/admin/issues/{}/visibility", newsletter_issue_id),
+            &serde_json::json!({ "subscribers_only": subscribers_only }),
+        )
+        .await
+    }
+
+    pub async fn get_archive_html(&self, page: u32) -> String {
+        self.get_html(&format!("/issues?page={}", page)).await
+    }
+
+    pub async fn get_archived_issue(&self, newsletter_issue_id: &str) -> Response {
+        self.get(&format!("/issues/{}", newsletter_issue_id)).await
+    }
+
+    /// Fetches `/feed.rss` or `/feed.atom`, with the given conditional request headers
+    pub async fn get_feed(&self, feed: &str, headers: &[(&str, &str)]) -> Response {
+        let mut request = self.api_client.get(format!("{}/{}", self.address, feed));
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        request.send().await.expect("Failed to execute request")
+    }
+
+    /// Reports an email event as the provider would, with the webhook credentials
+    pub async fn post_email_event(&self, payload: &serde_json::Value) -> Response {
+        self.api_client
+            .post(format!("{}/webhooks/email-events", &self.address))
+            .basic_auth(
+                &self.email_webhook.username,
+                Some(self.email_webhook.password.expose_secret()),
+            )
+            .json(payload)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    /// Forwards an email received by the inbound address, as the provider would
+    pub async fn post_inbound_email(&self, payload: &serde_json::Value) -> Response {
+        self.api_client
+            .post(format!("{}/webhooks/inbound-email", &self.address))
+            .basic_auth(
+                &self.email_webhook.username,
+                Some(self.email_webhook.password.expose_secret()),
+            )
+            .json(payload)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_failed_deliveries(&self) -> Response {
+        self.get("/admin/deliveries/failed").await
+    }
+
+    pub async fn get_failed_deliveries_html(&self) -> String {
+        self.get_html("/admin/deliveries/failed").await
+    }
+
+    pub async fn post_requeue_all_failed_deliveries(&self) -> Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/deliveries/failed/requeue_all",
+                &self.address
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_outbox(&self) -> Response {
+        self.get("/admin/outbox").await
+    }
+
+    pub async fn get_outbox_html(&self) -> String {
+        self.get_html("/admin/outbox").await
+    }
+
+    pub async fn get_outbox_email(&self, captured_email_id: &str) -> Response {
+        self.get(&format!("/admin/outbox/{}", captured_email_id))
+            .await
+    }
+
+    pub async fn get_outbox_email_html(&self, captured_email_id: &str) -> String {
+        self.get_html(&format!("/admin/outbox/{}", captured_email_id))
+            .await
+    }
+
+    pub async fn get_lists_html(&self) -> String {
+        self.get_html("/admin/lists").await
+    }
+
+    pub async fn post_create_list<Body>(&self, body: &Body) -> Response
+    where
+        Body: serde::Serialize,
+    {
+        self.post_form("/admin/lists", body).await
+    }
+
+    pub async fn post_update_list<Body>(&self, list_id: Uuid, body: &Body) -> Response
+    where
+        Body: serde::Serialize,
+    {
+        self.post_form(&format!("/admin/lists/{}", list_id), body)
+            .await
+    }
+
+    pub async fn get_suppressions(&self) -> Response {
+        self.get("/admin/suppressions").await
+    }
+
+    pub async fn get_suppressions_html(&self) -> String {
+        self.get_html("/admin/suppressions").await
+    }
+
+    pub async fn post_add_suppression<Body>(&self, body: &Body) -> Response
+    where
+        Body: serde::Serialize,
+    {
+        self.post_form("/admin/suppressions", body).await
+    }
+
+    pub async fn post_remove_suppression(&self, email: &str) -> Response {
+        self.post_form(
+            "/admin/suppressions/remove",
+            &serde_json::json!({ "email": email }),
+        )
+        .await
+    }
+
+    pub async fn post_import_suppressions(&self, csv: &str) -> Response {
+        self.post_csv("/admin/suppressions/import", csv).await
+    }
+
+    pub async fn get_suppressions_export(&self) -> Response {
+        self.get("/admin/suppressions/export").await
+    }
+
+    pub async fn get_tags_html(&self) -> String {
+        self.get_html("/admin/tags").await
+    }
+
+    pub async fn post_add_tags(&self, email: &str, tags: &str) -> Response {
+        self.post_form(
+            "/admin/tags",
+            &serde_json::json!({ "email": email, "tags": tags }),
+        )
+        .await
+    }
+
+    pub async fn post_remove_tag(&self, email: &str, tag: &str) -> Response {
+        self.post_form(
+            "/admin/tags/remove",
+            &serde_json::json!({ "email": email, "tag": tag }),
+        )
+        .await
+    }
+
+    pub async fn post_import_tags(&self, csv: &str) -> Response {
+        self.post_csv("/admin/tags/import", csv).await
+    }
+
+    pub async fn get_segments_html(&self) -> String {
+        self.get_html("/admin/segments").await
+    }
+
+    pub async fn post_create_segment<Body>(&self, body: &Body) -> Response
+    where
+        Body: serde::Serialize,
+    {
+        self.post_form("/admin/segments", body).await
+    }
+
+    pub async fn post_delete_segment(&self, segment_id: Uuid) -> Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/segments/{}/delete",
+                &self.address, segment_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_preview_recipients<Body>(&self, body: &Body) -> String
+    where
+        Body: serde::Serialize,
+    {
+        self.post_form("/admin/newsletters/recipients", body)
+            .await
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_import_subscribers_html(&self) -> String {
+        self.get_html("/admin/subscribers/import").await
+    }
+
+    pub async fn post_import_subscribers(&self, csv: &str, mode: &str) -> Response {
+        self.post_csv_with_fields("/admin/subscribers/import", csv, &[("mode", mode)])
+            .await
+    }
+
+    pub async fn post_import_subscribers_into_list(
+        &self,
+        csv: &str,
+        mode: &str,
+        list_id: Uuid,
+    ) -> Response {
+        self.post_csv_with_fields(
+            "/admin/subscribers/import",
+            csv,
+            &[("mode", mode), ("list_id", &list_id.to_string())],
+        )
+        .await
+    }
+
+    pub async fn get_subscribers(&self, query: &[(&str, &str)]) -> Response {
+        self.api_client
+            .get(format!("{}/admin/subscribers", self.address))
+            .query(query)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_subscribers_html(&self, query: &[(&str, &str)]) -> String {
+        self.get_subscribers(query).await.text().await.unwrap()
+    }
+
+    pub async fn get_subscriber(&self, subscriber_id: Uuid) -> Response {
+        self.get(&format!("/admin/subscribers/{}", subscriber_id))
+            .await
+    }
+
+    pub async fn get_subscriber_html(&self, subscriber_id: Uuid) -> String {
+        self.get_subscriber(subscriber_id)
+            .await
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_subscriber_action(&self, subscriber_id: Uuid, action: &str) -> Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/subscribers/{}/{}",
+                self.address, subscriber_id, action
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_subscriber_data_html(&self) -> String {
+        self.get_html("/admin/subscribers/data").await
+    }
+
+    pub async fn get_subscribers_export(&self, format: &str) -> Response {
+        self.get(&format!("/admin/subscribers/export?format={}", format))
+            .await
+    }
+
+    pub async fn get_subscriber_data_export(&self, email: &str) -> Response {
+        self.api_client
+            .get(format!("{}/admin/subscribers/data/export", self.address))
+            .query(&[("email", email)])
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_erase_subscriber(&self, email: &str) -> Response {
+        self.post_form(
+            "/admin/subscribers/data/erase",
+            &serde_json::json!({ "email": email }),
+        )
+        .await
+    }
+
+    async fn post_csv(&self, url: &str, csv: &str) -> Response {
+        self.post_csv_with_fields(url, csv, &[]).await
+    }
+
+    /// Uploads `csv` as the `file` field of a multipart form, next to text `fields`
+    async fn post_csv_with_fields(
+        &self,
+        url: &str,
+        csv: &str,
+        fields: &[(&str, &str)],
+    ) -> Response {
+        let boundary = "csv-file-boundary";
+        let mut body = String::new();
+        for (name, value) in fields {
+            body.push_str(&format!(
+                "--{boundary}\r\n\
+                Content-Disposition: form-data; name=\"{name}\"\r\n\r\n\
+                {value}\r\n"
+            ));
+        }
+        body.push_str(&format!(
+            "--{boundary}\r\n\
+            Content-Disposition: form-data; name=\"file\"; filename=\"import.csv\"\r\n\
+            Content-Type: text/csv\r\n\r\n\
+            {csv}\r\n\
+            --{boundary}--\r\n"
+        ));
+        self.api_client
+            .post(format!("{}{}", &self.address, url))
+            .header(
+                "Content-Type",
+                format!("multipart/form-data; boundary={}", boundary),
+            )
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
     async fn get(&self, url: &str) -> Response {
         self.api_client
             .get(format!("{}{}", self.address, url))
@@ -159,6 +682,18 @@ impl TestApp {
             .expect("Failed to execute request")
     }
 
+    async fn post_form<Body>(&self, url: &str, body: &Body) -> Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}{}", self.address, url))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
     async fn get_html(&self, url: &str) -> String {
         self.api_client
             .get(format!("{}{}", self.address, url))
@@ -170,12 +705,53 @@ impl TestApp {
             .unwrap()
     }
 
-    pub async fn dispatch_pending_emails(&self) {
+    pub async fn publish_due_issues(&self) {
         loop {
             if let ExecutionOutcome::EmptyQueue =
-                try_execute_task(&self.db_pool, &self.email_client)
-                    .await
-                    .unwrap()
+                try_publish_scheduled_issue(&self.db_pool).await.unwrap()
+            {
+                break;
+            }
+        }
+    }
+
+    pub async fn purge_unconfirmed_subscriptions(&self) {
+        purge_unconfirmed_subscriptions(
+            &self.db_pool,
+            self.confirmation_token_ttl,
+            self.unconfirmed_subscriber_retention,
+        )
+        .await
+        .unwrap();
+    }
+
+    pub async fn dispatch_confirmation_emails(&self) {
+        loop {
+            if let ExecutionOutcome::EmptyQueue = try_send_queued_confirmation(
+                &self.db_pool,
+                self.email_client.as_ref(),
+                &self.issue_delivery,
+                &self.base_url,
+            )
+            .await
+            .unwrap()
+            {
+                break;
+            }
+        }
+    }
+
+    pub async fn dispatch_pending_emails(&self) {
+        loop {
+            if let ExecutionOutcome::EmptyQueue = try_execute_task(
+                &self.db_pool,
+                self.email_client.as_ref(),
+                &self.issue_delivery,
+                &self.base_url,
+                &self.token_signer,
+            )
+            .await
+            .unwrap()
             {
                 break;
             }
@@ -191,8 +767,12 @@ pub async fn spawn_app() -> TestApp {
     let configuration = {
         let mut c = get_configuration().expect("Failed to read configuration");
         c.database.database_name = Uuid::new_v4().to_string();
+        c.email_client.transport = EmailTransport::Postmark;
         c.email_client.base_url = email_server.uri();
         c.application.port = 0;
+        // Retry failed deliveries straight away
+        c.issue_delivery.base_backoff_ms = 0;
+        c.issue_delivery.max_backoff_ms = 0;
         c
     };
 
@@ -211,14 +791,24 @@ pub async fn spawn_app() -> TestApp {
         .build()
         .unwrap();
 
+    let db_pool = get_connection_pool(&configuration.database);
+    let email_webhook = configuration.email_client.webhook.clone();
     let test_app = TestApp {
         address,
         port: application_port,
-        db_pool: get_connection_pool(&configuration.database),
+        email_client: configuration.email_client.client(&db_pool),
+        db_pool,
         email_server,
         test_user: TestUser::generate(),
         api_client,
-        email_client: configuration.email_client.client(),
+        issue_delivery: configuration.issue_delivery,
+        confirmation_token_ttl: configuration.application.confirmation_token_ttl(),
+        unconfirmed_subscriber_retention: configuration
+            .application
+            .unconfirmed_subscriber_retention(),
+        base_url: configuration.application.base_url,
+        token_signer: TokenSigner::new(configuration.application.hmac_secret),
+        email_webhook,
     };
 
     test_app.test_user.store(&test_app.db_pool).await;
@@ -289,3 +879,44 @@ pub fn assert_is_redirect_to(response: &reqwest::Response, location: &str) {
     assert_eq!(response.status().as_u16(), 303);
     assert_eq!(response.headers().get(LOCATION).unwrap(), location);
 }
+
+pub async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
+    let name: String = Name().fake();
+    let email: String = SafeEmail().fake();
+    let body = serde_urlencoded::to_string(serde_json::json!({
+            "name": name,
+            "email": email
+    }))
+    .unwrap();
+
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .named("Create unconfirmed subscriber")
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+
+    app.get_confirmation_links(&email_request)
+}
+
+pub async fn create_confirmed_subscriber(app: &TestApp) {
+    let confirmation_links = create_unconfirmed_subscriber(app).await;
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
@@ -0,0 +1,484 @@
+use wiremock::{matchers::any, Mock, ResponseTemplate};
+
+use crate::helpers::{assert_is_redirect_to, create_confirmed_subscriber, spawn_app, TestApp};
+
+fn dummy_draft_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Draft title",
+        "text_content": "Draft body as plain text",
+        "html_content": "<p>Draft body as HTML</p>",
+    })
+}
+
+/// Saves a draft and returns its id
+async fn save_a_draft(app: &TestApp) -> String {
+    let response = app.post_save_draft(&dummy_draft_body()).await;
+    assert_is_redirect_to(&response, "/admin/issues");
+
+    sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues WHERE status = 'draft'")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the saved draft.")
+        .newsletter_issue_id
+        .to_string()
+}
+
+async fn issue_status(app: &TestApp) -> String {
+    sqlx::query!("SELECT status FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the newsletter issue.")
+        .status
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_issues() {
+    let app = spawn_app().await;
+    let response = app.get_issues().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_save_a_draft() {
+    let app = spawn_app().await;
+    let response = app.post_save_draft(&dummy_draft_body()).await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn saved_drafts_are_listed_but_not_delivered() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.login_with_test_user().await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    save_a_draft(&app).await;
+    app.dispatch_pending_emails().await;
+
+    let html_page = app.get_issues_html().await;
+    assert!(html_page.contains("The draft has been saved."));
+    assert!(html_page.contains("Draft title"));
+    assert_eq!(issue_status(&app).await, "draft");
+}
+
+#[tokio::test]
+async fn drafts_can_be_edited() {
+    let app = spawn_app().await;
+    app.login_with_test_user().await;
+    let draft_id = save_a_draft(&app).await;
+
+    let html_page = app.get_edit_draft_html(&draft_id).await;
+    assert!(html_page.contains(&format!(
+        r#"value="{}""#,
+        htmlescape::encode_attribute("Draft title")
+    )));
+    assert!(html_page.contains("&lt;p&gt;Draft body as HTML&lt;/p&gt;"));
+
+    let response = app
+        .post_edit_draft(
+            &draft_id,
+            &serde_json::json!({
+                "title": "Edited title",
+                "text_content": "Edited body",
+                "html_content": "<p>Edited body</p>",
+            }),
+        )
+        .await;
+    assert_is_redirect_to(&response, &format!("/admin/issues/{}/edit", draft_id));
+
+    let html_page = app.get_edit_draft_html(&draft_id).await;
+    assert!(html_page.contains("The draft has been saved."));
+    assert!(html_page.contains(&format!(
+        r#"value="{}""#,
+        htmlescape::encode_attribute("Edited title")
+    )));
+}
+
+#[tokio::test]
+async fn drafts_can_be_deleted() {
+    let app = spawn_app().await;
+    app.login_with_test_user().await;
+    let draft_id = save_a_draft(&app).await;
+
+    let response = app.post_delete_draft(&draft_id).await;
+    assert_is_redirect_to(&response, "/admin/issues");
+
+    let html_page = app.get_issues_html().await;
+    assert!(html_page.contains("The draft has been deleted."));
+    assert!(!html_page.contains("Draft title"));
+}
+
+#[tokio::test]
+async fn published_drafts_are_delivered_and_marked_as_sent() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.login_with_test_user().await;
+    let draft_id = save_a_draft(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_publish_draft(
+            &draft_id,
+            &serde_json::json!({"idempotency_key": uuid::Uuid::new_v4().to_string()}),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/issues");
+    let html_page = app.get_issues_html().await;
+    assert!(html_page.contains("The newsletter issue has been accepted"));
+    assert_eq!(issue_status(&app).await, "sending");
+
+    app.dispatch_pending_emails().await;
+    assert_eq!(issue_status(&app).await, "sent");
+}
+
+#[tokio::test]
+async fn the_delivery_statistics_follow_the_progress_of_an_issue() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    app.login_with_test_user().await;
+    let draft_id = save_a_draft(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let html_page = app.get_issue_stats_html(&draft_id).await;
+    assert!(html_page.contains("This issue has not been published yet."));
+
+    app.post_publish_draft(
+        &draft_id,
+        &serde_json::json!({"idempotency_key": uuid::Uuid::new_v4().to_string()}),
+    )
+    .await;
+    let html_page = app.get_issue_stats_html(&draft_id).await;
+    assert!(html_page.contains("<th>Queued</th><td>2</td>"));
+    assert!(html_page.contains(r#"<progress value="0" max="2">"#));
+
+    // One of them leaves before the issue reaches them
+    sqlx::query!(
+        "UPDATE subscriptions SET status = 'unsubscribed' \
+        WHERE id = (SELECT id FROM subscriptions LIMIT 1)"
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.dispatch_pending_emails().await;
+
+    let html_page = app.get_issue_stats_html(&draft_id).await;
+    assert!(html_page.contains("<th>Queued</th><td>0</td>"));
+    assert!(html_page.contains("<th>Sent</th><td>1</td>"));
+    assert!(html_page.contains("<th>Failed</th><td>0</td>"));
+    assert!(html_page.contains("<th>Skipped</th><td>1</td>"));
+    assert!(!html_page.contains("<progress"));
+}
+
+#[tokio::test]
+async fn a_draft_is_published_only_once() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.login_with_test_user().await;
+    let draft_id = save_a_draft(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let body = serde_json::json!({"idempotency_key": uuid::Uuid::new_v4().to_string()});
+
+    // Retrying with the same idempotency key replays the saved response
+    for _ in 0..2 {
+        let response = app.post_publish_draft(&draft_id, &body).await;
+        assert_is_redirect_to(&response, "/admin/issues");
+        let html_page = app.get_issues_html().await;
+        assert!(html_page.contains("The newsletter issue has been accepted"));
+    }
+
+    // A different key (e.g. from another tab) finds the issue already published
+    let response = app
+        .post_publish_draft(
+            &draft_id,
+            &serde_json::json!({"idempotency_key": uuid::Uuid::new_v4().to_string()}),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/issues");
+    let html_page = app.get_issues_html().await;
+    assert!(html_page.contains("it may have been published already"));
+
+    app.dispatch_pending_emails().await;
+}
+
+#[tokio::test]
+async fn published_issues_cannot_be_edited_or_deleted() {
+    let app = spawn_app().await;
+    app.login_with_test_user().await;
+    let draft_id = save_a_draft(&app).await;
+    app.post_publish_draft(
+        &draft_id,
+        &serde_json::json!({"idempotency_key": uuid::Uuid::new_v4().to_string()}),
+    )
+    .await;
+
+    let response = app.post_edit_draft(&draft_id, &dummy_draft_body()).await;
+    assert_is_redirect_to(&response, "/admin/issues");
+
+    let response = app.post_delete_draft(&draft_id).await;
+    assert_is_redirect_to(&response, "/admin/issues");
+
+    // Nobody to deliver it to, so it is sent straight away
+    assert_eq!(issue_status(&app).await, "sent");
+}
+
+/// Wall-clock time, in UTC, of a moment `offset` away from now
+fn utc_local_time(offset: chrono::Duration) -> String {
+    (chrono::Utc::now() + offset)
+        .format("%Y-%m-%dT%H:%M")
+        .to_string()
+}
+
+/// Pretends the scheduled time of every issue has come
+async fn fast_forward_schedules(app: &TestApp) {
+    sqlx::query!("UPDATE newsletter_issues SET scheduled_for = now() - interval '1 minute' WHERE scheduled_for IS NOT NULL")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn scheduled_issues_are_delivered_once_their_time_has_come() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.login_with_test_user().await;
+    let draft_id = save_a_draft(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_schedule_issue(
+            &draft_id,
+            &serde_json::json!({
+                "scheduled_for": utc_local_time(chrono::Duration::days(3)),
+                "timezone": "Europe/Rome",
+            }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/issues");
+    let html_page = app.get_issues_html().await;
+    assert!(html_page.contains("The issue has been scheduled for"));
+
+    // Nothing goes out before the scheduled time
+    app.publish_due_issues().await;
+    app.dispatch_pending_emails().await;
+    assert_eq!(issue_status(&app).await, "scheduled");
+
+    fast_forward_schedules(&app).await;
+    app.publish_due_issues().await;
+    app.dispatch_pending_emails().await;
+    assert_eq!(issue_status(&app).await, "sent");
+}
+
+#[tokio::test]
+async fn cancelled_schedules_are_not_delivered() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.login_with_test_user().await;
+    let draft_id = save_a_draft(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_schedule_issue(
+        &draft_id,
+        &serde_json::json!({
+            "scheduled_for": utc_local_time(chrono::Duration::days(3)),
+            "timezone": "UTC",
+        }),
+    )
+    .await;
+    let response = app.post_cancel_schedule(&draft_id).await;
+    assert_is_redirect_to(&response, "/admin/issues");
+    let html_page = app.get_issues_html().await;
+    assert!(html_page.contains("The issue has been moved back to the drafts."));
+
+    app.publish_due_issues().await;
+    app.dispatch_pending_emails().await;
+    assert_eq!(issue_status(&app).await, "draft");
+}
+
+#[tokio::test]
+async fn scheduled_issues_can_be_rescheduled() {
+    let app = spawn_app().await;
+    app.login_with_test_user().await;
+    let draft_id = save_a_draft(&app).await;
+
+    for scheduled_for in ["2100-01-01T08:00", "2100-02-01T08:00"] {
+        let response = app
+            .post_schedule_issue(
+                &draft_id,
+                &serde_json::json!({"scheduled_for": scheduled_for, "timezone": "Europe/Rome"}),
+            )
+            .await;
+        assert_is_redirect_to(&response, "/admin/issues");
+    }
+
+    let scheduled_for = sqlx::query!("SELECT scheduled_for FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .scheduled_for
+        .unwrap();
+    assert_eq!(scheduled_for.to_rfc3339(), "2100-02-01T07:00:00+00:00");
+    assert_eq!(issue_status(&app).await, "scheduled");
+}
+
+#[tokio::test]
+async fn invalid_schedules_are_rejected() {
+    let app = spawn_app().await;
+    app.login_with_test_user().await;
+    let draft_id = save_a_draft(&app).await;
+
+    let test_cases = vec![
+        (
+            serde_json::json!({"scheduled_for": "2100-01-01T08:00", "timezone": "Nowhere/Land"}),
+            "is not a valid timezone",
+        ),
+        (
+            serde_json::json!({"scheduled_for": "tomorrow", "timezone": "UTC"}),
+            "is not a valid date and time",
+        ),
+        (
+            serde_json::json!({
+                "scheduled_for": utc_local_time(-chrono::Duration::hours(1)),
+                "timezone": "UTC"
+            }),
+            "The scheduled time must be in the future.",
+        ),
+    ];
+    for (body, error_message) in test_cases {
+        let response = app.post_schedule_issue(&draft_id, &body).await;
+        assert_is_redirect_to(&response, "/admin/issues");
+        let html_page = app.get_issues_html().await;
+        assert!(html_page.contains(error_message));
+    }
+    assert_eq!(issue_status(&app).await, "draft");
+}
+
+#[tokio::test]
+async fn drafts_with_unknown_template_variables_cannot_be_published_or_scheduled() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.login_with_test_user().await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_save_draft(&serde_json::json!({
+        "title": "Hi {{ first_name }}",
+        "text_content": "Draft body as plain text",
+        "html_content": "<p>Draft body as HTML</p>",
+    }))
+    .await;
+    let draft_id = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id
+        .to_string();
+
+    let response = app
+        .post_publish_draft(
+            &draft_id,
+            &serde_json::json!({"idempotency_key": uuid::Uuid::new_v4().to_string()}),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/issues");
+    let html_page = app.get_issues_html().await;
+    assert!(html_page.contains("Unknown template variable"));
+
+    app.post_schedule_issue(
+        &draft_id,
+        &serde_json::json!({"scheduled_for": "2100-01-01T08:00", "timezone": "UTC"}),
+    )
+    .await;
+    let html_page = app.get_issues_html().await;
+    assert!(html_page.contains("Unknown template variable"));
+
+    assert_eq!(issue_status(&app).await, "draft");
+    app.dispatch_pending_emails().await;
+}
+
+#[tokio::test]
+async fn the_preview_renders_the_issue_for_a_sample_subscriber() {
+    let app = spawn_app().await;
+    app.login_with_test_user().await;
+    app.post_save_draft(&serde_json::json!({
+        "title": "News for {{ name }}",
+        "text_content": "Hi {{ name }}, read it online: {{ archive_url }}",
+        "html_content": "<p>Hi {{ name }}</p>",
+    }))
+    .await;
+    let draft_id = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id
+        .to_string();
+
+    let html_page = app.get_issue_preview_html(&draft_id).await;
+
+    assert!(html_page.contains("<h1>News for Jane Doe</h1>"));
+    assert!(html_page.contains(&format!(
+        "Hi Jane Doe, read it online: {}/issues/{}",
+        app.base_url, draft_id
+    )));
+    assert!(html_page.contains(&htmlescape::encode_attribute("<p>Hi Jane Doe</p>")));
+}
+
+#[tokio::test]
+async fn markdown_drafts_keep_their_source_for_editing() {
+    let app = spawn_app().await;
+    app.login_with_test_user().await;
+    app.post_save_draft(&serde_json::json!({
+        "title": "Draft title",
+        "markdown_content": "Hello **world**",
+    }))
+    .await;
+    let draft = sqlx::query!(
+        "SELECT newsletter_issue_id, text_content, html_content FROM newsletter_issues"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(draft.text_content, "Hello world");
+    assert_eq!(draft.html_content, "<p>Hello <strong>world</strong></p>\n");
+
+    let html_page = app
+        .get_edit_draft_html(&draft.newsletter_issue_id.to_string())
+        .await;
+    assert!(html_page
+        .contains(r#"name="markdown_content" rows="20" cols="50">Hello **world**</textarea>"#));
+}
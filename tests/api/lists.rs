@@ -0,0 +1,295 @@
+use std::collections::HashMap;
+
+use uuid::Uuid;
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{assert_is_redirect_to, create_confirmed_subscriber, spawn_app, TestApp};
+
+async fn create_list(app: &TestApp, name: &str, sender_email: &str) -> Uuid {
+    let response = app
+        .post_create_list(&serde_json::json!({
+            "name": name,
+            "description": "",
+            "sender_email": sender_email,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/lists");
+    sqlx::query!("SELECT list_id FROM lists WHERE name = $1", name)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .list_id
+}
+
+async fn subscriber_id(app: &TestApp, email: &str) -> Uuid {
+    sqlx::query!("SELECT id FROM subscriptions WHERE email = $1", email)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id
+}
+
+/// Signs up for `list_id` and follows the confirmation link
+async fn subscribe_to_list(app: &TestApp, email: &str, list_id: Uuid) {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let body = format!("name=le%20guin&email={}&list_id={}", email, list_id);
+    app.post_subscriptions(body)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let requests = app.email_server.received_requests().await.unwrap();
+    let confirmation_links = app.get_confirmation_links(requests.last().unwrap());
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+async fn publish_to_list(app: &TestApp, list_id: Uuid) {
+    let response = app
+        .post_newsletter(&serde_json::json!({
+            "list_id": list_id,
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+}
+
+#[tokio::test]
+async fn admins_can_create_and_rename_lists() {
+    let app = spawn_app().await;
+    app.login_with_test_user().await;
+
+    let list_id = create_list(&app, "Releases", "releases@example.com").await;
+    let html = app.get_lists_html().await;
+    assert!(html.contains("The list has been created."));
+    assert!(html.contains(r#"value="Releases""#));
+    assert!(html.contains(&format!(
+        r#"value="{}""#,
+        htmlescape::encode_attribute("releases@example.com")
+    )));
+
+    // Names are unique
+    app.post_create_list(&serde_json::json!({ "name": "Releases" }))
+        .await;
+    assert!(app
+        .get_lists_html()
+        .await
+        .contains("There is already a list with this name."));
+
+    // Sender addresses must be valid
+    app.post_update_list(
+        list_id,
+        &serde_json::json!({ "name": "Changelog", "sender_email": "not an email" }),
+    )
+    .await;
+    assert!(app
+        .get_lists_html()
+        .await
+        .contains("is not a valid email address"));
+
+    let response = app
+        .post_update_list(list_id, &serde_json::json!({ "name": "Changelog" }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/lists");
+    let html = app.get_lists_html().await;
+    assert!(html.contains("The list has been updated."));
+    assert!(html.contains(r#"value="Changelog""#));
+}
+
+#[tokio::test]
+async fn the_subscribe_form_and_the_newsletter_form_offer_a_choice_of_lists() {
+    let app = spawn_app().await;
+    app.login_with_test_user().await;
+    let list_id = create_list(&app, "Release notes", "").await;
+
+    let home = reqwest::get(&app.address)
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(home.contains(r#"<select name="list_id">"#));
+    assert!(home.contains(&format!(
+        r#"<option value="{}">Release notes</option>"#,
+        list_id
+    )));
+
+    let newsletter_form = app.get_newsletter_form_html().await;
+    assert!(newsletter_form.contains(&format!(
+        r#"<option value="{}">Release notes</option>"#,
+        list_id
+    )));
+}
+
+#[tokio::test]
+async fn subscribing_to_an_unknown_list_is_rejected() {
+    let app = spawn_app().await;
+    let body = format!(
+        "name=le%20guin&email=ursula_le_guin%40gmail.com&list_id={}",
+        Uuid::new_v4()
+    );
+
+    let response = app.post_subscriptions(body).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("The selected list does not exist."));
+}
+
+#[tokio::test]
+async fn issues_only_go_to_the_subscribers_of_their_list_from_its_sender() {
+    let app = spawn_app().await;
+    app.login_with_test_user().await;
+    // On the default list
+    create_confirmed_subscriber(&app).await;
+    let list_id = create_list(&app, "Release notes", "releases@example.com").await;
+    subscribe_to_list(&app, "ursula_le_guin@gmail.com", list_id).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    publish_to_list(&app, list_id).await;
+    app.dispatch_pending_emails().await;
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], "ursula_le_guin@gmail.com");
+    assert_eq!(body["From"], "releases@example.com");
+}
+
+#[tokio::test]
+async fn confirmed_subscribers_confirm_each_list_they_join() {
+    let app = spawn_app().await;
+    app.login_with_test_user().await;
+    let list_id = create_list(&app, "Release notes", "").await;
+    subscribe_to_list(&app, "ursula_le_guin@gmail.com", list_id).await;
+    let default_list_id = sqlx::query!("SELECT list_id FROM lists ORDER BY created_at LIMIT 1")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .list_id;
+
+    // Joining the default list takes a confirmation of its own
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com")
+        .await
+        .error_for_status()
+        .unwrap();
+    let requests = app.email_server.received_requests().await.unwrap();
+    let confirmation_links = app.get_confirmation_links(requests.last().unwrap());
+    drop(_mock_guard);
+
+    // Meanwhile, they keep receiving the list they are confirmed on
+    let status =
+        sqlx::query!("SELECT status FROM subscriptions WHERE email = 'ursula_le_guin@gmail.com'")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap()
+            .status;
+    assert_eq!(status, "confirmed");
+    let not_confirmed_guard = Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .named("Not confirmed yet")
+        .mount_as_scoped(&app.email_server)
+        .await;
+    publish_to_list(&app, default_list_id).await;
+    app.dispatch_pending_emails().await;
+    drop(not_confirmed_guard);
+
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .named("Confirmed")
+        .mount(&app.email_server)
+        .await;
+    publish_to_list(&app, default_list_id).await;
+    app.dispatch_pending_emails().await;
+}
+
+#[tokio::test]
+async fn subscribers_choose_their_lists_from_the_preferences_page() {
+    let app = spawn_app().await;
+    app.login_with_test_user().await;
+    let list_id = create_list(&app, "Release notes", "").await;
+    subscribe_to_list(&app, "ursula_le_guin@gmail.com", list_id).await;
+    let preferences_link =
+        app.preferences_link(subscriber_id(&app, "ursula_le_guin@gmail.com").await);
+    let default_list_id = sqlx::query!("SELECT list_id FROM lists ORDER BY created_at LIMIT 1")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .list_id;
+
+    // Swap the release notes for the default list
+    let response = app
+        .post_preferences(
+            &preferences_link,
+            "lists",
+            &HashMap::from([(default_list_id.to_string(), "on")]),
+        )
+        .await;
+    assert_is_redirect_to(&response, preferences_link.path());
+    let html = app
+        .api_client
+        .get(preferences_link.clone())
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains("Your lists have been updated."));
+    assert!(html.contains(&format!(
+        r#"<input type="checkbox" name="{}" value="on" checked>"#,
+        default_list_id
+    )));
+    assert!(html.contains(&format!(
+        r#"<input type="checkbox" name="{}" value="on">"#,
+        list_id
+    )));
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    publish_to_list(&app, list_id).await;
+    publish_to_list(&app, default_list_id).await;
+    app.dispatch_pending_emails().await;
+}
@@ -1,8 +1,19 @@
 mod admin_dashboard;
+mod archive;
 mod change_password;
+mod email_events;
 mod health_check;
 mod helpers;
+mod issues;
+mod lists;
 mod login;
 mod newsletter;
+mod outbox;
+mod preferences;
+mod segments;
+mod subscribers;
 mod subscriptions;
 mod subscriptions_confirm;
+mod suppressions;
+mod tracking;
+mod unsubscribe;
@@ -1,14 +1,17 @@
 use std::time::Duration;
 
-use fake::faker::name::en::Name;
-use fake::{faker::internet::en::SafeEmail, Fake};
 use tokio::test;
 use wiremock::{
     matchers::{any, method, path},
     Mock, ResponseTemplate,
 };
 
-use crate::helpers::{assert_is_redirect_to, spawn_app, ConfirmationLinks, TestApp};
+use zero2prod::configuration::IssueDeliverySettings;
+use zero2prod::issue_delivery_worker::{try_execute_task, ExecutionOutcome};
+
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, create_unconfirmed_subscriber, spawn_app,
+};
 
 #[test]
 async fn you_must_be_logged_in_to_see_newsletter_form() {
@@ -131,6 +134,136 @@ async fn concurrent_form_submission_is_handled_gracefully() {
     app.dispatch_pending_emails().await;
 }
 
+#[test]
+async fn newsletters_are_personalised_for_each_subscriber() {
+    let app = spawn_app().await;
+    app.login_with_test_user().await;
+    create_confirmed_subscriber(&app).await;
+    let subscriber = sqlx::query!("SELECT name FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletter(&serde_json::json!({
+            "title": "News for {{ name }}",
+            "text_content": "Hi {{ name }}, read it online: {{ archive_url }}",
+            "html_content": "<p>Hi {{ name }}</p><a href=\"{{ unsubscribe_url }}\">Leave</a>",
+            "idempotency_key": uuid::Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_pending_emails().await;
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let issue_id = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id;
+    assert_eq!(body["Subject"], format!("News for {}", subscriber.name));
+    assert!(body["TextBody"].as_str().unwrap().starts_with(&format!(
+        "Hi {}, read it online: {}/issues/{}",
+        subscriber.name, app.base_url, issue_id
+    )));
+    let html_body = body["HtmlBody"].as_str().unwrap();
+    assert!(html_body.contains(&format!(
+        "<p>Hi {}</p>",
+        htmlescape::encode_minimal(&subscriber.name)
+    )));
+    assert!(!html_body.contains("{{"));
+    assert!(html_body.contains("/subscriptions/unsubscribe?token="));
+}
+
+#[test]
+async fn newsletters_with_unknown_template_variables_are_rejected() {
+    let app = spawn_app().await;
+    app.login_with_test_user().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Hi {{ nmae }}",
+            "html_content": "<p>Hi {{ name }}</p>",
+            "idempotency_key": uuid::Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    let html_page = app.get_newsletter_form_html().await;
+    assert!(html_page.contains("Unknown template variable"));
+    app.dispatch_pending_emails().await;
+}
+
+#[test]
+async fn newsletters_written_in_markdown_get_html_and_plain_text_bodies() {
+    let app = spawn_app().await;
+    app.login_with_test_user().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "markdown_content": "# News\n\nRead [the post](https://example.com/post).\n\n<script>alert(1)</script>",
+            "idempotency_key": uuid::Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_pending_emails().await;
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let html_body = body["HtmlBody"].as_str().unwrap();
+    assert!(html_body.contains("<h1>News</h1>"));
+    assert!(!html_body.contains("<script>"));
+    // The link goes through click tracking
+    let click_link = app.get_click_tracking_links(&email_request).pop().unwrap();
+    let response = app.api_client.get(click_link).send().await.unwrap();
+    assert_eq!(response.headers()["Location"], "https://example.com/post");
+    assert!(body["TextBody"]
+        .as_str()
+        .unwrap()
+        .starts_with("News\n====\n\nRead the post [1].\n\n[1] https://example.com/post"));
+
+    let stored = sqlx::query!("SELECT markdown_content FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(stored.markdown_content.unwrap().starts_with("# News"));
+}
+
 fn dummy_newsletter_body() -> serde_json::Value {
     serde_json::json!({
         "title": "Newsletter title",
@@ -140,43 +273,214 @@ fn dummy_newsletter_body() -> serde_json::Value {
     })
 }
 
-async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
-    let name: String = Name().fake();
-    let email: String = SafeEmail().fake();
-    let body = serde_urlencoded::to_string(serde_json::json!({
-            "name": name,
-            "email": email
-    }))
-    .unwrap();
+#[test]
+async fn failed_deliveries_are_retried() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.login_with_test_user().await;
 
-    let _mock_guard = Mock::given(path("/email"))
+    // The first attempt fails, the retry goes through
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/email"))
         .and(method("POST"))
         .respond_with(ResponseTemplate::new(200))
-        .named("Create unconfirmed subscriber")
-        .mount_as_scoped(&app.email_server)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_newsletter(&dummy_newsletter_body()).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    app.dispatch_pending_emails().await;
+
+    let dead_letters =
+        sqlx::query!("SELECT COUNT(*) as \"count!\" FROM issue_delivery_dead_letters")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(dead_letters.count, 0);
+}
+
+#[test]
+async fn failed_deliveries_are_delayed_with_backoff() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.login_with_test_user().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_newsletter(&dummy_newsletter_body()).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    let settings = IssueDeliverySettings {
+        base_backoff_ms: 60_000,
+        max_backoff_ms: 60_000,
+        ..app.issue_delivery.clone()
+    };
+    let outcome = try_execute_task(
+        &app.db_pool,
+        app.email_client.as_ref(),
+        &settings,
+        &app.base_url,
+        &app.token_signer,
+    )
+    .await
+    .unwrap();
+    assert!(matches!(outcome, ExecutionOutcome::TaskCompleted));
+
+    // The task is still queued, but not ready to be picked up again
+    let task = sqlx::query!(
+        "SELECT n_retries, execute_after > now() as \"delayed!\" FROM issue_delivery_queue"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(task.n_retries, 1);
+    assert!(task.delayed);
+
+    let outcome = try_execute_task(
+        &app.db_pool,
+        app.email_client.as_ref(),
+        &settings,
+        &app.base_url,
+        &app.token_signer,
+    )
+    .await
+    .unwrap();
+    assert!(matches!(outcome, ExecutionOutcome::EmptyQueue));
+}
+
+#[test]
+async fn deliveries_exhausting_the_retry_budget_are_dead_lettered() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.login_with_test_user().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(u64::from(app.issue_delivery.max_retries) + 1)
+        .mount(&app.email_server)
         .await;
 
-    app.post_subscriptions(body)
+    let response = app.post_newsletter(&dummy_newsletter_body()).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    app.dispatch_pending_emails().await;
+
+    let queued = sqlx::query!("SELECT COUNT(*) as \"count!\" FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
         .await
-        .error_for_status()
         .unwrap();
+    assert_eq!(queued.count, 0);
 
-    let email_request = app
-        .email_server
-        .received_requests()
+    let dead_letter = sqlx::query!("SELECT subscriber_email FROM issue_delivery_dead_letters")
+        .fetch_one(&app.db_pool)
         .await
-        .unwrap()
-        .pop()
         .unwrap();
 
-    app.get_confirmation_links(&email_request)
+    let html_page = app.get_failed_deliveries_html().await;
+    assert!(html_page.contains(&dead_letter.subscriber_email));
 }
 
-async fn create_confirmed_subscriber(app: &TestApp) {
-    let confirmation_links = create_unconfirmed_subscriber(app).await;
-    reqwest::get(confirmation_links.html)
-        .await
-        .unwrap()
-        .error_for_status()
-        .unwrap();
+#[test]
+async fn requeued_failed_deliveries_are_delivered() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.login_with_test_user().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_newsletter(&dummy_newsletter_body()).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_pending_emails().await;
+
+    // The provider is back online
+    app.email_server.reset().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_requeue_all_failed_deliveries().await;
+    assert_is_redirect_to(&response, "/admin/deliveries/failed");
+    let html_page = app.get_failed_deliveries_html().await;
+    assert!(html_page.contains("1 deliveries have been requeued."));
+
+    app.dispatch_pending_emails().await;
+
+    let dead_letters =
+        sqlx::query!("SELECT COUNT(*) as \"count!\" FROM issue_delivery_dead_letters")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(dead_letters.count, 0);
+}
+
+#[test]
+async fn delivery_attempts_are_logged() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.login_with_test_user().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(serde_json::json!({ "MessageID": "msg-1" })),
+        )
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_newsletter(&dummy_newsletter_body()).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_pending_emails().await;
+
+    let attempts = sqlx::query!(
+        r#"
+        SELECT outcome, provider_message_id, error, subscriber_id
+        FROM issue_deliveries
+        ORDER BY attempted_at
+        "#
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(attempts.len(), 2);
+    assert_eq!(attempts[0].outcome, "retrying");
+    assert!(attempts[0].error.is_some());
+    assert_eq!(attempts[1].outcome, "sent");
+    assert_eq!(attempts[1].provider_message_id.as_deref(), Some("msg-1"));
+    assert!(attempts[1].subscriber_id.is_some());
+}
+
+#[test]
+async fn you_must_be_logged_in_to_see_failed_deliveries() {
+    let app = spawn_app().await;
+    let response = app.get_failed_deliveries().await;
+    assert_is_redirect_to(&response, "/login");
 }
@@ -0,0 +1,63 @@
+use zero2prod::{
+    domain::SubscriberEmail,
+    email_client::{CaptureEmailClient, EmailHeader, EmailSender},
+};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_outbox() {
+    let app = spawn_app().await;
+    let response = app.get_outbox().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn captured_emails_are_listed_in_the_outbox() {
+    let app = spawn_app().await;
+    app.login_with_test_user().await;
+    let email_client = CaptureEmailClient::new(
+        app.db_pool.clone(),
+        SubscriberEmail::parse("sender@example.com").unwrap(),
+    );
+
+    email_client
+        .send_email(
+            &SubscriberEmail::parse("recipient@example.com").unwrap(),
+            "Captured subject",
+            "<p>Captured html</p>",
+            "Captured text",
+            &[EmailHeader::new("X-Custom", "custom value")],
+        )
+        .await
+        .unwrap();
+
+    let html_page = app.get_outbox_html().await;
+    assert!(html_page.contains("recipient@example.com"));
+    assert!(html_page.contains("Captured subject"));
+
+    let captured_email_id = sqlx::query!("SELECT captured_email_id FROM captured_emails")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .captured_email_id;
+    let html_page = app
+        .get_outbox_email_html(&captured_email_id.to_string())
+        .await;
+    assert!(html_page.contains("Captured text"));
+    assert!(html_page.contains(r#"srcdoc="&lt;p&gt;Captured"#));
+    assert!(html_page.contains("X-Custom"));
+    assert!(html_page.contains("custom value"));
+}
+
+#[tokio::test]
+async fn an_unknown_captured_email_returns_404() {
+    let app = spawn_app().await;
+    app.login_with_test_user().await;
+
+    let response = app
+        .get_outbox_email(&uuid::Uuid::new_v4().to_string())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 404);
+}
@@ -0,0 +1,271 @@
+use uuid::Uuid;
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{assert_is_redirect_to, create_confirmed_subscriber, spawn_app, TestApp};
+
+struct Subscriber {
+    id: Uuid,
+    email: String,
+    name: String,
+}
+
+async fn subscriber(app: &TestApp) -> Subscriber {
+    sqlx::query_as!(Subscriber, "SELECT id, email, name FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+}
+
+async fn get_html(app: &TestApp, url: &reqwest::Url) -> String {
+    app.api_client
+        .get(url.clone())
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn issue_emails_link_to_the_preferences_page() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let subscriber = subscriber(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.login_with_test_user().await;
+    app.post_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string()
+    }))
+    .await;
+    app.dispatch_pending_emails().await;
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let preferences_link = app.preferences_link(subscriber.id);
+    let mut sent_link = preferences_link.clone();
+    sent_link.set_port(None).unwrap();
+    assert!(body["HtmlBody"]
+        .as_str()
+        .unwrap()
+        .contains(sent_link.as_str()));
+    assert!(body["TextBody"]
+        .as_str()
+        .unwrap()
+        .contains(sent_link.as_str()));
+
+    let html_page = get_html(&app, &preferences_link).await;
+    assert!(html_page.contains(&format!(
+        "You receive our newsletter at <b>{}</b>.",
+        subscriber.email
+    )));
+    assert!(html_page.contains(&format!(
+        r#"value="{}""#,
+        htmlescape::encode_attribute(&subscriber.name)
+    )));
+}
+
+#[tokio::test]
+async fn preferences_links_must_be_signed() {
+    let app = spawn_app().await;
+
+    let response = app
+        .api_client
+        .get(format!("{}/preferences/{}", app.address, Uuid::new_v4()))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn subscribers_can_change_their_name() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let preferences_link = app.preferences_link(subscriber(&app).await.id);
+
+    let response = app
+        .post_preferences(
+            &preferences_link,
+            "name",
+            &serde_json::json!({ "name": "Ursula Le Guin" }),
+        )
+        .await;
+
+    assert_is_redirect_to(&response, preferences_link.path());
+    let html_page = get_html(&app, &preferences_link).await;
+    assert!(html_page.contains("<p><i>Your name has been updated.</i></p>"));
+    assert_eq!(subscriber(&app).await.name, "Ursula Le Guin");
+}
+
+#[tokio::test]
+async fn invalid_names_are_rejected() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let before = subscriber(&app).await;
+    let preferences_link = app.preferences_link(before.id);
+
+    let response = app
+        .post_preferences(
+            &preferences_link,
+            "name",
+            &serde_json::json!({ "name": "<script>" }),
+        )
+        .await;
+
+    assert_is_redirect_to(&response, preferences_link.path());
+    let html_page = get_html(&app, &preferences_link).await;
+    assert!(html_page.contains("&lt;script&gt;"));
+    assert!(!html_page.contains("<script>"));
+    assert_eq!(subscriber(&app).await.name, before.name);
+}
+
+#[tokio::test]
+async fn email_changes_take_effect_once_confirmed_from_the_new_address() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let before = subscriber(&app).await;
+    let preferences_link = app.preferences_link(before.id);
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_preferences(
+            &preferences_link,
+            "email",
+            &serde_json::json!({ "email": "ursula@example.com" }),
+        )
+        .await;
+    assert_is_redirect_to(&response, preferences_link.path());
+    assert_eq!(subscriber(&app).await.email, before.email);
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], "ursula@example.com");
+    let links = app.get_confirmation_links(&email_request);
+    let response = reqwest::get(links.html.clone()).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("The next issues will be sent to <b>ursula@example.com</b>."));
+    assert_eq!(subscriber(&app).await.email, "ursula@example.com");
+
+    // Following the link again changes nothing
+    let response = reqwest::get(links.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn addresses_of_other_subscribers_cannot_be_taken_over() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    let subscribers = sqlx::query!("SELECT id, email FROM subscriptions ORDER BY email")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    let preferences_link = app.preferences_link(subscribers[0].id);
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_preferences(
+        &preferences_link,
+        "email",
+        &serde_json::json!({ "email": subscribers[1].email }),
+    )
+    .await;
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let links = app.get_confirmation_links(&email_request);
+    let response = reqwest::get(links.html).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 409);
+    let emails: Vec<String> = sqlx::query!("SELECT email FROM subscriptions ORDER BY email")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|s| s.email)
+        .collect();
+    assert_eq!(
+        emails,
+        subscribers.into_iter().map(|s| s.email).collect::<Vec<_>>()
+    );
+}
+
+#[tokio::test]
+async fn superseded_email_change_links_cannot_revert_a_later_change() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let preferences_link = app.preferences_link(subscriber(&app).await.id);
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let mut links = vec![];
+    for email in ["ursula@example.com", "le_guin@example.com"] {
+        app.post_preferences(
+            &preferences_link,
+            "email",
+            &serde_json::json!({ "email": email }),
+        )
+        .await;
+        let email_request = app
+            .email_server
+            .received_requests()
+            .await
+            .unwrap()
+            .pop()
+            .unwrap();
+        let link = app.get_confirmation_links(&email_request).html;
+        let response = reqwest::get(link.clone()).await.unwrap();
+        assert_eq!(response.status().as_u16(), 200);
+        links.push(link);
+    }
+    assert_eq!(subscriber(&app).await.email, "le_guin@example.com");
+
+    let response = reqwest::get(links[0].clone()).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 410);
+    assert_eq!(subscriber(&app).await.email, "le_guin@example.com");
+}
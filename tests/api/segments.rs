@@ -0,0 +1,333 @@
+use uuid::Uuid;
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{assert_is_redirect_to, create_confirmed_subscriber, spawn_app, TestApp};
+
+/// Signs up through a form tagging its subscribers with `tags`, then confirms
+async fn subscribe_with_tags(app: &TestApp, email: &str, tags: &str) {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let body = serde_urlencoded::to_string([("name", "le guin"), ("email", email), ("tags", tags)])
+        .unwrap();
+    app.post_subscriptions(body)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let requests = app.email_server.received_requests().await.unwrap();
+    let confirmation_links = app.get_confirmation_links(requests.last().unwrap());
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+async fn subscriber_tags(app: &TestApp, email: &str) -> Vec<String> {
+    sqlx::query!(
+        r#"
+        SELECT t.tag
+        FROM subscriber_tags t
+        JOIN subscriptions s ON s.id = t.subscriber_id
+        WHERE s.email = $1
+        ORDER BY t.tag
+        "#,
+        email
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| r.tag)
+    .collect()
+}
+
+async fn create_segment(app: &TestApp, name: &str, tag_expression: &str) -> Uuid {
+    let response = app
+        .post_create_segment(&serde_json::json!({
+            "name": name,
+            "tag_expression": tag_expression,
+            "subscribed_from": "",
+            "subscribed_before": "",
+            "status": "",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/segments");
+    sqlx::query!("SELECT segment_id FROM segments WHERE name = $1", name)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .segment_id
+}
+
+#[tokio::test]
+async fn subscribe_forms_tag_their_subscribers() {
+    let app = spawn_app().await;
+
+    subscribe_with_tags(&app, "ursula_le_guin@gmail.com", "Webinar, early-adopter").await;
+    assert_eq!(
+        subscriber_tags(&app, "ursula_le_guin@gmail.com").await,
+        ["early-adopter", "webinar"]
+    );
+
+    // Anyone can sign up an address: confirmed subscribers keep the tags they have
+    let _mock_guard = Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com&tags=rust")
+        .await
+        .error_for_status()
+        .unwrap();
+    assert_eq!(
+        subscriber_tags(&app, "ursula_le_guin@gmail.com").await,
+        ["early-adopter", "webinar"]
+    );
+}
+
+#[tokio::test]
+async fn subscribe_forms_with_invalid_tags_are_rejected() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com&tags=c%2B%2B")
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("is not a valid tag"));
+}
+
+#[tokio::test]
+async fn admins_can_tag_and_untag_subscribers() {
+    let app = spawn_app().await;
+    app.login_with_test_user().await;
+    subscribe_with_tags(&app, "ursula_le_guin@gmail.com", "").await;
+
+    let response = app
+        .post_add_tags("ursula_le_guin@gmail.com", "rust go")
+        .await;
+    assert_is_redirect_to(&response, "/admin/tags");
+    let html = app.get_tags_html().await;
+    assert!(html.contains("The subscriber has been tagged."));
+    assert!(html.contains("<td>rust</td>"));
+
+    app.post_remove_tag("ursula_le_guin@gmail.com", "Rust")
+        .await;
+    assert!(app
+        .get_tags_html()
+        .await
+        .contains("The tag has been removed."));
+    assert_eq!(
+        subscriber_tags(&app, "ursula_le_guin@gmail.com").await,
+        ["go"]
+    );
+
+    app.post_add_tags("nobody@example.com", "rust").await;
+    assert!(app
+        .get_tags_html()
+        .await
+        .contains("There is no subscriber with this address."));
+}
+
+#[tokio::test]
+async fn admins_can_import_tags_from_a_csv_file() {
+    let app = spawn_app().await;
+    app.login_with_test_user().await;
+    subscribe_with_tags(&app, "ursula_le_guin@gmail.com", "webinar").await;
+
+    let csv = "email,tags\n\
+        ursula_le_guin@gmail.com,\"rust, go\"\n\
+        nobody@example.com,rust\n\
+        ursula_le_guin@gmail.com,c++";
+    let response = app.post_import_tags(csv).await;
+
+    assert_is_redirect_to(&response, "/admin/tags");
+    assert!(app.get_tags_html().await.contains(
+        "1 subscribers have been tagged, 1 rows didn't match any subscriber \
+        and 1 invalid rows were skipped."
+    ));
+    assert_eq!(
+        subscriber_tags(&app, "ursula_le_guin@gmail.com").await,
+        ["go", "rust", "webinar"]
+    );
+}
+
+#[tokio::test]
+async fn segments_with_invalid_filters_are_rejected() {
+    let app = spawn_app().await;
+    app.login_with_test_user().await;
+
+    for (body, error) in [
+        (
+            serde_json::json!({ "name": "Rustaceans", "tag_expression": "rust go" }),
+            "Tags must be combined with AND or OR in the tag expression.",
+        ),
+        (
+            serde_json::json!({ "name": "Rustaceans", "tag_expression": "(rust" }),
+            "Missing &quot;)&quot; in the tag expression.",
+        ),
+        (
+            serde_json::json!({ "name": "", "tag_expression": "rust" }),
+            "The segment needs a name.",
+        ),
+        (
+            serde_json::json!({
+                "name": "Rustaceans",
+                "subscribed_from": "2025-02-01",
+                "subscribed_before": "2025-01-01"
+            }),
+            "The subscription dates don&#x27;t leave any day to match.",
+        ),
+    ] {
+        let response = app.post_create_segment(&body).await;
+        assert_is_redirect_to(&response, "/admin/segments");
+        let html = app.get_segments_html().await;
+        assert!(html.contains(error), "Missing \"{}\" in:\n{}", error, html);
+    }
+}
+
+#[tokio::test]
+async fn segmented_issues_only_reach_the_matching_subscribers() {
+    let app = spawn_app().await;
+    app.login_with_test_user().await;
+    create_confirmed_subscriber(&app).await;
+    subscribe_with_tags(&app, "ursula_le_guin@gmail.com", "rust").await;
+    subscribe_with_tags(&app, "octavia_butler@gmail.com", "rust churned").await;
+    let segment_id = create_segment(&app, "Rustaceans", "rust AND NOT churned").await;
+    assert!(app.get_segments_html().await.contains("<td>1</td>"));
+
+    // The form shows the count and keeps what has been written so far
+    let issue = serde_json::json!({
+        "segment_id": segment_id,
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string()
+    });
+    let html = app.post_preview_recipients(&issue).await;
+    assert!(html.contains("This issue would reach 1 confirmed subscribers."));
+    assert!(html.contains("Newsletter body as plain text</textarea>"));
+    assert!(html.contains(&format!(
+        r#"<option value="{}" selected>Rustaceans</option>"#,
+        segment_id
+    )));
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let response = app.post_newsletter(&issue).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_pending_emails().await;
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], "ursula_le_guin@gmail.com");
+}
+
+#[tokio::test]
+async fn segments_of_drafts_cannot_be_deleted() {
+    let app = spawn_app().await;
+    app.login_with_test_user().await;
+    let segment_id = create_segment(&app, "Rustaceans", "rust").await;
+    app.post_save_draft(&serde_json::json!({
+        "segment_id": segment_id,
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+    }))
+    .await;
+
+    app.post_delete_segment(segment_id).await;
+    assert!(app
+        .get_segments_html()
+        .await
+        .contains("a draft or scheduled issue is restricted to it"));
+
+    sqlx::query!("DELETE FROM newsletter_issues")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    let response = app.post_delete_segment(segment_id).await;
+    assert_is_redirect_to(&response, "/admin/segments");
+    assert!(app
+        .get_segments_html()
+        .await
+        .contains("The segment has been deleted."));
+}
+
+#[tokio::test]
+async fn segments_can_select_bounced_and_complained_subscribers() {
+    let app = spawn_app().await;
+    app.login_with_test_user().await;
+
+    for status in ["bounced", "complained"] {
+        let response = app
+            .post_create_segment(&serde_json::json!({
+                "name": status,
+                "tag_expression": "",
+                "status": status,
+            }))
+            .await;
+        assert_is_redirect_to(&response, "/admin/segments");
+    }
+
+    let html = app.get_segments_html().await;
+    assert!(html.contains("The segment has been created."));
+    assert!(html.contains(r#"<option value="complained">complained</option>"#));
+    let n_segments = sqlx::query!("SELECT segment_id FROM segments")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .len();
+    assert_eq!(n_segments, 2);
+}
+
+#[tokio::test]
+async fn segments_of_sent_issues_can_be_deleted() {
+    let app = spawn_app().await;
+    app.login_with_test_user().await;
+    let segment_id = create_segment(&app, "Rustaceans", "rust").await;
+    let response = app
+        .post_newsletter(&serde_json::json!({
+            "segment_id": segment_id,
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    app.post_delete_segment(segment_id).await;
+
+    assert!(app
+        .get_segments_html()
+        .await
+        .contains("The segment has been deleted."));
+    let issue = sqlx::query!("SELECT status, segment_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issue.status, "sent");
+    assert_eq!(issue.segment_id, None);
+}
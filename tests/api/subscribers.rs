@@ -0,0 +1,597 @@
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{assert_is_redirect_to, create_confirmed_subscriber, spawn_app, TestApp};
+
+async fn subscriber_status(app: &TestApp, email: &str) -> Option<(String, String)> {
+    sqlx::query!(
+        r#"
+        SELECT s.status, ls.status as list_status
+        FROM subscriptions s
+        JOIN list_subscriptions ls ON ls.subscriber_id = s.id
+        WHERE s.email = $1
+        "#,
+        email
+    )
+    .fetch_optional(&app.db_pool)
+    .await
+    .unwrap()
+    .map(|r| (r.status, r.list_status))
+}
+
+#[tokio::test]
+async fn importing_as_confirmed_reports_the_skipped_rows() {
+    let app = spawn_app().await;
+    app.login_with_test_user().await;
+    create_confirmed_subscriber(&app).await;
+    let existing = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .email;
+    let _mock_guard = Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    let csv = format!(
+        "email,name,tags\n\
+        ursula_le_guin@gmail.com,Ursula Le Guin,\"rust, webinar\"\n\
+        {existing},Someone,\n\
+        not-an-email,Someone,\n\
+        octavia_butler@gmail.com,Octavia Butler,c++\n\
+        Ursula_Le_Guin@gmail.com,Ursula,\n\
+        n_k_jemisin@gmail.com,N. K. Jemisin,"
+    );
+    let response = app.post_import_subscribers(&csv, "confirmed").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html = response.text().await.unwrap();
+    assert!(html.contains(
+        "2 subscribers have been imported as confirmed, \
+        0 existing subscribers have been added to the list, 1 were already on it \
+        and 3 rows were skipped."
+    ));
+    assert!(html.contains("not a valid email"));
+    assert!(html.contains("is not a valid tag"));
+    assert!(html.contains("The address is already on line 2."));
+    for email in ["ursula_le_guin@gmail.com", "n_k_jemisin@gmail.com"] {
+        assert_eq!(
+            subscriber_status(&app, email).await,
+            Some(("confirmed".into(), "confirmed".into()))
+        );
+    }
+    assert_eq!(
+        subscriber_status(&app, "octavia_butler@gmail.com").await,
+        None
+    );
+    let tags = sqlx::query!(
+        r#"
+        SELECT t.tag
+        FROM subscriber_tags t
+        JOIN subscriptions s ON s.id = t.subscriber_id
+        WHERE s.email = 'ursula_le_guin@gmail.com'
+        ORDER BY t.tag
+        "#
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(
+        tags.into_iter().map(|r| r.tag).collect::<Vec<_>>(),
+        ["rust", "webinar"]
+    );
+}
+
+async fn create_list(app: &TestApp, name: &str) -> uuid::Uuid {
+    let response = app
+        .post_create_list(&serde_json::json!({
+            "name": name,
+            "description": "",
+            "sender_email": "",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/lists");
+    sqlx::query!("SELECT list_id FROM lists WHERE name = $1", name)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .list_id
+}
+
+#[tokio::test]
+async fn existing_subscribers_are_added_to_the_list_they_are_imported_into() {
+    let app = spawn_app().await;
+    app.login_with_test_user().await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    let emails: Vec<String> = sqlx::query!("SELECT email FROM subscriptions ORDER BY email")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| r.email)
+        .collect();
+    sqlx::query!(
+        "UPDATE subscriptions SET status = 'unsubscribed' WHERE email = $1",
+        emails[1]
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    let list_id = create_list(&app, "Releases").await;
+
+    let csv = format!(
+        "email,name,tags\n\
+        {},Someone,rust\n\
+        {},Someone else,",
+        emails[0].to_uppercase(),
+        emails[1]
+    );
+    let response = app
+        .post_import_subscribers_into_list(&csv, "confirmed", list_id)
+        .await;
+
+    let html = response.text().await.unwrap();
+    assert!(html.contains(
+        "0 subscribers have been imported as confirmed, \
+        1 existing subscribers have been added to the list, 0 were already on it \
+        and 1 rows were skipped."
+    ));
+    assert!(html.contains("The subscriber has unsubscribed."));
+    let memberships = sqlx::query!(
+        r#"
+        SELECT s.email, ls.status
+        FROM list_subscriptions ls
+        JOIN subscriptions s ON s.id = ls.subscriber_id
+        WHERE ls.list_id = $1
+        "#,
+        list_id
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(memberships.len(), 1);
+    assert_eq!(memberships[0].email, emails[0]);
+    assert_eq!(memberships[0].status, "confirmed");
+
+    // Importing the same file again changes nothing
+    let response = app
+        .post_import_subscribers_into_list(&csv, "confirmed", list_id)
+        .await;
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("0 existing subscribers have been added to the list, 1 were already on it"));
+}
+
+#[tokio::test]
+async fn existing_subscribers_are_asked_to_confirm_a_list_they_are_imported_into() {
+    let app = spawn_app().await;
+    app.login_with_test_user().await;
+    create_confirmed_subscriber(&app).await;
+    let email = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .email;
+    let list_id = create_list(&app, "Releases").await;
+
+    let csv = format!("email,name\n{email},Someone");
+    let response = app
+        .post_import_subscribers_into_list(&csv, "send_confirmation", list_id)
+        .await;
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("1 existing subscribers have been added to the list"));
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_confirmation_emails().await;
+}
+
+#[tokio::test]
+async fn confirmation_emails_of_imported_subscribers_are_sent_in_the_background() {
+    let app = spawn_app().await;
+    app.login_with_test_user().await;
+
+    let csv = "email,name\nursula_le_guin@gmail.com,Ursula Le Guin";
+    let not_sent_yet = Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .named("Not sent by the import")
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let response = app.post_import_subscribers(csv, "send_confirmation").await;
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("1 subscribers have been imported and will receive a confirmation email"));
+    drop(not_sent_yet);
+    assert_eq!(
+        subscriber_status(&app, "ursula_le_guin@gmail.com").await,
+        Some(("pending_confirmation".into(), "pending_confirmation".into()))
+    );
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_confirmation_emails().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    assert_eq!(
+        subscriber_status(&app, "ursula_le_guin@gmail.com").await,
+        Some(("confirmed".into(), "confirmed".into()))
+    );
+    // Sent once only
+    app.dispatch_confirmation_emails().await;
+}
+
+#[tokio::test]
+async fn imported_files_need_email_and_name_columns() {
+    let app = spawn_app().await;
+    app.login_with_test_user().await;
+
+    let response = app
+        .post_import_subscribers("email\nursula_le_guin@gmail.com", "confirmed")
+        .await;
+
+    assert_is_redirect_to(&response, "/admin/subscribers/import");
+    assert!(app
+        .get_import_subscribers_html()
+        .await
+        .contains("The CSV file must have a header with email and name columns."));
+}
+
+/// Publishes an issue tracking opens to a single confirmed subscriber, who opens it.
+/// Returns their address.
+async fn deliver_opened_issue(app: &TestApp) -> String {
+    create_confirmed_subscriber(app).await;
+    let email = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .email;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let response = app
+        .post_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "track_opens": true,
+            "idempotency_key": uuid::Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_pending_emails().await;
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let pixel_link = app.get_open_pixel_link(&email_request).unwrap();
+    reqwest::get(pixel_link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    email
+}
+
+#[tokio::test]
+async fn all_subscribers_can_be_exported_as_csv_or_json() {
+    let app = spawn_app().await;
+    app.login_with_test_user().await;
+    let csv = "email,name,tags\n\
+        ursula_le_guin@gmail.com,Ursula Le Guin,\"rust, webinar\"";
+    app.post_import_subscribers(csv, "confirmed").await;
+
+    let response = app.get_subscribers_export("csv").await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Content-Disposition"],
+        "attachment; filename=\"subscribers.csv\""
+    );
+    let body = response.text().await.unwrap();
+    let mut lines = body.lines();
+    assert_eq!(
+        lines.next(),
+        Some("email,name,status,subscribed_at,lists,tags")
+    );
+    assert!(lines
+        .next()
+        .unwrap()
+        .starts_with("ursula_le_guin@gmail.com,Ursula Le Guin,confirmed,"));
+
+    let response = app.get_subscribers_export("json").await;
+    assert_eq!(response.status().as_u16(), 200);
+    let subscribers: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(subscribers[0]["email"], "ursula_le_guin@gmail.com");
+    assert_eq!(subscribers[0]["lists"].as_array().unwrap().len(), 1);
+    assert_eq!(
+        subscribers[0]["tags"],
+        serde_json::json!(["rust", "webinar"])
+    );
+}
+
+#[tokio::test]
+async fn access_requests_bundle_everything_stored_about_an_address() {
+    let app = spawn_app().await;
+    app.login_with_test_user().await;
+    let email = deliver_opened_issue(&app).await;
+
+    let response = app.get_subscriber_data_export(&email).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let export: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(export["subscription"]["email"], email.as_str());
+    assert_eq!(export["subscription"]["status"], "confirmed");
+    assert_eq!(export["confirmation_tokens"].as_array().unwrap().len(), 1);
+    assert_eq!(export["deliveries"][0]["issue_title"], "Newsletter title");
+    assert_eq!(export["deliveries"][0]["outcome"], "sent");
+    assert_eq!(export["opens"].as_array().unwrap().len(), 1);
+
+    let response = app.get_subscriber_data_export("nobody@example.com").await;
+    assert_is_redirect_to(&response, "/admin/subscribers/data");
+    assert!(app
+        .get_subscriber_data_html()
+        .await
+        .contains("Nothing is stored about this address."));
+}
+
+#[tokio::test]
+async fn erasure_requests_remove_the_address_but_keep_the_statistics() {
+    let app = spawn_app().await;
+    app.login_with_test_user().await;
+    let email = deliver_opened_issue(&app).await;
+    app.post_add_suppression(
+        &serde_json::json!({ "email": email, "reason": "manual", "note": "" }),
+    )
+    .await;
+
+    // Whatever the case the address is typed in
+    let response = app.post_erase_subscriber(&email.to_uppercase()).await;
+    assert_is_redirect_to(&response, "/admin/subscribers/data");
+    assert!(app
+        .get_subscriber_data_html()
+        .await
+        .contains("The address has been erased."));
+
+    let remaining = sqlx::query!(
+        r#"
+        SELECT
+            (SELECT COUNT(*) FROM subscriptions) as "subscriptions!",
+            (SELECT COUNT(*) FROM subscription_tokens) as "tokens!",
+            (SELECT COUNT(*) FROM list_subscriptions) as "memberships!",
+            (SELECT COUNT(*) FROM suppressions) as "suppressions!",
+            (SELECT COUNT(*) FROM issue_deliveries WHERE subscriber_email = $1) as "deliveries!",
+            (SELECT COUNT(*) FROM issue_opens WHERE subscriber_id IS NOT NULL) as "opens!"
+        "#,
+        email
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(
+        (
+            remaining.subscriptions,
+            remaining.tokens,
+            remaining.memberships,
+            remaining.suppressions,
+            remaining.deliveries,
+            remaining.opens
+        ),
+        (0, 0, 0, 0, 0, 0)
+    );
+    let issue_id = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id;
+    let html_page = app.get_issue_stats_html(&issue_id.to_string()).await;
+    assert!(html_page.contains("<tr><th>Sent</th><td>1</td></tr>"));
+    assert!(html_page.contains("1 unique, 1 total"));
+
+    // Nothing left to export or erase
+    let response = app.get_subscriber_data_export(&email).await;
+    assert_is_redirect_to(&response, "/admin/subscribers/data");
+    app.post_erase_subscriber(&email).await;
+    assert!(app
+        .get_subscriber_data_html()
+        .await
+        .contains("Nothing is stored about this address."));
+}
+
+#[tokio::test]
+async fn erased_addresses_cannot_be_imported_again() {
+    let app = spawn_app().await;
+    app.login_with_test_user().await;
+    let email = deliver_opened_issue(&app).await;
+    app.post_erase_subscriber(&email).await;
+
+    let response = app
+        .post_import_subscribers(&format!("email,name\n{email},Someone"), "confirmed")
+        .await;
+
+    let html = response.text().await.unwrap();
+    assert!(html.contains("The address is on the suppression list."));
+    assert_eq!(subscriber_status(&app, &email).await, None);
+    // Only a hash of the address is kept
+    let erased = sqlx::query!("SELECT email_hash FROM erased_addresses")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(!erased.email_hash.contains(&email));
+    let n_suppressions = sqlx::query!(r#"SELECT COUNT(*) as "count!" FROM suppressions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_suppressions, 0);
+}
+
+async fn subscriber_id(app: &TestApp, email: &str) -> uuid::Uuid {
+    sqlx::query!("SELECT id FROM subscriptions WHERE email = $1", email)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_subscribers() {
+    let app = spawn_app().await;
+    let response = app.get_subscribers(&[]).await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn subscribers_can_be_searched_and_filtered_by_status() {
+    let app = spawn_app().await;
+    app.login_with_test_user().await;
+    let csv = "email,name\n\
+        ursula_le_guin@gmail.com,Ursula Le Guin\n\
+        octavia_butler@gmail.com,Octavia Butler";
+    app.post_import_subscribers(csv, "confirmed").await;
+    app.post_import_subscribers(
+        "email,name\nn_k_jemisin@gmail.com,N. K. Jemisin",
+        "send_confirmation",
+    )
+    .await;
+
+    let html = app.get_subscribers_html(&[("q", "GUIN")]).await;
+    assert!(html.contains("1 subscribers match."));
+    assert!(html.contains("ursula_le_guin@gmail.com"));
+    assert!(!html.contains("octavia_butler@gmail.com"));
+
+    let html = app
+        .get_subscribers_html(&[("q", ""), ("status", "pending_confirmation")])
+        .await;
+    assert!(html.contains("1 subscribers match."));
+    assert!(html.contains("n_k_jemisin@gmail.com"));
+    assert!(html.contains(r#"<option value="pending_confirmation" selected>"#));
+
+    // Wildcards are matched literally
+    let html = app.get_subscribers_html(&[("q", "%")]).await;
+    assert!(html.contains("0 subscribers match."));
+}
+
+#[tokio::test]
+async fn subscribers_are_listed_fifty_per_page() {
+    let app = spawn_app().await;
+    app.login_with_test_user().await;
+    let mut csv = "email,name\n".to_owned();
+    for i in 0..51 {
+        csv.push_str(&format!("subscriber{}@example.com,Subscriber {}\n", i, i));
+    }
+    app.post_import_subscribers(&csv, "confirmed").await;
+
+    let html = app.get_subscribers_html(&[("q", "subscriber")]).await;
+    assert!(html.contains("51 subscribers match."));
+    assert_eq!(
+        html.matches("<td><a href=\"/admin/subscribers/").count(),
+        50
+    );
+    assert!(html.contains(
+        r#"<a href="/admin/subscribers?q=subscriber&amp;status=&amp;page=2">Next page -&gt;</a>"#
+    ));
+
+    let html = app
+        .get_subscribers_html(&[("q", "subscriber"), ("page", "2")])
+        .await;
+    assert_eq!(html.matches("<td><a href=\"/admin/subscribers/").count(), 1);
+    assert!(html.contains("&lt;- Previous page"));
+    assert!(!html.contains("Next page"));
+}
+
+#[tokio::test]
+async fn subscribers_can_be_confirmed_unsubscribed_and_deleted_from_their_page() {
+    let app = spawn_app().await;
+    app.login_with_test_user().await;
+    app.post_import_subscribers(
+        "email,name,tags\nursula_le_guin@gmail.com,Ursula Le Guin,rust",
+        "send_confirmation",
+    )
+    .await;
+    let id = subscriber_id(&app, "ursula_le_guin@gmail.com").await;
+
+    let html = app.get_subscriber_html(id).await;
+    assert!(html.contains("<h1>ursula_le_guin@gmail.com</h1>"));
+    assert!(html.contains("<tr><th>Tags</th><td>rust</td></tr>"));
+    assert!(html.contains("<td>Subscribed</td>"));
+    assert!(html.contains(&format!("/admin/subscribers/{}/confirm", id)));
+
+    let response = app.post_subscriber_action(id, "confirm").await;
+    assert_is_redirect_to(&response, &format!("/admin/subscribers/{}", id));
+    assert!(app
+        .get_subscriber_html(id)
+        .await
+        .contains("The subscriber has been confirmed."));
+    assert_eq!(
+        subscriber_status(&app, "ursula_le_guin@gmail.com").await,
+        Some(("confirmed".into(), "confirmed".into()))
+    );
+
+    app.post_subscriber_action(id, "unsubscribe").await;
+    assert!(app
+        .get_subscriber_html(id)
+        .await
+        .contains("The subscriber has been unsubscribed."));
+    assert_eq!(
+        subscriber_status(&app, "ursula_le_guin@gmail.com").await,
+        Some(("unsubscribed".into(), "unsubscribed".into()))
+    );
+    // They have to opt in again themselves
+    app.post_subscriber_action(id, "confirm").await;
+    assert!(app
+        .get_subscriber_html(id)
+        .await
+        .contains("Only subscribers waiting for confirmation can be confirmed."));
+
+    let response = app.post_subscriber_action(id, "delete").await;
+    assert_is_redirect_to(&response, "/admin/subscribers");
+    assert!(app
+        .get_subscribers_html(&[])
+        .await
+        .contains("The subscriber has been deleted."));
+    assert_eq!(app.get_subscriber(id).await.status().as_u16(), 404);
+    assert_eq!(
+        subscriber_status(&app, "ursula_le_guin@gmail.com").await,
+        None
+    );
+    // Unlike an erasure, deleting doesn't keep them from being added back
+    let response = app
+        .post_import_subscribers("email,name\nursula_le_guin@gmail.com,Ursula", "confirmed")
+        .await;
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("1 subscribers have been imported as confirmed"));
+}
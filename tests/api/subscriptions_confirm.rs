@@ -1,9 +1,13 @@
+use std::time::Duration;
+
 use wiremock::{
     matchers::{method, path},
     Mock, ResponseTemplate,
 };
 
-use crate::helpers::spawn_app;
+use crate::helpers::{
+    create_confirmed_subscriber, create_unconfirmed_subscriber, spawn_app, TestApp,
+};
 
 #[tokio::test]
 async fn confirmation_without_token_is_rejected_with_400() {
@@ -65,3 +69,270 @@ async fn clicking_confirmation_link_confirms_a_subscriber() {
     assert_eq!(saved.name, "le guin");
     assert_eq!(saved.status, "confirmed");
 }
+
+/// Pretends the subscriber signed up, and got their confirmation link, `age` ago
+async fn age_subscription(app: &TestApp, age: Duration) {
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET subscribed_at = subscribed_at - make_interval(secs => $1),
+            pending_since = pending_since - make_interval(secs => $1)
+        "#,
+        age.as_secs_f64()
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        "UPDATE subscription_tokens SET created_at = created_at - make_interval(secs => $1)",
+        age.as_secs_f64()
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn confirmation_links_can_only_be_used_once() {
+    let app = spawn_app().await;
+    let links = create_unconfirmed_subscriber(&app).await;
+    reqwest::get(links.html.clone())
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    sqlx::query!("UPDATE subscriptions SET status = 'unsubscribed'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = reqwest::get(links.html).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 404);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "unsubscribed");
+}
+
+#[tokio::test]
+async fn confirming_shows_a_welcome_page() {
+    let app = spawn_app().await;
+    let links = create_unconfirmed_subscriber(&app).await;
+
+    let response = reqwest::get(links.html).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "text/html; charset=utf-8"
+    );
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("<h1>Welcome aboard!</h1>"));
+}
+
+#[tokio::test]
+async fn clicking_the_confirmation_link_again_says_you_are_already_subscribed() {
+    let app = spawn_app().await;
+    let links = create_unconfirmed_subscriber(&app).await;
+    reqwest::get(links.html.clone())
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let response = reqwest::get(links.html).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("<h1>You are already subscribed</h1>"));
+}
+
+#[tokio::test]
+async fn unknown_confirmation_tokens_offer_to_send_a_new_link() {
+    let app = spawn_app().await;
+
+    let response = reqwest::get(format!(
+        "{}/subscriptions/confirm?token=mistyped",
+        app.address
+    ))
+    .await
+    .unwrap();
+
+    assert_eq!(response.status().as_u16(), 404);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("<h1>This confirmation link is not valid</h1>"));
+    assert!(html_page.contains(r#"<form action="/subscriptions/confirm/resend" method="post">"#));
+}
+
+#[tokio::test]
+async fn expired_confirmation_links_offer_to_send_a_new_one() {
+    let app = spawn_app().await;
+    let links = create_unconfirmed_subscriber(&app).await;
+    age_subscription(&app, app.confirmation_token_ttl + Duration::from_secs(60)).await;
+
+    let response = reqwest::get(links.html).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 410);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("This confirmation link has expired"));
+    assert!(html_page.contains(r#"<form action="/subscriptions/confirm/resend" method="post">"#));
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn a_new_confirmation_link_can_be_requested() {
+    let app = spawn_app().await;
+    let expired_links = create_unconfirmed_subscriber(&app).await;
+    age_subscription(&app, app.confirmation_token_ttl + Duration::from_secs(60)).await;
+    let email = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .email;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let response = app.post_resend_confirmation(&email).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let links = app.get_confirmation_links(&email_request);
+    let response = reqwest::get(expired_links.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 404);
+    let response = reqwest::get(links.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn requesting_a_new_link_for_an_unknown_address_sends_nothing() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_resend_confirmation("ursula@example.com").await;
+
+    // Same answer as for a pending subscriber
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn the_cleanup_purges_expired_tokens_and_stale_unconfirmed_subscribers() {
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    age_subscription(
+        &app,
+        app.unconfirmed_subscriber_retention + Duration::from_secs(60),
+    )
+    .await;
+    // Signed up recently, but their link has expired already
+    create_unconfirmed_subscriber(&app).await;
+    sqlx::query!(
+        r#"
+        UPDATE subscription_tokens SET created_at = created_at - make_interval(secs => $1)
+        WHERE created_at > now() - interval '1 minute'
+        "#,
+        (app.confirmation_token_ttl + Duration::from_secs(60)).as_secs_f64()
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    // Signed up just now
+    create_unconfirmed_subscriber(&app).await;
+
+    app.purge_unconfirmed_subscriptions().await;
+
+    let statuses: Vec<String> =
+        sqlx::query!("SELECT status FROM subscriptions ORDER BY subscribed_at")
+            .fetch_all(&app.db_pool)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|s| s.status)
+            .collect();
+    assert_eq!(
+        statuses,
+        vec!["confirmed", "pending_confirmation", "pending_confirmation"]
+    );
+    let n_tokens = sqlx::query!("SELECT subscription_token FROM subscription_tokens")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .len();
+    assert_eq!(n_tokens, 1);
+}
+
+#[tokio::test]
+async fn the_cleanup_spares_former_subscribers_who_signed_up_again() {
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions(body)
+        .await
+        .error_for_status()
+        .unwrap();
+    sqlx::query!(
+        "UPDATE subscriptions SET status = 'unsubscribed' WHERE email = 'ursula_le_guin@gmail.com'"
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    // They left long ago
+    age_subscription(
+        &app,
+        app.unconfirmed_subscriber_retention + Duration::from_secs(60),
+    )
+    .await;
+
+    app.post_subscriptions(body)
+        .await
+        .error_for_status()
+        .unwrap();
+    app.purge_unconfirmed_subscriptions().await;
+
+    let subscriber =
+        sqlx::query!("SELECT status FROM subscriptions WHERE email = 'ursula_le_guin@gmail.com'")
+            .fetch_optional(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(subscriber.unwrap().status, "pending_confirmation");
+    // Their new link still works
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let confirmation_links = app.get_confirmation_links(&email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
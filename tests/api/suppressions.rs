@@ -0,0 +1,258 @@
+use wiremock::{matchers::any, Mock, ResponseTemplate};
+
+use crate::helpers::{assert_is_redirect_to, create_confirmed_subscriber, spawn_app, TestApp};
+
+async fn suppressed_addresses(app: &TestApp) -> Vec<(String, String)> {
+    sqlx::query!("SELECT email, reason FROM suppressions ORDER BY email")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|s| (s.email, s.reason))
+        .collect()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_the_suppression_list() {
+    let app = spawn_app().await;
+
+    let response = app.get_suppressions().await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app
+        .post_add_suppression(&serde_json::json!({
+            "email": "ursula@example.com",
+            "reason": "manual",
+            "note": ""
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app.get_suppressions_export().await;
+    assert_is_redirect_to(&response, "/login");
+    assert!(suppressed_addresses(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn addresses_can_be_added_to_and_removed_from_the_suppression_list() {
+    let app = spawn_app().await;
+    app.login_with_test_user().await;
+
+    let response = app
+        .post_add_suppression(&serde_json::json!({
+            "email": "Ursula@Example.com",
+            "reason": "legal",
+            "note": "Erasure request"
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/suppressions");
+    let html_page = app.get_suppressions_html().await;
+    assert!(html_page.contains("<p><i>The address has been added to the suppression list.</i></p>"));
+    assert!(html_page.contains("<td>ursula@example.com</td>"));
+    assert!(html_page.contains("<td>Erasure request</td>"));
+    assert_eq!(
+        suppressed_addresses(&app).await,
+        vec![("ursula@example.com".into(), "legal".into())]
+    );
+
+    let response = app.post_remove_suppression("ursula@example.com").await;
+    assert_is_redirect_to(&response, "/admin/suppressions");
+    let html_page = app.get_suppressions_html().await;
+    assert!(
+        html_page.contains("<p><i>The address has been removed from the suppression list.</i></p>")
+    );
+    assert!(!html_page.contains("<td>ursula@example.com</td>"));
+    assert!(suppressed_addresses(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn invalid_addresses_are_not_added_to_the_suppression_list() {
+    let app = spawn_app().await;
+    app.login_with_test_user().await;
+
+    let response = app
+        .post_add_suppression(&serde_json::json!({
+            "email": "not-an-email",
+            "reason": "manual",
+            "note": ""
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/suppressions");
+    let html_page = app.get_suppressions_html().await;
+    assert!(html_page.contains("<p><i>The address is not a valid email.</i></p>"));
+    assert!(suppressed_addresses(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn subscriptions_from_suppressed_addresses_are_silently_ignored() {
+    let app = spawn_app().await;
+    app.login_with_test_user().await;
+    app.post_add_suppression(&serde_json::json!({
+        "email": "ursula_le_guin@gmail.com",
+        "reason": "complaint",
+        "note": ""
+    }))
+    .await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let response = app
+        .post_subscriptions("name=le%20guin&email=Ursula_Le_Guin%40gmail.com")
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let subscriptions = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(subscriptions.is_empty());
+}
+
+#[tokio::test]
+async fn issues_are_not_delivered_to_suppressed_addresses() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let email = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .email;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    app.login_with_test_user().await;
+    let response = app
+        .post_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": uuid::Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    // Suppressed after the issue was queued
+    app.post_add_suppression(&serde_json::json!({
+        "email": email,
+        "reason": "manual",
+        "note": ""
+    }))
+    .await;
+    app.dispatch_pending_emails().await;
+
+    let delivery = sqlx::query!("SELECT outcome, error FROM issue_deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(delivery.outcome, "skipped");
+    assert_eq!(
+        delivery.error.as_deref(),
+        Some("The address is on the suppression list")
+    );
+}
+
+#[tokio::test]
+async fn hard_bounces_and_complaints_suppress_the_address() {
+    let app = spawn_app().await;
+    for (payload, email) in [
+        (
+            include_str!("fixtures/postmark/hard_bounce.json"),
+            "bounced@example.com",
+        ),
+        (
+            include_str!("fixtures/postmark/spam_complaint.json"),
+            "complained@example.com",
+        ),
+        (
+            include_str!("fixtures/postmark/soft_bounce.json"),
+            "full_mailbox@example.com",
+        ),
+    ] {
+        let mut event: serde_json::Value = serde_json::from_str(payload).unwrap();
+        event["Email"] = email.into();
+        let response = app.post_email_event(&event).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    assert_eq!(
+        suppressed_addresses(&app).await,
+        vec![
+            ("bounced@example.com".into(), "bounce".into()),
+            ("complained@example.com".into(), "complaint".into()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn suppression_lists_can_be_imported_and_exported() {
+    let app = spawn_app().await;
+    app.login_with_test_user().await;
+    app.post_add_suppression(&serde_json::json!({
+        "email": "ursula@example.com",
+        "reason": "manual",
+        "note": ""
+    }))
+    .await;
+
+    let response = app
+        .post_import_suppressions(
+            "email,reason,note\n\
+            ursula@example.com,bounce,\n\
+            octavia@example.com,legal,\"Erasure request, by post\"\n\
+            ted@example.com,,\n\
+            not-an-email,manual,\n\
+            nk@example.com,unknown,\n",
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/suppressions");
+    let html_page = app.get_suppressions_html().await;
+    assert!(html_page.contains(
+        "<p><i>2 addresses have been added to the suppression list, \
+        1 were already on it and 2 invalid rows were skipped.</i></p>"
+    ));
+    assert_eq!(
+        suppressed_addresses(&app).await,
+        vec![
+            ("octavia@example.com".into(), "legal".into()),
+            ("ted@example.com".into(), "manual".into()),
+            ("ursula@example.com".into(), "manual".into()),
+        ]
+    );
+
+    let response = app.get_suppressions_export().await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "text/csv; charset=utf-8"
+    );
+    assert_eq!(
+        response.headers()["Content-Disposition"],
+        r#"attachment; filename="suppressions.csv""#
+    );
+    let csv = response.text().await.unwrap();
+    let mut lines = csv.lines();
+    assert_eq!(lines.next(), Some("email,reason,note,created_at"));
+    assert!(csv.contains("octavia@example.com,legal,\"Erasure request, by post\","));
+    assert_eq!(lines.count(), 3);
+}
+
+#[tokio::test]
+async fn imports_without_an_email_column_are_rejected() {
+    let app = spawn_app().await;
+    app.login_with_test_user().await;
+
+    let response = app
+        .post_import_suppressions("address,reason\nursula@example.com,manual\n")
+        .await;
+    assert_is_redirect_to(&response, "/admin/suppressions");
+    let html_page = app.get_suppressions_html().await;
+    assert!(
+        html_page.contains("<p><i>The CSV file must have a header with an email column.</i></p>")
+    );
+    assert!(suppressed_addresses(&app).await.is_empty());
+}
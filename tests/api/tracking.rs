@@ -0,0 +1,168 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use wiremock::{matchers::any, Mock, ResponseTemplate};
+
+use crate::helpers::{assert_is_redirect_to, create_confirmed_subscriber, spawn_app, TestApp};
+
+/// Publishes an issue to a single confirmed subscriber and returns the email they got
+async fn deliver_issue(app: &TestApp, track_opens: bool) -> wiremock::Request {
+    create_confirmed_subscriber(app).await;
+    app.login_with_test_user().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": r#"<p>Newsletter body as HTML, <a href="https://example.com/post">read more</a>
+                or <a href="mailto:editor@example.com">write to us</a></p>"#,
+            "track_opens": track_opens,
+            "idempotency_key": uuid::Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_pending_emails().await;
+
+    app.email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap()
+}
+
+async fn issue_id(app: &TestApp) -> String {
+    sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id
+        .to_string()
+}
+
+#[tokio::test]
+async fn issues_carry_no_tracking_pixel_unless_asked_to() {
+    let app = spawn_app().await;
+    let email_request = deliver_issue(&app, false).await;
+
+    assert!(app.get_open_pixel_link(&email_request).is_none());
+    let html_page = app.get_issue_stats_html(&issue_id(&app).await).await;
+    assert!(html_page.contains("<th>Opens</th><td>Not tracked</td>"));
+}
+
+#[tokio::test]
+async fn opens_are_recorded_against_the_delivery() {
+    let app = spawn_app().await;
+    let email_request = deliver_issue(&app, true).await;
+    let pixel_link = app.get_open_pixel_link(&email_request).unwrap();
+
+    // Opened twice by the same subscriber
+    for _ in 0..2 {
+        let response = reqwest::get(pixel_link.clone()).await.unwrap();
+        assert_eq!(response.status().as_u16(), 200);
+        assert_eq!(response.headers()["Content-Type"], "image/gif");
+        assert_eq!(response.headers()["Cache-Control"], "no-store, private");
+    }
+
+    let open = sqlx::query!(
+        r#"
+        SELECT o.subscriber_id, d.outcome
+        FROM issue_opens o JOIN issue_deliveries d USING (issue_delivery_id)
+        LIMIT 1
+        "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert!(open.subscriber_id.is_some());
+    assert_eq!(open.outcome, "sent");
+
+    let html_page = app.get_issue_stats_html(&issue_id(&app).await).await;
+    assert!(html_page.contains("<th>Opens</th><td>1 unique, 2 total</td>"));
+}
+
+#[tokio::test]
+async fn forged_open_tokens_are_rejected() {
+    let app = spawn_app().await;
+    let email_request = deliver_issue(&app, true).await;
+    let pixel_link = app.get_open_pixel_link(&email_request).unwrap();
+
+    // Same signature, another delivery
+    let token = pixel_link
+        .path()
+        .trim_start_matches("/t/o/")
+        .trim_end_matches(".gif");
+    let (_, signature) = token.split_once('.').unwrap();
+    let mut forged_link = pixel_link.clone();
+    forged_link.set_path(&format!("/t/o/bm90LWEtZGVsaXZlcnk.{}.gif", signature));
+
+    for link in [forged_link, pixel_link.join("/t/o/garbage.gif").unwrap()] {
+        let response = reqwest::get(link).await.unwrap();
+        assert_eq!(response.status().as_u16(), 400);
+    }
+    let opens = sqlx::query!("SELECT COUNT(*) as \"count!\" FROM issue_opens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(opens.count, 0);
+}
+
+#[tokio::test]
+async fn clicks_are_recorded_and_redirected_to_the_original_link() {
+    let app = spawn_app().await;
+    let email_request = deliver_issue(&app, false).await;
+
+    // Only web links are tracked, and never the way out
+    let click_links = app.get_click_tracking_links(&email_request);
+    assert_eq!(click_links.len(), 1);
+    app.get_unsubscribe_link(&email_request);
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert!(body["HtmlBody"]
+        .as_str()
+        .unwrap()
+        .contains(r#"href="mailto:editor@example.com""#));
+
+    for _ in 0..2 {
+        let response = app
+            .api_client
+            .get(click_links[0].clone())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 302);
+        assert_eq!(response.headers()["Location"], "https://example.com/post");
+    }
+
+    let html_page = app.get_issue_stats_html(&issue_id(&app).await).await;
+    assert!(html_page.contains("<tr><td>https://example.com/post</td><td>1</td><td>2</td></tr>"));
+}
+
+#[tokio::test]
+async fn click_links_cannot_redirect_elsewhere() {
+    let app = spawn_app().await;
+    let email_request = deliver_issue(&app, false).await;
+    let click_link = app.get_click_tracking_links(&email_request).pop().unwrap();
+
+    // Same signature, another destination
+    let token = click_link.path().trim_start_matches("/t/c/");
+    let (payload, signature) = token.split_once('.').unwrap();
+    let payload = String::from_utf8(URL_SAFE_NO_PAD.decode(payload).unwrap()).unwrap();
+    let forged_payload = payload.replace("https://example.com/post", "https://evil.example.com");
+    let mut forged_link = click_link.clone();
+    forged_link.set_path(&format!(
+        "/t/c/{}.{}",
+        URL_SAFE_NO_PAD.encode(forged_payload),
+        signature
+    ));
+
+    let response = app.api_client.get(forged_link).send().await.unwrap();
+    assert_eq!(response.status().as_u16(), 400);
+    assert!(response.headers().get("Location").is_none());
+    let clicks = sqlx::query!("SELECT COUNT(*) as \"count!\" FROM issue_clicks")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(clicks.count, 0);
+}
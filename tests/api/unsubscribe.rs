@@ -0,0 +1,280 @@
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{create_confirmed_subscriber, spawn_app, TestApp};
+
+/// Sends an issue to a single confirmed subscriber and returns the email request
+async fn deliver_an_issue(app: &TestApp) -> wiremock::Request {
+    create_confirmed_subscriber(app).await;
+    app.login_with_test_user().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .named("Deliver issue")
+        .mount(&app.email_server)
+        .await;
+
+    app.post_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    }))
+    .await;
+    app.dispatch_pending_emails().await;
+
+    app.email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap()
+}
+
+/// Sends an issue to a single confirmed subscriber and returns their unsubscribe link
+async fn unsubscribe_link_from_an_issue(app: &TestApp) -> reqwest::Url {
+    let email_request = deliver_an_issue(app).await;
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert!(body["TextBody"]
+        .as_str()
+        .unwrap()
+        .contains("/subscriptions/unsubscribe?token="));
+
+    app.get_unsubscribe_link(&email_request)
+}
+
+/// Returns the `List-Unsubscribe` and `List-Unsubscribe-Post` header values of an email request
+fn list_unsubscribe_headers(email_request: &wiremock::Request) -> (String, String) {
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let header = |name: &str| {
+        body["Headers"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|h| h["Name"] == name)
+            .unwrap()["Value"]
+            .as_str()
+            .unwrap()
+            .to_owned()
+    };
+    (header("List-Unsubscribe"), header("List-Unsubscribe-Post"))
+}
+
+fn token_of(link: &reqwest::Url) -> String {
+    link.query_pairs()
+        .find(|(k, _)| k == "token")
+        .unwrap()
+        .1
+        .to_string()
+}
+
+async fn subscriber_status(app: &TestApp) -> String {
+    sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.")
+        .status
+}
+
+#[tokio::test]
+async fn unsubscribe_link_shows_a_confirmation_page() {
+    let app = spawn_app().await;
+    let link = unsubscribe_link_from_an_issue(&app).await;
+
+    let response = reqwest::get(link).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains(r#"action="/subscriptions/unsubscribe""#));
+    // Following the link alone must not unsubscribe
+    assert_eq!(subscriber_status(&app).await, "confirmed");
+}
+
+#[tokio::test]
+async fn confirming_the_unsubscription_unsubscribes_the_subscriber() {
+    let app = spawn_app().await;
+    let link = unsubscribe_link_from_an_issue(&app).await;
+    let token = token_of(&link);
+
+    let response = app.post_unsubscribe(&token).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("You have been unsubscribed"));
+    assert_eq!(subscriber_status(&app).await, "unsubscribed");
+}
+
+#[tokio::test]
+async fn unsubscribed_subscribers_do_not_receive_newsletters() {
+    let app = spawn_app().await;
+    let link = unsubscribe_link_from_an_issue(&app).await;
+    let token = token_of(&link);
+    app.post_unsubscribe(&token)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    app.email_server.reset().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    }))
+    .await;
+    app.dispatch_pending_emails().await;
+}
+
+#[tokio::test]
+async fn a_tampered_unsubscribe_token_is_rejected_with_400() {
+    let app = spawn_app().await;
+    let mut link = unsubscribe_link_from_an_issue(&app).await;
+    link.set_query(Some("token=bm90LWEtdXVpZA.c2lnbmF0dXJl"));
+
+    let response = reqwest::get(link).await.unwrap();
+    assert_eq!(response.status().as_u16(), 400);
+
+    let response = app.post_unsubscribe("bm90LWEtdXVpZA.c2lnbmF0dXJl").await;
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(subscriber_status(&app).await, "confirmed");
+}
+
+#[tokio::test]
+async fn unsubscribe_without_token_is_rejected_with_400() {
+    let app = spawn_app().await;
+    let response = reqwest::get(&format!("{}/subscriptions/unsubscribe", app.address))
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn newsletter_emails_carry_one_click_unsubscribe_headers() {
+    let app = spawn_app().await;
+    let email_request = deliver_an_issue(&app).await;
+
+    let (list_unsubscribe, list_unsubscribe_post) = list_unsubscribe_headers(&email_request);
+
+    assert!(list_unsubscribe.starts_with("<mailto:unsubscribe@localhost?subject=unsubscribe>, <"));
+    assert!(list_unsubscribe.contains("/subscriptions/unsubscribe/one-click?token="));
+    assert_eq!(list_unsubscribe_post, "List-Unsubscribe=One-Click");
+}
+
+#[tokio::test]
+async fn one_click_unsubscribe_unsubscribes_without_confirmation() {
+    let app = spawn_app().await;
+    let email_request = deliver_an_issue(&app).await;
+    let (list_unsubscribe, _) = list_unsubscribe_headers(&email_request);
+    let raw_link = list_unsubscribe
+        .split(", ")
+        .find(|l| l.starts_with("<http"))
+        .unwrap()
+        .trim_matches(|c| c == '<' || c == '>');
+    let mut link = reqwest::Url::parse(raw_link).unwrap();
+    link.set_port(Some(app.port)).unwrap();
+
+    // A request without the RFC 8058 body is not a one-click unsubscription
+    let response = reqwest::Client::new()
+        .post(link.clone())
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(subscriber_status(&app).await, "confirmed");
+    let response = reqwest::Client::new()
+        .post(link.clone())
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body("List-Unsubscribe=Yes")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(subscriber_status(&app).await, "confirmed");
+
+    let response = reqwest::Client::new()
+        .post(link)
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body("List-Unsubscribe=One-Click")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&app).await, "unsubscribed");
+}
+
+/// A recorded Postmark inbound email, sent by `email` with the given subject
+fn inbound_email(email: &str, subject: &str) -> serde_json::Value {
+    let mut inbound: serde_json::Value =
+        serde_json::from_str(include_str!("fixtures/postmark/inbound.json")).unwrap();
+    inbound["FromFull"]["Email"] = email.into();
+    inbound["Subject"] = subject.into();
+    inbound
+}
+
+#[tokio::test]
+async fn unsubscribe_emails_unsubscribe_their_sender() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let email = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .email;
+
+    // Anything else sent to the address is ignored
+    let response = app
+        .post_inbound_email(&inbound_email(&email, "Hello"))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&app).await, "confirmed");
+
+    let response = app
+        .post_inbound_email(&inbound_email(&email.to_uppercase(), "Re: unsubscribe"))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&app).await, "unsubscribed");
+
+    // Unknown senders are acknowledged, so that they are not sent again
+    let response = app
+        .post_inbound_email(&inbound_email("nobody@example.com", "unsubscribe"))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn inbound_emails_must_be_authenticated() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let email = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .email;
+
+    let response = app
+        .api_client
+        .post(format!("{}/webhooks/inbound-email", app.address))
+        .basic_auth(&app.email_webhook.username, Some("wrong password"))
+        .json(&inbound_email(&email, "unsubscribe"))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(subscriber_status(&app).await, "confirmed");
+}